@@ -1,44 +1,95 @@
+/// 元になったエラー
+pub type ErrorSource = Box<dyn std::error::Error + Send + Sync>;
+
 #[derive(Debug)]
-pub(crate) enum ResizedPngError {
-    Unsupported,
+pub enum ResizedPngError {
+    Unsupported(Option<ErrorSource>),
     NotFound,
-    IoError,
-    DecodingError,
-    EncodingError,
-    ParameterError,
-    LimitsError,
-    InputSizeError,
+    IoError(Option<ErrorSource>),
+    DecodingError(Option<ErrorSource>),
+    EncodingError(Option<ErrorSource>),
+    ParameterError(Option<ErrorSource>),
+    LimitsError(Option<ErrorSource>),
+    /// 幅か高さが0の画像。元の幅と高さを持つ
+    InputSizeError {
+        width: u32,
+        height: u32,
+    },
 }
 
 impl ResizedPngError {
     pub(crate) fn to_code(&self) -> u32 {
         match self {
-            Self::Unsupported => 1,
+            Self::Unsupported(_) => 1,
             Self::NotFound => 2,
-            Self::IoError => 3,
-            Self::DecodingError => 4,
-            Self::EncodingError => 5,
-            Self::ParameterError => 6,
-            Self::LimitsError => 7,
-            Self::InputSizeError => 8,
+            Self::IoError(_) => 3,
+            Self::DecodingError(_) => 4,
+            Self::EncodingError(_) => 5,
+            Self::ParameterError(_) => 6,
+            Self::LimitsError(_) => 7,
+            Self::InputSizeError { .. } => 8,
+        }
+    }
+
+    fn source_ref(&self) -> Option<&ErrorSource> {
+        match self {
+            Self::Unsupported(source)
+            | Self::IoError(source)
+            | Self::DecodingError(source)
+            | Self::EncodingError(source)
+            | Self::ParameterError(source)
+            | Self::LimitsError(source) => source.as_ref(),
+            Self::NotFound | Self::InputSizeError { .. } => None,
         }
     }
 }
 
+impl std::fmt::Display for ResizedPngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Self::Unsupported(_) => "対応していない形式です",
+            Self::NotFound => "ファイルが見つかりません",
+            Self::IoError(_) => "入出力に問題がありました",
+            Self::DecodingError(_) => "画像のデコードに問題がありました",
+            Self::EncodingError(_) => "画像のエンコードに問題がありました",
+            Self::ParameterError(_) => "画像のパラメータに問題がありました",
+            Self::LimitsError(_) => "画像の大きさが限界値を越えています",
+            Self::InputSizeError { width, height } => {
+                return write!(f, "画像サイズが小さすぎます: {}x{}", width, height);
+            }
+        };
+
+        match self.source_ref() {
+            Some(source) => write!(f, "{}: {}", message, source),
+            None => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ResizedPngError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source_ref()
+            .map(|v| v.as_ref() as &(dyn std::error::Error + 'static))
+    }
+}
+
 impl From<std::io::Error> for ResizedPngError {
     fn from(e: std::io::Error) -> Self {
         match e.kind() {
             std::io::ErrorKind::NotFound => Self::NotFound,
-            _ => Self::IoError,
+            _ => Self::IoError(Some(e.into())),
         }
     }
 }
 
 impl From<tinybmp::ParseError> for ResizedPngError {
     fn from(e: tinybmp::ParseError) -> Self {
+        // tinybmpのエラーはstd::error::Errorを実装していないので、内容を文字列で持つ。
+        let source = Some(format!("{:?}", e).into());
+
         match e {
-            tinybmp::ParseError::InvalidImageDimensions => Self::ParameterError,
-            _ => Self::Unsupported,
+            tinybmp::ParseError::InvalidImageDimensions => Self::ParameterError(source),
+            _ => Self::Unsupported(source),
         }
     }
 }
@@ -46,7 +97,7 @@ impl From<tinybmp::ParseError> for ResizedPngError {
 impl From<gif::DecodingError> for ResizedPngError {
     fn from(e: gif::DecodingError) -> Self {
         match e {
-            gif::DecodingError::Format(_) => Self::DecodingError,
+            gif::DecodingError::Format(_) => Self::DecodingError(Some(e.into())),
             gif::DecodingError::Io(e) => e.into(),
         }
     }
@@ -55,10 +106,10 @@ impl From<gif::DecodingError> for ResizedPngError {
 impl From<jpeg_decoder::Error> for ResizedPngError {
     fn from(e: jpeg_decoder::Error) -> Self {
         match e {
-            jpeg_decoder::Error::Format(_) => Self::DecodingError,
-            jpeg_decoder::Error::Unsupported(_) => Self::Unsupported,
+            jpeg_decoder::Error::Format(_) => Self::DecodingError(Some(e.into())),
+            jpeg_decoder::Error::Unsupported(_) => Self::Unsupported(Some(e.into())),
             jpeg_decoder::Error::Io(e) => e.into(),
-            jpeg_decoder::Error::Internal(_) => Self::DecodingError,
+            jpeg_decoder::Error::Internal(e) => Self::DecodingError(Some(e)),
         }
     }
 }
@@ -67,9 +118,9 @@ impl From<png::DecodingError> for ResizedPngError {
     fn from(e: png::DecodingError) -> Self {
         match e {
             png::DecodingError::IoError(e) => e.into(),
-            png::DecodingError::Format(_) => Self::DecodingError,
-            png::DecodingError::Parameter(_) => Self::ParameterError,
-            png::DecodingError::LimitsExceeded => Self::LimitsError,
+            png::DecodingError::Format(_) => Self::DecodingError(Some(e.into())),
+            png::DecodingError::Parameter(_) => Self::ParameterError(Some(e.into())),
+            png::DecodingError::LimitsExceeded => Self::LimitsError(Some(e.into())),
         }
     }
 }
@@ -78,9 +129,49 @@ impl From<png::EncodingError> for ResizedPngError {
     fn from(e: png::EncodingError) -> Self {
         match e {
             png::EncodingError::IoError(e) => e.into(),
-            png::EncodingError::Format(_) => Self::EncodingError,
-            png::EncodingError::Parameter(_) => Self::ParameterError,
-            png::EncodingError::LimitsExceeded => Self::LimitsError,
+            png::EncodingError::Format(_) => Self::EncodingError(Some(e.into())),
+            png::EncodingError::Parameter(_) => Self::ParameterError(Some(e.into())),
+            png::EncodingError::LimitsExceeded => Self::LimitsError(Some(e.into())),
+        }
+    }
+}
+
+#[cfg(feature = "avif")]
+impl From<avif_decode::Error> for ResizedPngError {
+    fn from(e: avif_decode::Error) -> Self {
+        Self::DecodingError(Some(e.into()))
+    }
+}
+
+#[cfg(feature = "icc")]
+impl From<lcms2::Error> for ResizedPngError {
+    fn from(e: lcms2::Error) -> Self {
+        Self::DecodingError(Some(e.into()))
+    }
+}
+
+impl From<jpeg_encoder::EncodingError> for ResizedPngError {
+    fn from(e: jpeg_encoder::EncodingError) -> Self {
+        match e {
+            jpeg_encoder::EncodingError::IoError(e) => e.into(),
+            jpeg_encoder::EncodingError::ZeroImageDimensions { .. } => {
+                Self::ParameterError(Some(e.into()))
+            }
+            _ => Self::EncodingError(Some(e.into())),
+        }
+    }
+}
+
+impl From<tiff::TiffError> for ResizedPngError {
+    fn from(e: tiff::TiffError) -> Self {
+        match e {
+            tiff::TiffError::FormatError(_) => Self::DecodingError(Some(e.into())),
+            tiff::TiffError::UnsupportedError(_) => Self::Unsupported(Some(e.into())),
+            tiff::TiffError::IoError(e) => e.into(),
+            tiff::TiffError::LimitsExceeded | tiff::TiffError::IntSizeError => {
+                Self::LimitsError(Some(e.into()))
+            }
+            tiff::TiffError::UsageError(_) => Self::ParameterError(Some(e.into())),
         }
     }
 }
@@ -88,8 +179,91 @@ impl From<png::EncodingError> for ResizedPngError {
 impl From<resize::Error> for ResizedPngError {
     fn from(e: resize::Error) -> Self {
         match e {
-            resize::Error::InvalidParameters => Self::ParameterError,
-            resize::Error::OutOfMemory => Self::LimitsError,
+            resize::Error::InvalidParameters => Self::ParameterError(Some(e.into())),
+            resize::Error::OutOfMemory => Self::LimitsError(Some(e.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [ResizedPngError; 8] = [
+        ResizedPngError::Unsupported(None),
+        ResizedPngError::NotFound,
+        ResizedPngError::IoError(None),
+        ResizedPngError::DecodingError(None),
+        ResizedPngError::EncodingError(None),
+        ResizedPngError::ParameterError(None),
+        ResizedPngError::LimitsError(None),
+        ResizedPngError::InputSizeError {
+            width: 0,
+            height: 0,
+        },
+    ];
+
+    mod send_sync {
+        use super::*;
+
+        #[test]
+        fn implemented_for_use_across_threads() {
+            fn assert_send_sync<T: Send + Sync>() {}
+
+            assert_send_sync::<ResizedPngError>();
+        }
+    }
+
+    mod to_code {
+        use super::*;
+
+        #[test]
+        fn checking_value() {
+            let codes: Vec<u32> = ALL.iter().map(|e| e.to_code()).collect();
+
+            assert_eq!(codes, [1, 2, 3, 4, 5, 6, 7, 8]);
+        }
+    }
+
+    mod from {
+        use super::*;
+
+        use std::error::Error;
+
+        #[test]
+        fn keep_source_when_png_decoding_error() {
+            let png_error = png::Decoder::new([0u8; 8].as_slice())
+                .read_info()
+                .err()
+                .unwrap();
+            let png_message = png_error.to_string();
+
+            let e: ResizedPngError = png_error.into();
+
+            assert_eq!(e.to_code(), 4);
+            assert!(e.to_string().contains(&png_message));
+            assert_eq!(e.source().unwrap().to_string(), png_message);
+        }
+    }
+
+    mod fmt {
+        use super::*;
+
+        #[test]
+        fn not_empty_when_each_variant() {
+            for e in ALL {
+                assert!(!e.to_string().is_empty());
+            }
+        }
+
+        #[test]
+        fn contains_size_when_input_size_error() {
+            let e = ResizedPngError::InputSizeError {
+                width: 0,
+                height: 200,
+            };
+
+            assert!(e.to_string().ends_with("0x200"));
         }
     }
 }
@@ -1,7 +1,960 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::error::ResizedPngError;
+
+#[cfg(feature = "avif")]
+pub(crate) mod avif;
 pub(crate) mod bmp;
 pub(crate) mod gif;
+#[cfg(feature = "icc")]
+pub(crate) mod icc;
 pub(crate) mod jpeg;
 pub(crate) mod png;
+pub(crate) mod tiff;
 pub(crate) mod webp;
 
-pub(crate) type ImageData = (Vec<u8>, u32, u32);
+/// 読み込む画像の画素数の上限。ヘッダの幅と高さだけが大きい画像で、メモリを使い果たさないため
+pub(crate) const MAX_INPUT_PIXELS: u64 = 20000 * 20000;
+
+/// ヘッダの幅と高さから、8bitのRGBAの並びの長さを求める
+///
+/// 確保する前に、画素数がMAX_INPUT_PIXELSを越えないか、usizeで表せるかを確かめる。どちらもだめならLimitsError。
+pub(crate) fn rgba8_len(width: u32, height: u32) -> Result<usize, ResizedPngError> {
+    if width as u64 * height as u64 > MAX_INPUT_PIXELS {
+        return Err(ResizedPngError::LimitsError(None));
+    }
+
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|v| v.checked_mul(4))
+        .ok_or(ResizedPngError::LimitsError(None))
+}
+
+/// 読み込む画像の大きさの上限。越える場合はLimitsErrorにする
+///
+/// 幅と高さと画素数は、できる形式では画素を読む前にヘッダの値で確かめる。
+/// `DecodeLimits::default()`から、`with_`で始まるメソッドで上限を変えて使う。
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct DecodeLimits {
+    pub(crate) max_width: u32,
+    pub(crate) max_height: u32,
+    pub(crate) max_pixels: u64,
+    /// 読み込むバイト列の長さの上限
+    pub(crate) max_bytes: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            max_pixels: MAX_INPUT_PIXELS,
+            max_bytes: usize::MAX,
+        }
+    }
+}
+
+impl DecodeLimits {
+    pub fn with_max_width(mut self, max_width: u32) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    pub fn with_max_height(mut self, max_height: u32) -> Self {
+        self.max_height = max_height;
+        self
+    }
+
+    /// 画素数の上限。MAX_INPUT_PIXELS(20000×20000)より大きくはできない
+    pub fn with_max_pixels(mut self, max_pixels: u64) -> Self {
+        self.max_pixels = max_pixels.min(MAX_INPUT_PIXELS);
+        self
+    }
+
+    /// 読み込むバイト列の長さの上限
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    fn check_bytes(&self, len: usize) -> Result<(), ResizedPngError> {
+        if len > self.max_bytes {
+            return Err(ResizedPngError::LimitsError(None));
+        }
+
+        Ok(())
+    }
+
+    fn check_size(&self, width: u32, height: u32) -> Result<(), ResizedPngError> {
+        if width > self.max_width
+            || height > self.max_height
+            || width as u64 * height as u64 > self.max_pixels
+        {
+            return Err(ResizedPngError::LimitsError(None));
+        }
+
+        Ok(())
+    }
+
+    /// 画素を読む前に、バイト列の長さとヘッダの幅と高さを確かめる
+    ///
+    /// 形式が分からない場合やヘッダだけを読めない形式は、読み込んだ後のcheck_sizeに任せる。
+    pub(crate) fn check_header(&self, bytes: &[u8]) -> Result<(), ResizedPngError> {
+        self.check_bytes(bytes.len())?;
+
+        let Some(format) = sniff_format(bytes) else {
+            return Ok(());
+        };
+        if let Some((width, height)) = read_dimensions_from_bytes_as(bytes, format)? {
+            self.check_size(width, height)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// 読み込める画像形式
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum ImageFormat {
+    Png,
+    Bmp,
+    Gif,
+    Jpeg,
+    Webp,
+    Tiff,
+    #[cfg(feature = "avif")]
+    Avif,
+}
+
+impl ImageFormat {
+    const ALL: &'static [ImageFormat] = &[
+        ImageFormat::Png,
+        ImageFormat::Bmp,
+        ImageFormat::Gif,
+        ImageFormat::Jpeg,
+        ImageFormat::Webp,
+        ImageFormat::Tiff,
+        #[cfg(feature = "avif")]
+        ImageFormat::Avif,
+    ];
+
+    /// GetImageTypeで返す名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "PNG",
+            ImageFormat::Bmp => "BMP",
+            ImageFormat::Gif => "GIF",
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::Webp => "WEBP",
+            ImageFormat::Tiff => "TIFF",
+            #[cfg(feature = "avif")]
+            ImageFormat::Avif => "AVIF",
+        }
+    }
+
+    /// この形式のファイルに使われる拡張子。小文字で、`.`はつけない
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ImageFormat::Png => &["png"],
+            ImageFormat::Bmp => &["bmp"],
+            ImageFormat::Gif => &["gif"],
+            ImageFormat::Jpeg => &["jpg", "jpeg"],
+            ImageFormat::Webp => &["webp"],
+            ImageFormat::Tiff => &["tif", "tiff"],
+            #[cfg(feature = "avif")]
+            ImageFormat::Avif => &["avif"],
+        }
+    }
+}
+
+/// 読み込める画像形式の一覧。有効なfeatureによって変わる
+pub fn supported_formats() -> &'static [ImageFormat] {
+    ImageFormat::ALL
+}
+
+/// 元の画像の色形式
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum SourceColorType {
+    Grayscale,
+    GrayscaleAlpha,
+    Rgb,
+    Rgba,
+    Indexed,
+}
+
+/// RGBAに変換した画像
+///
+/// 1チャンネルは既定で8bit。16bitのまま扱う場合は`ImageData<u16>`
+#[derive(Debug, Clone)]
+pub struct ImageData<T = u8> {
+    /// RGBAの並び
+    pub(crate) pixels: Vec<T>,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) source_color: SourceColorType,
+    /// 1ピクセルの物理的な大きさ(pngのpHYs)
+    pub(crate) pixel_dims: Option<::png::PixelDimensions>,
+    /// 埋め込まれていたICCプロファイル(pngのiCCP)
+    pub(crate) icc_profile: Option<Vec<u8>>,
+    /// 埋め込まれていたテキスト(pngのtEXtとzTXt)のキーワードと値
+    pub(crate) text_chunks: Vec<(String, String)>,
+}
+
+impl<T> ImageData<T> {
+    /// RGBAの並び
+    pub fn pixels(&self) -> &[T] {
+        &self.pixels
+    }
+
+    /// RGBAの並びを取り出す
+    pub fn into_pixels(self) -> Vec<T> {
+        self.pixels
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// 画像を読み込み、8bitのRGBAに変換する
+///
+/// 形式は先頭のバイト列から判別する。分からなければ全ての形式を順に試す。
+pub fn read_image_data(path: &PathBuf) -> Result<ImageData, ResizedPngError> {
+    read_any(path).map(|(image, _)| image)
+}
+
+/// メモリ上のバイト列から、read_image_dataと同じように読み込む
+pub fn read_image_data_from_bytes(bytes: &[u8]) -> Result<ImageData, ResizedPngError> {
+    read_any_from_bytes(bytes).map(|(image, _)| image)
+}
+
+/// 画像を読み込み、読み込めた形式と一緒に返す
+///
+/// 先頭のバイト列で形式が分かればその形式だけを試し、分からなければ全ての形式を順に試す。
+pub(crate) fn read_any(path: &PathBuf) -> Result<(ImageData, ImageFormat), ResizedPngError> {
+    let head = read_head(path)?;
+    check_not_empty(&head)?;
+
+    if let Some(format) = sniff_format(&head) {
+        return read_image_data_checked_as(path, format).map(|v| (v, format));
+    }
+
+    let mut result = Err(ResizedPngError::Unsupported(None));
+    for &format in ImageFormat::ALL {
+        result = read_image_data_checked_as(path, format).map(|v| (v, format));
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
+
+/// メモリ上のバイト列から画像を読み込み、読み込めた形式と一緒に返す
+///
+/// 形式の判別はread_anyと同じ。画素を読む前に、ヘッダの大きさを既定のDecodeLimitsで確かめる。
+pub(crate) fn read_any_from_bytes(
+    bytes: &[u8],
+) -> Result<(ImageData, ImageFormat), ResizedPngError> {
+    check_not_empty(bytes)?;
+    DecodeLimits::default().check_header(bytes)?;
+
+    read_any_from_bytes_unchecked(bytes)
+}
+
+/// read_any_from_bytesと同じ。大きさは呼び出し側で確かめる
+fn read_any_from_bytes_unchecked(
+    bytes: &[u8],
+) -> Result<(ImageData, ImageFormat), ResizedPngError> {
+    if let Some(format) = sniff_format(bytes) {
+        debug_log!("detected as {} by signature", format.name());
+        return read_image_data_from_bytes_as(bytes, format).map(|v| (v, format));
+    }
+
+    let mut result = Err(ResizedPngError::Unsupported(None));
+    for &format in ImageFormat::ALL {
+        debug_log!("trying to decode as {}", format.name());
+        result = read_image_data_from_bytes_as(bytes, format).map(|v| (v, format));
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
+
+/// 画素を読まずに、画像の幅と高さと形式を返す
+///
+/// 形式の判別はread_anyと同じ。
+pub(crate) fn read_dimensions(
+    path: &PathBuf,
+) -> Result<((u32, u32), ImageFormat), ResizedPngError> {
+    let mut head = Vec::new();
+    File::open(path)?.take(12).read_to_end(&mut head)?;
+    check_not_empty(&head)?;
+
+    if let Some(format) = sniff_format(&head) {
+        return read_dimensions_as(path, format).map(|v| (v, format));
+    }
+
+    let mut result = Err(ResizedPngError::Unsupported(None));
+    for &format in ImageFormat::ALL {
+        result = read_dimensions_as(path, format).map(|v| (v, format));
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result
+}
+
+/// フレームの数を返す。アニメーションに対応していない形式は1
+pub(crate) fn frame_count(path: &PathBuf, format: ImageFormat) -> Result<u32, ResizedPngError> {
+    match format {
+        ImageFormat::Gif => self::gif::frame_count(path),
+        ImageFormat::Webp => self::webp::frame_count(path),
+        _ => Ok(1),
+    }
+}
+
+/// sniff_formatで形式を判別するのに必要な、先頭のバイト数
+const SNIFF_LEN: u64 = 12;
+
+/// 形式の判別に使う、ファイルの先頭のバイト列を読む
+pub(crate) fn read_head(path: &PathBuf) -> Result<Vec<u8>, ResizedPngError> {
+    let mut head = Vec::new();
+    File::open(path)?.take(SNIFF_LEN).read_to_end(&mut head)?;
+
+    Ok(head)
+}
+
+/// 空のファイルやバイト列を読み込もうとしたときのエラーの元
+#[derive(Debug)]
+struct EmptyInput;
+
+impl std::fmt::Display for EmptyInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "入力が空です")
+    }
+}
+
+impl std::error::Error for EmptyInput {}
+
+/// 空の入力は、どの形式でも読めないので、それぞれのデコーダを試す前にDecodingErrorにする
+fn check_not_empty(head: &[u8]) -> Result<(), ResizedPngError> {
+    if head.is_empty() {
+        return Err(ResizedPngError::DecodingError(Some(EmptyInput.into())));
+    }
+
+    Ok(())
+}
+
+/// 先頭のバイト列から画像形式を判別する
+pub(crate) fn sniff_format(bytes: &[u8]) -> Option<ImageFormat> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(ImageFormat::Png),
+        [b'B', b'M', ..] => Some(ImageFormat::Bmp),
+        [b'G', b'I', b'F', b'8', ..] => Some(ImageFormat::Gif),
+        [0xFF, 0xD8, ..] => Some(ImageFormat::Jpeg),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(ImageFormat::Webp),
+        [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => Some(ImageFormat::Tiff),
+        #[cfg(feature = "avif")]
+        [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f' | b's', ..] => {
+            Some(ImageFormat::Avif)
+        }
+        _ => None,
+    }
+}
+
+/// メモリ上のバイト列から画像を読み込み、読み込めた形式と縮小する前の幅と高さと一緒に返す
+///
+/// jpegは、min_sizeが返す大きさを下回らない範囲で縮小しながら読み込む。
+/// gifは、gif_frame番目のフレームまでを重ねて読み込む。
+/// pngは、パレットの範囲外の番号をpalette_indexに従って扱う。
+/// それ以外の形式はそのままの大きさで読み込む。
+/// 大きさがlimitsを越える場合はLimitsErrorにする。
+pub(crate) fn read_any_scaled_from_bytes<F>(
+    bytes: &[u8],
+    gif_frame: usize,
+    palette_index: self::png::PaletteIndexMode,
+    limits: &DecodeLimits,
+    min_size: F,
+) -> Result<(ImageData, ImageFormat, (u32, u32)), ResizedPngError>
+where
+    F: FnOnce(u32, u32) -> Option<(u32, u32)>,
+{
+    check_not_empty(bytes)?;
+    limits.check_header(bytes)?;
+
+    let (image, format, original_size) =
+        read_any_scaled_from_bytes_unchecked(bytes, gif_frame, palette_index, min_size)?;
+    limits.check_size(original_size.0, original_size.1)?;
+    debug_log!(
+        "decoded as {} ({}x{}, source {}x{})",
+        format.name(),
+        image.width,
+        image.height,
+        original_size.0,
+        original_size.1
+    );
+
+    Ok((image, format, original_size))
+}
+
+fn read_any_scaled_from_bytes_unchecked<F>(
+    bytes: &[u8],
+    gif_frame: usize,
+    palette_index: self::png::PaletteIndexMode,
+    min_size: F,
+) -> Result<(ImageData, ImageFormat, (u32, u32)), ResizedPngError>
+where
+    F: FnOnce(u32, u32) -> Option<(u32, u32)>,
+{
+    match sniff_format(bytes) {
+        Some(ImageFormat::Jpeg) => {
+            let (image, original_size) =
+                self::jpeg::read_image_data_scaled_from_bytes(bytes, min_size)?;
+            return Ok((image, ImageFormat::Jpeg, original_size));
+        }
+        Some(ImageFormat::Gif) => {
+            let image = self::gif::read_frame_from_bytes(bytes, gif_frame)?;
+            let original_size = (image.width, image.height);
+            return Ok((image, ImageFormat::Gif, original_size));
+        }
+        Some(ImageFormat::Png) => {
+            let image = self::png::read_image_data_from_bytes_with(bytes, palette_index)?;
+            let original_size = (image.width, image.height);
+            return Ok((image, ImageFormat::Png, original_size));
+        }
+        _ => {}
+    }
+
+    let (image, format) = read_any_from_bytes_unchecked(bytes)?;
+    let original_size = (image.width, image.height);
+
+    Ok((image, format, original_size))
+}
+
+/// 16bitのまま扱える画像なら、16bitのRGBAで読み込む。そうでなければNone
+///
+/// 今のところ16bitのpngのみ。大きさがlimitsを越える場合はLimitsErrorにする。
+pub(crate) fn read_16bit_from_bytes(
+    bytes: &[u8],
+    limits: &DecodeLimits,
+) -> Result<Option<ImageData<u16>>, ResizedPngError> {
+    match sniff_format(bytes) {
+        Some(ImageFormat::Png) => {
+            limits.check_header(bytes)?;
+            let image = self::png::read_image_data16_from_bytes(bytes)?;
+            #[cfg(feature = "log")]
+            if let Some(image) = &image {
+                log::debug!("decoded as PNG in 16bit ({}x{})", image.width, image.height);
+            }
+            Ok(image)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// 画素を読む前に、ヘッダの幅と高さを既定のDecodeLimitsで確かめてから読み込む
+fn read_image_data_checked_as(
+    path: &PathBuf,
+    format: ImageFormat,
+) -> Result<ImageData, ResizedPngError> {
+    let (width, height) = read_dimensions_as(path, format)?;
+    DecodeLimits::default().check_size(width, height)?;
+
+    read_image_data_as(path, format)
+}
+
+fn read_image_data_as(path: &PathBuf, format: ImageFormat) -> Result<ImageData, ResizedPngError> {
+    match format {
+        ImageFormat::Png => self::png::read_image_data(path),
+        ImageFormat::Bmp => self::bmp::read_image_data(path),
+        ImageFormat::Gif => self::gif::read_image_data(path),
+        ImageFormat::Jpeg => self::jpeg::read_image_data(path),
+        ImageFormat::Webp => self::webp::read_image_data(path),
+        ImageFormat::Tiff => self::tiff::read_image_data(path),
+        #[cfg(feature = "avif")]
+        ImageFormat::Avif => self::avif::read_image_data(path),
+    }
+}
+
+fn read_image_data_from_bytes_as(
+    bytes: &[u8],
+    format: ImageFormat,
+) -> Result<ImageData, ResizedPngError> {
+    match format {
+        ImageFormat::Png => self::png::read_image_data_from_bytes(bytes),
+        ImageFormat::Bmp => self::bmp::read_image_data_from_bytes(bytes),
+        ImageFormat::Gif => self::gif::read_image_data_from_bytes(bytes),
+        ImageFormat::Jpeg => self::jpeg::read_image_data_from_bytes(bytes),
+        ImageFormat::Webp => self::webp::read_image_data_from_bytes(bytes),
+        ImageFormat::Tiff => self::tiff::read_image_data_from_bytes(bytes),
+        #[cfg(feature = "avif")]
+        ImageFormat::Avif => self::avif::read_image_data_from_bytes(bytes),
+    }
+}
+
+fn read_dimensions_as(path: &PathBuf, format: ImageFormat) -> Result<(u32, u32), ResizedPngError> {
+    match format {
+        ImageFormat::Png => self::png::read_dimensions(path),
+        ImageFormat::Bmp => self::bmp::read_dimensions(path),
+        ImageFormat::Gif => self::gif::read_dimensions(path),
+        ImageFormat::Jpeg => self::jpeg::read_dimensions(path),
+        ImageFormat::Webp => self::webp::read_dimensions(path),
+        ImageFormat::Tiff => self::tiff::read_dimensions(path),
+        #[cfg(feature = "avif")]
+        ImageFormat::Avif => self::avif::read_dimensions(path),
+    }
+}
+
+/// メモリ上のバイト列から、画素を読まずに幅と高さを返す。ヘッダだけを読めない形式はNone
+fn read_dimensions_from_bytes_as(
+    bytes: &[u8],
+    format: ImageFormat,
+) -> Result<Option<(u32, u32)>, ResizedPngError> {
+    let size = match format {
+        ImageFormat::Png => self::png::read_dimensions_from_bytes(bytes)?,
+        ImageFormat::Bmp => self::bmp::read_dimensions_from_bytes(bytes)?,
+        ImageFormat::Gif => self::gif::read_dimensions_from_bytes(bytes)?,
+        ImageFormat::Jpeg => self::jpeg::read_dimensions_from_bytes(bytes)?,
+        ImageFormat::Webp => self::webp::read_dimensions_from_bytes(bytes)?,
+        ImageFormat::Tiff => self::tiff::read_dimensions_from_bytes(bytes)?,
+        // avifは全体を読まないと大きさが分からない。
+        #[cfg(feature = "avif")]
+        ImageFormat::Avif => return Ok(None),
+    };
+
+    Ok(Some(size))
+}
+
+/// 16bitの値を8bitに丸める
+pub(crate) fn u16_to_u8(v: u16) -> u8 {
+    ((v as u32 + 128) / 257) as u8
+}
+
+/// インクの量で表されたCMYKの値をRGBの値に変換する
+pub(crate) fn cmyk_to_rgb_value(v: u8, k: u8) -> u8 {
+    ((u8::MAX - v) as u32 * (u8::MAX - k) as u32 / u8::MAX as u32) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod rgba8_len {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_within_limit() {
+            assert_eq!(rgba8_len(3, 2).unwrap(), 24);
+            assert_eq!(rgba8_len(0, 2).unwrap(), 0);
+        }
+
+        #[test]
+        fn limits_error_when_too_many_pixels() {
+            assert!(matches!(
+                rgba8_len(65535, 65535),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+            assert!(matches!(
+                rgba8_len(u32::MAX, 1),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+        }
+    }
+
+    mod decode_limits {
+        use super::*;
+
+        #[test]
+        fn clamped_when_max_pixels_is_over_input_limit() {
+            let limits = DecodeLimits::default().with_max_pixels(u64::MAX);
+            assert_eq!(limits.max_pixels, MAX_INPUT_PIXELS);
+        }
+
+        #[test]
+        fn limits_error_when_header_is_over_max_width() {
+            let bytes = std::fs::read(
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png"),
+            )
+            .unwrap();
+            let limits = DecodeLimits::default().with_max_width(99);
+
+            assert!(matches!(
+                limits.check_header(&bytes),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+            assert!(DecodeLimits::default()
+                .with_max_width(100)
+                .check_header(&bytes)
+                .is_ok());
+        }
+    }
+
+    mod read_any {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_png() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            let (data, format) = read_any(&path).unwrap();
+
+            assert_eq!(format, ImageFormat::Png);
+            assert_eq!(data.width, 100);
+            assert_eq!(data.height, 200);
+        }
+
+        #[test]
+        fn checking_value_when_jpeg() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+
+            let (_data, format) = read_any(&path).unwrap();
+
+            assert_eq!(format, ImageFormat::Jpeg);
+        }
+
+        #[test]
+        fn failed_when_not_image() {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+
+            assert!(read_any(&path).is_err());
+        }
+
+        #[test]
+        fn empty_input_when_empty_file() {
+            let out_dir = tempfile::tempdir().unwrap();
+            let path = out_dir.path().join("empty.png");
+            File::create(&path).unwrap();
+
+            let e = read_any(&path).err().unwrap();
+            assert!(matches!(e, ResizedPngError::DecodingError(Some(_))));
+            assert!(e.to_string().contains("入力が空です"));
+
+            let e = read_dimensions(&path).err().unwrap();
+            assert!(matches!(e, ResizedPngError::DecodingError(Some(_))));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn limits_error_when_header_size_is_too_large() {
+            let out_dir = tempfile::tempdir().unwrap();
+            let path = out_dir.path().join("large.bmp");
+            std::fs::write(&path, large_bmp_header()).unwrap();
+
+            assert!(matches!(
+                read_any(&path),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod read_any_from_bytes {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_each_sample() {
+            let cases = [
+                ("sample.png", ImageFormat::Png),
+                ("sample.bmp", ImageFormat::Bmp),
+                ("sample.gif", ImageFormat::Gif),
+                ("sample.jpg", ImageFormat::Jpeg),
+                ("sample.webp", ImageFormat::Webp),
+                ("sample.tif", ImageFormat::Tiff),
+            ];
+
+            for (name, expected) in cases {
+                let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("test_target/image")
+                    .join(name);
+                let bytes = std::fs::read(&path).unwrap();
+
+                let (from_bytes, format) = read_any_from_bytes(&bytes).unwrap();
+                let (from_path, _) = read_any(&path).unwrap();
+
+                assert_eq!(format, expected, "{}", name);
+                assert_eq!(from_bytes.pixels, from_path.pixels, "{}", name);
+                assert_eq!(from_bytes.width, from_path.width, "{}", name);
+                assert_eq!(from_bytes.height, from_path.height, "{}", name);
+            }
+        }
+
+        #[test]
+        fn failed_when_not_image() {
+            assert!(read_any_from_bytes(b"[package]").is_err());
+        }
+
+        #[test]
+        fn empty_input_when_empty() {
+            let e = read_any_from_bytes(&[]).err().unwrap();
+            assert!(matches!(e, ResizedPngError::DecodingError(Some(_))));
+            assert!(e.to_string().contains("入力が空です"));
+        }
+
+        #[test]
+        fn limits_error_when_header_size_is_too_large() {
+            let bytes = large_bmp_header();
+
+            assert!(matches!(
+                read_any_from_bytes(&bytes),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+        }
+    }
+
+    /// 幅と高さだけが60000x60000の、画素のないBMP
+    fn large_bmp_header() -> Vec<u8> {
+        let mut bytes = vec![0; 54];
+        bytes[..2].copy_from_slice(b"BM");
+        bytes[10..14].copy_from_slice(&54u32.to_le_bytes());
+        bytes[14..18].copy_from_slice(&40u32.to_le_bytes());
+        bytes[18..22].copy_from_slice(&60000i32.to_le_bytes());
+        bytes[22..26].copy_from_slice(&60000i32.to_le_bytes());
+        bytes[26..28].copy_from_slice(&1u16.to_le_bytes());
+        bytes[28..30].copy_from_slice(&24u16.to_le_bytes());
+
+        bytes
+    }
+
+    mod sniff_format {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_each_signature() {
+            let cases: [(&[u8], ImageFormat); 7] = [
+                (
+                    &[
+                        0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D,
+                    ],
+                    ImageFormat::Png,
+                ),
+                (b"BM\x36\x00\x00\x00", ImageFormat::Bmp),
+                (b"GIF89a", ImageFormat::Gif),
+                (&[0xFF, 0xD8, 0xFF, 0xE0], ImageFormat::Jpeg),
+                (b"RIFF\x24\x00\x00\x00WEBPVP8L", ImageFormat::Webp),
+                (b"II*\x00\x08\x00\x00\x00", ImageFormat::Tiff),
+                (b"MM\x00*\x00\x00\x00\x08", ImageFormat::Tiff),
+            ];
+
+            for (bytes, format) in cases {
+                assert_eq!(sniff_format(bytes), Some(format));
+            }
+        }
+
+        #[test]
+        fn none_when_unknown_signature() {
+            assert_eq!(sniff_format(b"RIFF\x24\x00\x00\x00WAVE"), None);
+            assert_eq!(sniff_format(b"[package]"), None);
+            assert_eq!(sniff_format(&[]), None);
+        }
+
+        #[test]
+        fn checking_value_when_sample_files() {
+            let cases = [
+                ("sample.png", ImageFormat::Png),
+                ("sample.bmp", ImageFormat::Bmp),
+                ("sample.gif", ImageFormat::Gif),
+                ("sample.jpg", ImageFormat::Jpeg),
+                ("sample.webp", ImageFormat::Webp),
+                ("sample.tif", ImageFormat::Tiff),
+            ];
+
+            for (name, format) in cases {
+                let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("test_target/image")
+                    .join(name);
+                let bytes = std::fs::read(path).unwrap();
+
+                assert_eq!(sniff_format(&bytes), Some(format));
+            }
+        }
+
+        #[cfg(feature = "avif")]
+        #[test]
+        fn checking_value_when_sample_avif() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.avif");
+            let bytes = std::fs::read(path).unwrap();
+
+            assert_eq!(sniff_format(&bytes), Some(ImageFormat::Avif));
+        }
+
+        #[cfg(not(feature = "avif"))]
+        #[test]
+        fn none_when_avif_without_feature() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.avif");
+            let bytes = std::fs::read(path).unwrap();
+
+            assert_eq!(sniff_format(&bytes), None);
+        }
+    }
+
+    mod read_any_scaled_from_bytes {
+        use super::*;
+
+        const SAMPLES: [&str; 6] = [
+            "sample.png",
+            "sample.bmp",
+            "sample.gif",
+            "sample.jpg",
+            "sample.webp",
+            "sample.tif",
+        ];
+
+        fn read_with(
+            bytes: &[u8],
+            limits: &DecodeLimits,
+        ) -> Result<(ImageData, ImageFormat, (u32, u32)), ResizedPngError> {
+            read_any_scaled_from_bytes(
+                bytes,
+                0,
+                crate::image::png::PaletteIndexMode::default(),
+                limits,
+                |_, _| None,
+            )
+        }
+
+        fn read_sample(name: &str) -> Vec<u8> {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("test_target/image")
+                .join(name);
+            std::fs::read(path).unwrap()
+        }
+
+        #[test]
+        fn success_when_each_sample_is_at_limits() {
+            for name in SAMPLES {
+                let bytes = read_sample(name);
+                let limits = DecodeLimits {
+                    max_width: 100,
+                    max_height: 200,
+                    max_pixels: 100 * 200,
+                    max_bytes: bytes.len(),
+                };
+
+                let (data, _format, original_size) = read_with(&bytes, &limits).unwrap();
+
+                assert_eq!(original_size, (100, 200), "{}", name);
+                assert_eq!((data.width, data.height), (100, 200), "{}", name);
+            }
+        }
+
+        #[test]
+        fn failed_when_each_sample_is_over_limits() {
+            for name in SAMPLES {
+                let bytes = read_sample(name);
+                let at_limits = DecodeLimits {
+                    max_width: 100,
+                    max_height: 200,
+                    max_pixels: 100 * 200,
+                    max_bytes: bytes.len(),
+                };
+                let cases = [
+                    DecodeLimits {
+                        max_width: 99,
+                        ..at_limits
+                    },
+                    DecodeLimits {
+                        max_height: 199,
+                        ..at_limits
+                    },
+                    DecodeLimits {
+                        max_pixels: 100 * 200 - 1,
+                        ..at_limits
+                    },
+                    DecodeLimits {
+                        max_bytes: bytes.len() - 1,
+                        ..at_limits
+                    },
+                ];
+
+                for limits in cases {
+                    assert!(
+                        matches!(
+                            read_with(&bytes, &limits),
+                            Err(ResizedPngError::LimitsError(None))
+                        ),
+                        "{} {:?}",
+                        name,
+                        limits
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn success_when_default_limits() {
+            let bytes = read_sample("sample.png");
+
+            assert!(read_with(&bytes, &DecodeLimits::default()).is_ok());
+        }
+    }
+
+    mod supported_formats {
+        use super::*;
+
+        #[test]
+        fn contains_each_format() {
+            for format in [
+                ImageFormat::Png,
+                ImageFormat::Bmp,
+                ImageFormat::Gif,
+                ImageFormat::Jpeg,
+                ImageFormat::Webp,
+                ImageFormat::Tiff,
+            ] {
+                assert!(supported_formats().contains(&format), "{:?}", format);
+            }
+        }
+    }
+
+    mod image_format {
+        use super::*;
+
+        #[test]
+        fn checking_value_of_extensions() {
+            assert_eq!(ImageFormat::Png.extensions(), ["png"]);
+            assert_eq!(ImageFormat::Bmp.extensions(), ["bmp"]);
+            assert_eq!(ImageFormat::Gif.extensions(), ["gif"]);
+            assert_eq!(ImageFormat::Jpeg.extensions(), ["jpg", "jpeg"]);
+            assert_eq!(ImageFormat::Webp.extensions(), ["webp"]);
+            assert_eq!(ImageFormat::Tiff.extensions(), ["tif", "tiff"]);
+        }
+    }
+
+    mod u16_to_u8 {
+        use super::*;
+
+        #[test]
+        fn checking_value_at_edges() {
+            assert_eq!(u16_to_u8(0), 0);
+            assert_eq!(u16_to_u8(u16::MAX), u8::MAX);
+        }
+
+        #[test]
+        fn checking_value_near_rounding_boundaries() {
+            // 128 / 257 = 0.498..., 129 / 257 = 0.501...
+            assert_eq!(u16_to_u8(128), 0);
+            assert_eq!(u16_to_u8(129), 1);
+            // 65280 / 257 = 254.007..., 65407 / 257 = 254.50...
+            assert_eq!(u16_to_u8(0xFF00), 254);
+            assert_eq!(u16_to_u8(0xFF7F), 255);
+            // 上位だけを見ると切り捨てになってしまう値。
+            assert_eq!(u16_to_u8(0x12FF), 0x13);
+        }
+    }
+}
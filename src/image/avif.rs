@@ -0,0 +1,145 @@
+//! avifの読み込み
+//!
+//! `avif` featureを有効にしたときだけ組み込まれる。
+//! デコーダがCのライブラリ(libaom)を使うので、既定のビルドには含めない。
+
+use std::path::PathBuf;
+
+use avif_decode::{Decoder, Image};
+
+use crate::error::ResizedPngError;
+
+use super::{u16_to_u8, ImageData, SourceColorType};
+
+pub(crate) fn read_image_data(path: &PathBuf) -> Result<ImageData, ResizedPngError> {
+    let bytes = std::fs::read(path)?;
+    read_image_data_from_bytes(&bytes)
+}
+
+/// メモリ上のバイト列から読み込む
+pub(crate) fn read_image_data_from_bytes(bytes: &[u8]) -> Result<ImageData, ResizedPngError> {
+    let image = Decoder::from_avif(bytes)?.to_image()?;
+
+    let (pixels, width, height, source_color): (Vec<u8>, usize, usize, SourceColorType) =
+        match image {
+            Image::Rgb8(img) => (
+                img.pixels()
+                    .flat_map(|p| [p.r, p.g, p.b, u8::MAX])
+                    .collect(),
+                img.width(),
+                img.height(),
+                SourceColorType::Rgb,
+            ),
+            Image::Rgb16(img) => (
+                img.pixels()
+                    .flat_map(|p| [u16_to_u8(p.r), u16_to_u8(p.g), u16_to_u8(p.b), u8::MAX])
+                    .collect(),
+                img.width(),
+                img.height(),
+                SourceColorType::Rgb,
+            ),
+            Image::Rgba8(img) => (
+                img.pixels().flat_map(|p| [p.r, p.g, p.b, p.a]).collect(),
+                img.width(),
+                img.height(),
+                SourceColorType::Rgba,
+            ),
+            Image::Rgba16(img) => (
+                img.pixels()
+                    .flat_map(|p| {
+                        [
+                            u16_to_u8(p.r),
+                            u16_to_u8(p.g),
+                            u16_to_u8(p.b),
+                            u16_to_u8(p.a),
+                        ]
+                    })
+                    .collect(),
+                img.width(),
+                img.height(),
+                SourceColorType::Rgba,
+            ),
+            Image::Gray8(img) => (
+                img.pixels()
+                    .flat_map(|p| [p.0, p.0, p.0, u8::MAX])
+                    .collect(),
+                img.width(),
+                img.height(),
+                SourceColorType::Grayscale,
+            ),
+            Image::Gray16(img) => (
+                img.pixels()
+                    .flat_map(|p| {
+                        let v = u16_to_u8(p.0);
+                        [v, v, v, u8::MAX]
+                    })
+                    .collect(),
+                img.width(),
+                img.height(),
+                SourceColorType::Grayscale,
+            ),
+        };
+
+    Ok(ImageData {
+        pixels,
+        width: width as u32,
+        height: height as u32,
+        source_color,
+        pixel_dims: None,
+        icc_profile: None,
+        text_chunks: Vec::new(),
+    })
+}
+
+/// 幅と高さを返す
+///
+/// ヘッダだけを読む手段がないので、画素までデコードする。
+pub(crate) fn read_dimensions(path: &PathBuf) -> Result<(u32, u32), ResizedPngError> {
+    read_image_data(path).map(|v| (v.width, v.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod read_dimensions {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_sample_avif() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.avif");
+
+            assert_eq!(read_dimensions(&path).unwrap(), (100, 200));
+        }
+    }
+
+    mod read_image_data {
+        use super::*;
+
+        #[test]
+        fn success_when_valid_avif_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.avif");
+
+            let ImageData {
+                pixels,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 100);
+            assert_eq!(height, 200);
+            assert_eq!(pixels.len(), 100 * 200 * 4);
+        }
+
+        #[test]
+        fn failed_when_invalid_avif_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert!(read_image_data(&path).is_err());
+        }
+    }
+}
@@ -3,50 +3,755 @@ use std::io::prelude::Read;
 use std::path::PathBuf;
 
 use embedded_graphics::{pixelcolor::Rgb888, prelude::RgbColor, Pixel};
-use tinybmp::Bmp;
+use tinybmp::{Bmp, Bpp};
 
 use crate::error::ResizedPngError;
 
-use super::ImageData;
+use super::{rgba8_len, ImageData, SourceColorType};
 
 pub(crate) fn read_image_data(path: &PathBuf) -> Result<ImageData, ResizedPngError> {
     let mut fs = File::open(path)?;
     let mut bytes = Vec::new();
     fs.read_to_end(&mut bytes)?;
 
-    let bmp = Bmp::<Rgb888>::from_slice(&bytes)?;
+    read_image_data_from_bytes(&bytes)
+}
 
-    let mut buf = Vec::new();
-    for Pixel(_position, color) in bmp.pixels() {
-        buf.push(color.r());
-        buf.push(color.g());
-        buf.push(color.b());
-        buf.push(u8::MAX);
+/// メモリ上のバイト列から読み込む
+pub(crate) fn read_image_data_from_bytes(bytes: &[u8]) -> Result<ImageData, ResizedPngError> {
+    // tinybmpはアルファのマスクを持つ画像と、ランレングス圧縮の画像を読めないので、自前で読む。
+    if let Some(masks) = alpha_channel_masks(bytes) {
+        return read_bitfields_32bit(bytes, masks);
+    }
+    if let Some(rle) = rle_compression(bytes) {
+        return read_rle(bytes, rle);
     }
 
+    // パレット、16bit、32bitの画像も、tinybmpがRgb888に変換してくれる。
+    let bmp = Bmp::<Rgb888>::from_slice(bytes)?;
+
     let header = bmp.as_raw().header();
     let width = header.image_size.width;
     let height = header.image_size.height;
 
-    Ok((buf, width, height))
+    // pixels()はファイル内の順(通常は下の行から)で返すので、位置に従って並べる。
+    let pixel_len = width as usize * height as usize;
+    let mut buf = vec![0; pixel_len * 4];
+    let mut written = 0;
+    for Pixel(position, color) in bmp.pixels() {
+        let index = (position.y as usize * width as usize + position.x as usize) * 4;
+
+        if let Some(pixel) = buf.get_mut(index..index + 4) {
+            pixel.copy_from_slice(&[color.r(), color.g(), color.b(), u8::MAX]);
+            written += 1;
+        }
+    }
+
+    // 画素が欠けた場合も、長さの合わないバッファは返さない。
+    if written != pixel_len {
+        return Err(ResizedPngError::DecodingError(None));
+    }
+
+    let source_color = match header.bpp {
+        Bpp::Bits1 | Bpp::Bits4 | Bpp::Bits8 => SourceColorType::Indexed,
+        _ => SourceColorType::Rgb,
+    };
+
+    Ok(ImageData {
+        pixels: buf,
+        width,
+        height,
+        source_color,
+        pixel_dims: None,
+        icc_profile: None,
+        text_chunks: Vec::new(),
+    })
+}
+
+/// 32bitでV4以降の情報ヘッダにアルファのマスクがある場合、赤、緑、青、アルファのマスクを返す
+fn alpha_channel_masks(bytes: &[u8]) -> Option<[u32; 4]> {
+    let read_u32 = |offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            bytes.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+
+    let header_size = read_u32(14)?;
+    let bpp = u16::from_le_bytes(bytes.get(28..30)?.try_into().ok()?);
+    let compression = read_u32(30)?;
+
+    // 3はBI_BITFIELDS、6はBI_ALPHABITFIELDS。
+    if header_size < 56 || bpp != 32 || !matches!(compression, 3 | 6) {
+        return None;
+    }
+
+    let masks = [read_u32(54)?, read_u32(58)?, read_u32(62)?, read_u32(66)?];
+
+    (masks[3] != 0).then_some(masks)
+}
+
+/// マスクに従って、32bitの画像をアルファを残したまま読み込む
+fn read_bitfields_32bit(bytes: &[u8], masks: [u32; 4]) -> Result<ImageData, ResizedPngError> {
+    let (width, height) =
+        dimensions_from_header(bytes).ok_or(ResizedPngError::DecodingError(None))?;
+    let top_down = i32::from_le_bytes(bytes[22..26].try_into().unwrap()) < 0;
+    let data_start = u32::from_le_bytes(bytes[10..14].try_into().unwrap()) as usize;
+
+    let row_len = width as usize * 4;
+    let data = (row_len as u64)
+        .checked_mul(height as u64)
+        .and_then(|len| bytes.get(data_start..)?.get(..usize::try_from(len).ok()?))
+        .ok_or(ResizedPngError::DecodingError(None))?;
+
+    let mut buf = vec![0; data.len()];
+    for (y, row) in data.chunks_exact(row_len).enumerate() {
+        let y = if top_down { y } else { height as usize - 1 - y };
+
+        for (x, pixel) in row.chunks_exact(4).enumerate() {
+            let value = u32::from_le_bytes(pixel.try_into().unwrap());
+            let index = (y * width as usize + x) * 4;
+
+            for (channel, mask) in buf[index..index + 4].iter_mut().zip(masks) {
+                *channel = channel_value(value, mask);
+            }
+        }
+    }
+
+    // アルファが全て0の画像は、アルファを使っていないものとして扱う。
+    if buf.chunks_exact(4).all(|v| v[3] == 0) {
+        buf.chunks_exact_mut(4).for_each(|v| v[3] = u8::MAX);
+    }
+
+    Ok(ImageData {
+        pixels: buf,
+        width,
+        height,
+        source_color: SourceColorType::Rgba,
+        pixel_dims: None,
+        icc_profile: None,
+        text_chunks: Vec::new(),
+    })
+}
+
+/// ランレングス圧縮の種類
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Rle {
+    /// BI_RLE8。1つの番号が1byte
+    Rle8,
+    /// BI_RLE4。1つの番号が4bit
+    Rle4,
+}
+
+/// 情報ヘッダの圧縮形式がRLE8かRLE4の場合、その種類を返す
+fn rle_compression(bytes: &[u8]) -> Option<Rle> {
+    let header_size = u32::from_le_bytes(bytes.get(14..18)?.try_into().ok()?);
+    let bpp = u16::from_le_bytes(bytes.get(28..30)?.try_into().ok()?);
+    let compression = u32::from_le_bytes(bytes.get(30..34)?.try_into().ok()?);
+
+    // OS/2のBITMAPCOREHEADERには圧縮形式がない。
+    match (header_size >= 40, bpp, compression) {
+        (true, 8, 1) => Some(Rle::Rle8),
+        (true, 4, 2) => Some(Rle::Rle4),
+        _ => None,
+    }
+}
+
+/// ランレングス圧縮の画像を、パレットの色で読み込む
+///
+/// 行の終わりや移動で飛ばされたピクセルは透明にする。
+fn read_rle(bytes: &[u8], rle: Rle) -> Result<ImageData, ResizedPngError> {
+    let read_u32 = |offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            bytes.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    let (width, height) =
+        dimensions_from_header(bytes).ok_or(ResizedPngError::DecodingError(None))?;
+    // 圧縮した画像は下の行からのみ。
+    if i32::from_le_bytes(bytes[22..26].try_into().unwrap()) < 0 {
+        return Err(ResizedPngError::DecodingError(None));
+    }
+    let header_size = read_u32(14).ok_or(ResizedPngError::DecodingError(None))? as usize;
+    let data_start = read_u32(10).ok_or(ResizedPngError::DecodingError(None))? as usize;
+    let max_colors = match rle {
+        Rle::Rle8 => 256,
+        Rle::Rle4 => 16,
+    };
+    let colors = match read_u32(46).ok_or(ResizedPngError::DecodingError(None))? as usize {
+        0 => max_colors,
+        v => v.min(max_colors),
+    };
+
+    // パレットはBGRXの順。
+    let palette: Vec<[u8; 4]> = bytes
+        .get(14 + header_size..)
+        .and_then(|v| v.get(..colors * 4))
+        .ok_or(ResizedPngError::DecodingError(None))?
+        .chunks_exact(4)
+        .map(|v| [v[2], v[1], v[0], u8::MAX])
+        .collect();
+    let data = bytes
+        .get(data_start..)
+        .ok_or(ResizedPngError::DecodingError(None))?;
+
+    let (w, h) = (width as usize, height as usize);
+    // 圧縮したデータの長さは大きさと関係ないので、ヘッダの幅と高さを確保する前に確かめる。
+    let mut buf = vec![0; rgba8_len(width, height)?];
+    let (mut x, mut y) = (0, 0);
+    let mut put = |x: usize, y: usize, index: u8| -> Result<(), ResizedPngError> {
+        let color = palette
+            .get(index as usize)
+            .ok_or(ResizedPngError::DecodingError(None))?;
+        // 行からはみ出した分は捨てる。
+        if x < w && y < h {
+            let offset = ((h - 1 - y) * w + x) * 4;
+            buf[offset..offset + 4].copy_from_slice(color);
+        }
+        Ok(())
+    };
+
+    let mut iter = data.iter().copied();
+    while let (Some(count), Some(value)) = (iter.next(), iter.next()) {
+        match (count, value) {
+            (0, 0) => {
+                x = 0;
+                y += 1;
+            }
+            (0, 1) => break,
+            (0, 2) => {
+                let (Some(dx), Some(dy)) = (iter.next(), iter.next()) else {
+                    break;
+                };
+                x += dx as usize;
+                y += dy as usize;
+            }
+            // 絶対モード。valueの数の番号がそのまま続き、2byteの境界まで埋められている。
+            (0, len) => {
+                let len = len as usize;
+                let byte_len = match rle {
+                    Rle::Rle8 => len,
+                    Rle::Rle4 => len.div_ceil(2),
+                };
+                let literal: Vec<u8> = iter.by_ref().take(byte_len + byte_len % 2).collect();
+                if literal.len() < byte_len {
+                    return Err(ResizedPngError::DecodingError(None));
+                }
+                let indices: Vec<u8> = match rle {
+                    Rle::Rle8 => literal[..len].to_vec(),
+                    Rle::Rle4 => (0..len).map(|i| nibble(literal[i / 2], i)).collect(),
+                };
+                for (i, index) in indices.into_iter().enumerate() {
+                    put(x + i, y, index)?;
+                }
+                x += len;
+            }
+            // 符号化モード。RLE4では、valueの上位と下位の4bitを交互に使う。
+            (count, value) => {
+                for i in 0..count as usize {
+                    let index = match rle {
+                        Rle::Rle8 => value,
+                        Rle::Rle4 => nibble(value, i),
+                    };
+                    put(x + i, y, index)?;
+                }
+                x += count as usize;
+            }
+        }
+
+        if y >= h {
+            break;
+        }
+    }
+
+    Ok(ImageData {
+        pixels: buf,
+        width,
+        height,
+        source_color: SourceColorType::Indexed,
+        pixel_dims: None,
+        icc_profile: None,
+        text_chunks: Vec::new(),
+    })
+}
+
+/// 4bitの番号を、偶数番目は上位、奇数番目は下位から取り出す
+fn nibble(byte: u8, i: usize) -> u8 {
+    if i.is_multiple_of(2) {
+        byte >> 4
+    } else {
+        byte & 0x0F
+    }
+}
+
+/// マスクの部分を取り出し、8bitに直す
+fn channel_value(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let max = mask >> mask.trailing_zeros();
+    let v = (value & mask) >> mask.trailing_zeros();
+
+    ((v as u64 * u8::MAX as u64 + max as u64 / 2) / max as u64) as u8
+}
+
+/// ファイルヘッダと情報ヘッダの先頭だけを読み、幅と高さを返す
+pub(crate) fn read_dimensions(path: &PathBuf) -> Result<(u32, u32), ResizedPngError> {
+    let mut head = Vec::new();
+    File::open(path)?.take(26).read_to_end(&mut head)?;
+
+    dimensions_from_header(&head).ok_or(ResizedPngError::DecodingError(None))
+}
+
+/// メモリ上のバイト列から、画素を読まずに幅と高さを返す
+pub(crate) fn read_dimensions_from_bytes(bytes: &[u8]) -> Result<(u32, u32), ResizedPngError> {
+    dimensions_from_header(bytes).ok_or(ResizedPngError::DecodingError(None))
+}
+
+fn dimensions_from_header(head: &[u8]) -> Option<(u32, u32)> {
+    let [b'B', b'M', _, _, _, _, _, _, _, _, _, _, _, _, rest @ ..] = head else {
+        return None;
+    };
+    let header_size = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?);
+    let size = &rest[4..];
+
+    let (width, height) = match header_size {
+        // OS/2のBITMAPCOREHEADERは16bit。
+        12 => (
+            u16::from_le_bytes(size.get(..2)?.try_into().ok()?) as i32,
+            u16::from_le_bytes(size.get(2..4)?.try_into().ok()?) as i32,
+        ),
+        _ => (
+            i32::from_le_bytes(size.get(..4)?.try_into().ok()?),
+            i32::from_le_bytes(size.get(4..8)?.try_into().ok()?),
+        ),
+    };
+
+    // 高さが負の場合は上の行から並んでいる。
+    match width {
+        w if w > 0 => Some((w as u32, height.unsigned_abs())),
+        _ => None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::path::Path;
+
+    /// テスト用のBMPを書き出す。rowsはファイル内の順で、パディングなしで渡す。
+    pub(super) fn write_bmp(
+        path: &Path,
+        width: i32,
+        height: i32,
+        bpp: u16,
+        palette: &[[u8; 4]],
+        rows: &[Vec<u8>],
+    ) {
+        let mut data = Vec::new();
+        for row in rows {
+            data.extend_from_slice(row);
+            data.resize(data.len() + (4 - row.len() % 4) % 4, 0);
+        }
+        let data_start = 14 + 40 + palette.len() as u32 * 4;
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"BM");
+        bytes.extend_from_slice(&(data_start + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&data_start.to_le_bytes());
+
+        bytes.extend_from_slice(&40u32.to_le_bytes());
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&bpp.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&2835u32.to_le_bytes());
+        bytes.extend_from_slice(&2835u32.to_le_bytes());
+        bytes.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        for entry in palette {
+            bytes.extend_from_slice(entry);
+        }
+        bytes.extend_from_slice(&data);
+
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    /// アルファのマスクを持つ32bitのBMPをV4の情報ヘッダで書き出す。rowsはBGRAの順で、下の行から
+    pub(super) fn write_bmp_with_alpha(path: &Path, width: i32, height: i32, rows: &[Vec<u8>]) {
+        let data = rows.concat();
+        let data_start = 14 + 108u32;
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"BM");
+        bytes.extend_from_slice(&(data_start + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&data_start.to_le_bytes());
+
+        bytes.extend_from_slice(&108u32.to_le_bytes());
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&32u16.to_le_bytes());
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&2835u32.to_le_bytes());
+        bytes.extend_from_slice(&2835u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        for mask in [0x00FF0000u32, 0x0000FF00, 0x000000FF, 0xFF000000] {
+            bytes.extend_from_slice(&mask.to_le_bytes());
+        }
+        // LCS_sRGB。エンドポイントとガンマは使われない。
+        bytes.extend_from_slice(b"BGRs");
+        bytes.extend_from_slice(&[0; 36 + 12]);
+
+        bytes.extend_from_slice(&data);
+
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    mod read_dimensions {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn checking_value_when_sample_bmp() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+
+            assert_eq!(read_dimensions(&path).unwrap(), (100, 200));
+        }
+
+        #[test]
+        fn checking_value_when_top_down_bmp() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("top_down.bmp");
+
+            write_bmp(&path, 2, -3, 24, &[], &[vec![0; 6], vec![0; 6], vec![0; 6]]);
+
+            assert_eq!(read_dimensions(&path).unwrap(), (2, 3));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_invalid_bmp_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert!(matches!(
+                read_dimensions(&path),
+                Err(ResizedPngError::DecodingError(_))
+            ));
+        }
+    }
+
     mod read_image_data {
         use super::*;
 
+        use tempfile::tempdir;
+
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        const GREEN: [u8; 4] = [0, 255, 0, 255];
+        const BLUE: [u8; 4] = [0, 0, 255, 255];
+        const WHITE: [u8; 4] = [255, 255, 255, 255];
+
         #[test]
         fn success_when_valid_bmp_path() {
             let path =
                 PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
 
-            let (_data, width, height) = read_image_data(&path).unwrap();
+            let ImageData {
+                width,
+                height,
+                source_color,
+                ..
+            } = read_image_data(&path).unwrap();
 
             assert_eq!(width, 100);
             assert_eq!(height, 200);
+            assert_eq!(source_color, SourceColorType::Rgb);
+        }
+
+        #[test]
+        fn checking_value_when_sample_bmp() {
+            let bmp_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+            let png_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            let ImageData {
+                pixels: bmp_data, ..
+            } = read_image_data(&bmp_path).unwrap();
+            let ImageData {
+                pixels: png_data, ..
+            } = crate::image::png::read_image_data(&png_path).unwrap();
+
+            // 上下が反転していないことを、先頭と末尾の行で確認する。
+            assert_eq!(bmp_data[..100 * 4], png_data[..100 * 4]);
+            assert_eq!(bmp_data[199 * 100 * 4..], png_data[199 * 100 * 4..]);
+        }
+
+        #[test]
+        fn checking_value_when_8bit_palette_bmp() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("palette.bmp");
+
+            // パレットはBGRの順。
+            let palette = [[0, 0, 255, 0], [0, 255, 0, 0], [255, 0, 0, 0]];
+            // 下の行から。
+            let rows = [vec![2, 1], vec![0, 2]];
+            write_bmp(&path, 2, 2, 8, &palette, &rows);
+
+            let ImageData {
+                pixels: data,
+                width,
+                height,
+                source_color,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 2);
+            assert_eq!(height, 2);
+            assert_eq!(data, [RED, BLUE, BLUE, GREEN].concat());
+            assert_eq!(source_color, SourceColorType::Indexed);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_32bit_bmp() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("32bit.bmp");
+
+            // BGRXの順、下の行から。
+            let rows = [
+                vec![255, 255, 255, 0, 0, 0, 255, 0],
+                vec![0, 255, 0, 0, 255, 0, 0, 0],
+            ];
+            write_bmp(&path, 2, 2, 32, &[], &rows);
+
+            let ImageData {
+                pixels: data,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 2);
+            assert_eq!(height, 2);
+            assert_eq!(data, [GREEN, BLUE, WHITE, RED].concat());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_top_down_bmp() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("top_down.bmp");
+
+            // 高さが負のときは上の行から。
+            let rows = [vec![0, 0, 255, 0, 255, 0], vec![255, 0, 0, 255, 255, 255]];
+            write_bmp(&path, 2, -2, 24, &[], &rows);
+
+            let ImageData {
+                pixels: data,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 2);
+            assert_eq!(height, 2);
+            assert_eq!(data, [RED, GREEN, BLUE, WHITE].concat());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_top_down_8bit_palette_bmp() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("top_down_palette.bmp");
+
+            // パレットはBGRの順。高さが負のときは上の行から。
+            let palette = [[0, 0, 255, 0], [0, 255, 0, 0], [255, 0, 0, 0]];
+            let rows = [vec![0, 0], vec![1, 1], vec![2, 2]];
+            write_bmp(&path, 2, -3, 8, &palette, &rows);
+
+            let ImageData {
+                pixels: data,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 2);
+            assert_eq!(height, 3);
+            assert_eq!(data, [RED, RED, GREEN, GREEN, BLUE, BLUE].concat());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_top_down_32bit_bmp_with_alpha() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("top_down_alpha.bmp");
+
+            // BGRAの順、上の行から。
+            let rows = [
+                vec![0, 0, 255, 255],
+                vec![0, 255, 0, 128],
+                vec![255, 0, 0, 64],
+            ];
+            write_bmp_with_alpha(&path, 1, -3, &rows);
+
+            let ImageData {
+                pixels: data,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 1);
+            assert_eq!(height, 3);
+            assert_eq!(
+                data,
+                [[255, 0, 0, 255], [0, 255, 0, 128], [0, 0, 255, 64]].concat()
+            );
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_data_is_shorter_than_image_size() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("mismatch.bmp");
+
+            write_bmp(&path, 2, 2, 32, &[], &[vec![0; 8], vec![0; 8]]);
+
+            // データを1行分だけにする。
+            let mut bytes = std::fs::read(&path).unwrap();
+            bytes.truncate(bytes.len() - 8);
+            std::fs::write(&path, bytes).unwrap();
+
+            // tinybmpが読み込みの時点で、データの終わりが足りないとして弾く。
+            assert!(matches!(
+                read_image_data(&path),
+                Err(ResizedPngError::Unsupported(_))
+            ));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn success_when_data_length_is_omitted() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("omitted.bmp");
+
+            write_bmp(&path, 2, 2, 32, &[], &[vec![0; 8], vec![0; 8]]);
+
+            // 圧縮しない画像では、データの長さを0にしてよい。
+            let mut bytes = std::fs::read(&path).unwrap();
+            bytes[34..38].copy_from_slice(&0u32.to_le_bytes());
+            std::fs::write(&path, bytes).unwrap();
+
+            let image = read_image_data(&path).unwrap();
+            assert_eq!(image.pixels.len(), 2 * 2 * 4);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn success_when_data_length_excludes_row_padding() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("unpadded.bmp");
+
+            write_bmp(&path, 3, 1, 24, &[], &[vec![0; 9]]);
+
+            // データの長さは目安なので、行の埋め草を含まない値でも、データが揃っていれば読める。
+            let mut bytes = std::fs::read(&path).unwrap();
+            bytes[34..38].copy_from_slice(&9u32.to_le_bytes());
+            std::fs::write(&path, bytes).unwrap();
+
+            let image = read_image_data(&path).unwrap();
+            assert_eq!((image.width, image.height), (3, 1));
+            assert_eq!(image.pixels.len(), 3 * 4);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_32bit_bmp_with_alpha() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("alpha.bmp");
+
+            // BGRAの順、下の行から。
+            let rows = [
+                vec![255, 0, 0, 0, 255, 255, 255, 64],
+                vec![0, 0, 255, 255, 0, 255, 0, 128],
+            ];
+            write_bmp_with_alpha(&path, 2, 2, &rows);
+
+            let ImageData {
+                pixels: data,
+                width,
+                height,
+                source_color,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 2);
+            assert_eq!(height, 2);
+            assert_eq!(
+                data,
+                [
+                    [255, 0, 0, 255],
+                    [0, 255, 0, 128],
+                    [0, 0, 255, 0],
+                    [255, 255, 255, 64]
+                ]
+                .concat()
+            );
+            assert_eq!(source_color, SourceColorType::Rgba);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_32bit_bmp_with_alpha_all_0() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("alpha_0.bmp");
+
+            let rows = [vec![0, 0, 255, 0, 0, 255, 0, 0]];
+            write_bmp_with_alpha(&path, 2, 1, &rows);
+
+            let ImageData { pixels: data, .. } = read_image_data(&path).unwrap();
+
+            assert_eq!(data, [RED, GREEN].concat());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_32bit_bmp_with_alpha_is_truncated() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("truncated.bmp");
+
+            write_bmp_with_alpha(&path, 2, 2, &[vec![0; 8]]);
+
+            assert!(matches!(
+                read_image_data(&path),
+                Err(ResizedPngError::DecodingError(_))
+            ));
+
+            out_dir.close().unwrap();
         }
 
         #[test]
@@ -57,4 +762,141 @@ mod tests {
             assert!(read_image_data(&path).is_err());
         }
     }
+
+    mod read_rle {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        const GREEN: [u8; 4] = [0, 255, 0, 255];
+        const BLUE: [u8; 4] = [0, 0, 255, 255];
+        const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+        /// 圧縮したデータをそのまま置き、情報ヘッダの圧縮形式を書き換えたBMPを読む
+        fn read_rle_bmp(
+            width: i32,
+            height: i32,
+            bpp: u16,
+            compression: u32,
+            data: &[u8],
+        ) -> ImageData {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("rle.bmp");
+
+            // パレットはBGRの順。
+            let palette = [[0, 0, 255, 0], [0, 255, 0, 0], [255, 0, 0, 0]];
+            write_bmp(&path, width, height, bpp, &palette, &[data.to_vec()]);
+            let mut bytes = std::fs::read(&path).unwrap();
+            bytes[30..34].copy_from_slice(&compression.to_le_bytes());
+            std::fs::write(&path, bytes).unwrap();
+
+            let image = read_image_data(&path).unwrap();
+            out_dir.close().unwrap();
+
+            image
+        }
+
+        #[test]
+        fn checking_value_when_rle8() {
+            // 下の行から。1行目は繰り返しと絶対モード、2行目は移動で2ピクセル飛ばす。
+            let data = [
+                2, 0, // 赤を2つ
+                0, 3, 1, 2, 1, 0, // 絶対モードで緑、青、緑と埋め草
+                0, 0, // 行の終わり
+                0, 2, 2, 0, // 2つ右へ
+                4, 2, // 青を4つ(1つは行からはみ出す)
+                0, 1, // 画像の終わり
+            ];
+
+            let image = read_rle_bmp(5, 3, 8, 1, &data);
+
+            assert_eq!((image.width, image.height), (5, 3));
+            assert_eq!(image.source_color, SourceColorType::Indexed);
+            assert_eq!(
+                image.pixels,
+                [
+                    [CLEAR; 5].concat(),
+                    [CLEAR, CLEAR, BLUE, BLUE, BLUE].concat(),
+                    [RED, RED, GREEN, BLUE, GREEN].concat(),
+                ]
+                .concat()
+            );
+        }
+
+        #[test]
+        fn checking_value_when_rle4() {
+            let data = [
+                3, 0x01, // 赤、緑、赤
+                0, 0, // 行の終わり
+                0, 3, 0x21, 0x00, // 絶対モードで青、緑、赤と埋め草
+                0, 1, // 画像の終わり
+            ];
+
+            let image = read_rle_bmp(3, 2, 4, 2, &data);
+
+            assert_eq!(
+                image.pixels,
+                [[BLUE, GREEN, RED].concat(), [RED, GREEN, RED].concat()].concat()
+            );
+        }
+
+        #[test]
+        fn failed_when_index_is_out_of_palette() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("rle.bmp");
+
+            write_bmp(&path, 2, 1, 8, &[[0, 0, 0, 0]], &[vec![2, 5, 0, 1]]);
+            let mut bytes = std::fs::read(&path).unwrap();
+            bytes[30..34].copy_from_slice(&1u32.to_le_bytes());
+            std::fs::write(&path, bytes).unwrap();
+
+            assert!(matches!(
+                read_image_data(&path),
+                Err(ResizedPngError::DecodingError(_))
+            ));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn limits_error_when_header_size_is_too_large() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("rle.bmp");
+
+            write_bmp(&path, 60000, 60000, 8, &[[0, 0, 0, 0]], &[vec![0, 1]]);
+            let mut bytes = std::fs::read(&path).unwrap();
+            bytes[30..34].copy_from_slice(&1u32.to_le_bytes());
+            std::fs::write(&path, bytes).unwrap();
+
+            assert!(matches!(
+                read_image_data(&path),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod channel_value {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_8bit_mask() {
+            assert_eq!(channel_value(0x80FF0000, 0xFF000000), 128);
+            assert_eq!(channel_value(0x80FF0000, 0x00FF0000), 255);
+        }
+
+        #[test]
+        fn checking_value_when_narrow_mask() {
+            // 5bitの最大値は255になる。
+            assert_eq!(channel_value(0x1F, 0x1F), 255);
+            assert_eq!(channel_value(0x10, 0x1F), 132);
+        }
+
+        #[test]
+        fn checking_value_when_mask_is_0() {
+            assert_eq!(channel_value(0xFFFFFFFF, 0), 0);
+        }
+    }
 }
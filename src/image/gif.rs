@@ -3,42 +3,553 @@ use std::path::PathBuf;
 
 use crate::error::ResizedPngError;
 
-use super::ImageData;
+use super::{rgba8_len, DecodeLimits, ImageData, SourceColorType};
 
 pub(crate) fn read_image_data(path: &PathBuf) -> Result<ImageData, ResizedPngError> {
+    read_frame(path, 0)
+}
+
+/// メモリ上のバイト列から読み込む
+pub(crate) fn read_image_data_from_bytes(bytes: &[u8]) -> Result<ImageData, ResizedPngError> {
+    read_frame_from_bytes(bytes, 0)
+}
+
+/// index番目(0から)のフレームまでを重ねた画像を読み込む
+pub(crate) fn read_frame(path: &PathBuf, index: usize) -> Result<ImageData, ResizedPngError> {
+    let bytes = std::fs::read(path)?;
+    read_frame_from_bytes(&bytes, index)
+}
+
+/// メモリ上のバイト列から、index番目(0から)のフレームまでを重ねた画像を読み込む
+///
+/// フレームが足りない場合は、最後のフレームまでを重ねた画像になる。
+pub(crate) fn read_frame_from_bytes(
+    bytes: &[u8],
+    index: usize,
+) -> Result<ImageData, ResizedPngError> {
+    let (buf, width, height) = composite_frames(bytes, index.saturating_add(1), |_, _| Ok(()))?;
+
+    Ok(canvas_image(buf, width, height))
+}
+
+/// メモリ上のバイト列から、全てのフレームをそれぞれそこまで重ねた画像と、その表示時間(1/100秒)を読み込む
+///
+/// 全てのフレームを持っておくので、フレームの画素数の合計がlimitsの`max_pixels`を越える場合はLimitsErrorにする。
+pub(crate) fn read_frames_from_bytes(
+    bytes: &[u8],
+    limits: &DecodeLimits,
+) -> Result<Vec<(ImageData, u16)>, ResizedPngError> {
+    let mut frames = Vec::new();
+    let mut total_pixels: u64 = 0;
+    let (_, width, height) = composite_frames(bytes, usize::MAX, |canvas, frame| {
+        total_pixels += canvas.len() as u64 / 4;
+        if total_pixels > limits.max_pixels {
+            return Err(ResizedPngError::LimitsError(None));
+        }
+
+        frames.push((canvas.to_vec(), frame.delay));
+        Ok(())
+    })?;
+
+    Ok(frames
+        .into_iter()
+        .map(|(buf, delay)| (canvas_image(buf, width, height), delay))
+        .collect())
+}
+
+/// 最初からcount枚までのフレームを順に重ね、重ねるたびにその時のcanvasとフレームをon_frameに渡す
+///
+/// on_frameがエラーを返した場合は、そこで止めてそのエラーを返す。
+/// 最後のcanvasと論理画面の幅と高さを返す。フレームが1枚もなければDecodingError。
+fn composite_frames(
+    bytes: &[u8],
+    count: usize,
+    mut on_frame: impl FnMut(&[u8], &gif::Frame) -> Result<(), ResizedPngError>,
+) -> Result<(Vec<u8>, u32, u32), ResizedPngError> {
     let mut decode_options = gif::DecodeOptions::new();
     decode_options.set_color_output(gif::ColorOutput::RGBA);
 
+    let mut decoder = decode_options.read_info(bytes)?;
+
+    let width = decoder.width() as u32;
+    let height = decoder.height() as u32;
+
+    // 論理画面の大きさはフレームと関係なく書けるので、確保する前に確かめる。
+    let mut buf = vec![0; rgba8_len(width, height)?];
+    let mut has_frame = false;
+    // 前のフレームの処分方法と範囲、Previousのときは描く前のcanvas。
+    let mut pending: Option<(gif::DisposalMethod, FrameRect, Option<Vec<u8>>)> = None;
+    for _ in 0..count {
+        let Some(frame) = decoder.read_next_frame()? else {
+            break;
+        };
+
+        if let Some((dispose, rect, saved)) = pending.take() {
+            dispose_frame(&mut buf, width, height, dispose, rect, saved);
+        }
+
+        let saved = (frame.dispose == gif::DisposalMethod::Previous).then(|| buf.clone());
+        pending = Some((
+            frame.dispose,
+            (frame.left, frame.top, frame.width, frame.height),
+            saved,
+        ));
+
+        composite_frame(&mut buf, width, height, frame);
+        on_frame(&buf, frame)?;
+        has_frame = true;
+    }
+
+    if !has_frame {
+        return Err(ResizedPngError::DecodingError(None));
+    }
+
+    Ok((buf, width, height))
+}
+
+fn canvas_image(pixels: Vec<u8>, width: u32, height: u32) -> ImageData {
+    ImageData {
+        pixels,
+        width,
+        height,
+        source_color: SourceColorType::Indexed,
+        pixel_dims: None,
+        icc_profile: None,
+        text_chunks: Vec::new(),
+    }
+}
+
+/// 画素を読まずに、論理画面の幅と高さを返す
+pub(crate) fn read_dimensions(path: &PathBuf) -> Result<(u32, u32), ResizedPngError> {
+    let fs = File::open(path)?;
+    let decoder = gif::DecodeOptions::new().read_info(fs)?;
+
+    Ok((decoder.width() as u32, decoder.height() as u32))
+}
+
+/// メモリ上のバイト列から、画素を読まずに幅と高さを返す
+pub(crate) fn read_dimensions_from_bytes(bytes: &[u8]) -> Result<(u32, u32), ResizedPngError> {
+    let decoder = gif::DecodeOptions::new().read_info(bytes)?;
+
+    Ok((decoder.width() as u32, decoder.height() as u32))
+}
+
+/// フレームの数を返す
+pub(crate) fn frame_count(path: &PathBuf) -> Result<u32, ResizedPngError> {
+    let mut decode_options = gif::DecodeOptions::new();
+    // 数えるだけなので、LZWの展開はしない。
+    decode_options.skip_frame_decoding(true);
+
     let fs = File::open(path)?;
     let mut decoder = decode_options.read_info(fs)?;
 
-    let frame = decoder
-        .read_next_frame()?
-        .ok_or(ResizedPngError::DecodingError)?;
+    let mut count = 0;
+    while decoder.read_next_frame()?.is_some() {
+        count += 1;
+    }
 
-    let buf = frame.buffer.to_vec();
-    let width = frame.width as u32;
-    let height = frame.height as u32;
+    Ok(count)
+}
 
-    Ok((buf, width, height))
+/// フレームの左上の位置と幅と高さ
+type FrameRect = (u16, u16, u16, u16);
+
+/// 次のフレームを描く前に、前のフレームの処分方法に従ってcanvasを戻す
+fn dispose_frame(
+    canvas: &mut [u8],
+    canvas_width: u32,
+    canvas_height: u32,
+    dispose: gif::DisposalMethod,
+    (left, top, width, height): FrameRect,
+    saved: Option<Vec<u8>>,
+) {
+    match dispose {
+        gif::DisposalMethod::Any | gif::DisposalMethod::Keep => {}
+        // 背景色ではなく透明にする。多くのブラウザと同じ扱い。
+        gif::DisposalMethod::Background => {
+            let canvas_width = canvas_width as usize;
+            let right = (left as usize + width as usize).min(canvas_width);
+            let bottom = (top as usize + height as usize).min(canvas_height as usize);
+
+            for y in top as usize..bottom {
+                for x in (left as usize)..right {
+                    let index = (y * canvas_width + x) * 4;
+                    canvas[index..index + 4].fill(0);
+                }
+            }
+        }
+        gif::DisposalMethod::Previous => {
+            if let Some(saved) = saved {
+                canvas.copy_from_slice(&saved);
+            }
+        }
+    }
+}
+
+/// 論理画面サイズのcanvasにframeを描画する
+fn composite_frame(canvas: &mut [u8], canvas_width: u32, canvas_height: u32, frame: &gif::Frame) {
+    let canvas_width = canvas_width as usize;
+    let canvas_height = canvas_height as usize;
+    let left = frame.left as usize;
+    let top = frame.top as usize;
+    let frame_width = frame.width as usize;
+
+    for (y, line) in frame.buffer.chunks(frame_width * 4).enumerate() {
+        let canvas_y = top + y;
+        if canvas_y >= canvas_height {
+            break;
+        }
+
+        for (x, pixel) in line.chunks_exact(4).enumerate() {
+            let canvas_x = left + x;
+            if canvas_x >= canvas_width {
+                break;
+            }
+
+            // 透明なピクセルは下の内容を残す。
+            if pixel[3] == 0 {
+                continue;
+            }
+
+            let index = (canvas_y * canvas_width + canvas_x) * 4;
+            canvas[index..index + 4].copy_from_slice(pixel);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    mod read_dimensions {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_sample_gif() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.gif");
+
+            assert_eq!(read_dimensions(&path).unwrap(), (100, 200));
+        }
+
+        #[test]
+        fn failed_when_invalid_gif_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+
+            assert!(read_dimensions(&path).is_err());
+        }
+    }
+
+    mod frame_count {
+        use super::*;
+
+        use std::borrow::Cow;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn checking_value_when_static_gif() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.gif");
+
+            assert_eq!(frame_count(&path).unwrap(), 1);
+        }
+
+        #[test]
+        fn checking_value_when_animated_gif() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("animated.gif");
+
+            {
+                let fs = File::create(&path).unwrap();
+                let palette = [0, 0, 0, 255, 0, 0];
+                let mut encoder = gif::Encoder::new(fs, 2, 2, &palette).unwrap();
+                for index in 0..3 {
+                    let frame = gif::Frame {
+                        width: 2,
+                        height: 2,
+                        buffer: Cow::Owned(vec![index % 2; 4]),
+                        ..Default::default()
+                    };
+                    encoder.write_frame(&frame).unwrap();
+                }
+            }
+
+            assert_eq!(frame_count(&path).unwrap(), 3);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_invalid_gif_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert!(frame_count(&path).is_err());
+        }
+    }
+
+    mod read_frame {
+        use super::*;
+
+        use std::borrow::Cow;
+
+        use tempfile::tempdir;
+
+        /// 赤、緑、青の2x2のフレームが順に並ぶGIFを書き出す
+        fn write_rgb_frames(path: &std::path::Path) {
+            let fs = File::create(path).unwrap();
+            let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+            let mut encoder = gif::Encoder::new(fs, 2, 2, &palette).unwrap();
+            for index in 0..3 {
+                let frame = gif::Frame {
+                    width: 2,
+                    height: 2,
+                    buffer: Cow::Owned(vec![index; 4]),
+                    ..Default::default()
+                };
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+
+        #[test]
+        fn checking_value_when_selecting_frame() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("frames.gif");
+
+            write_rgb_frames(&path);
+
+            let first = read_frame(&path, 0).unwrap();
+            let third = read_frame(&path, 2).unwrap();
+
+            assert_eq!(first.pixels, [255, 0, 0, 255].repeat(4));
+            assert_eq!(third.pixels, [0, 0, 255, 255].repeat(4));
+            assert_ne!(first.pixels, third.pixels);
+
+            out_dir.close().unwrap();
+        }
+
+        /// (left, top, width, height, パレットの番号, 処分方法)のフレームを4x3のGIFに書き出す
+        fn write_disposal_frames(
+            path: &std::path::Path,
+            frames: &[(u16, u16, u16, u16, u8, gif::DisposalMethod)],
+        ) {
+            let fs = File::create(path).unwrap();
+            let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+            let mut encoder = gif::Encoder::new(fs, 4, 3, &palette).unwrap();
+            for &(left, top, width, height, index, dispose) in frames {
+                let frame = gif::Frame {
+                    left,
+                    top,
+                    width,
+                    height,
+                    dispose,
+                    buffer: Cow::Owned(vec![index; width as usize * height as usize]),
+                    ..Default::default()
+                };
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+
+        #[test]
+        fn transparent_when_previous_frame_is_restored_to_background() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("background.gif");
+
+            write_disposal_frames(
+                &path,
+                &[
+                    (0, 0, 3, 3, 0, gif::DisposalMethod::Background),
+                    (3, 2, 1, 1, 1, gif::DisposalMethod::Keep),
+                ],
+            );
+
+            let ImageData { pixels, .. } = read_frame(&path, 1).unwrap();
+
+            // 最初のフレームの範囲は透明に戻り、(3, 2)だけが緑。
+            let mut expected = [0, 0, 0, 0].repeat(12);
+            expected[(2 * 4 + 3) * 4..].copy_from_slice(&[0, 255, 0, 255]);
+            assert_eq!(pixels, expected);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_previous_frame_is_restored_to_previous() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("previous.gif");
+
+            write_disposal_frames(
+                &path,
+                &[
+                    (0, 0, 4, 3, 0, gif::DisposalMethod::Keep),
+                    (0, 0, 2, 2, 1, gif::DisposalMethod::Previous),
+                    (3, 2, 1, 1, 2, gif::DisposalMethod::Keep),
+                ],
+            );
+
+            let ImageData { pixels, .. } = read_frame(&path, 2).unwrap();
+
+            // 2番目のフレームの緑は消え、最初のフレームの赤に戻る。
+            let mut expected = [255, 0, 0, 255].repeat(12);
+            expected[(2 * 4 + 3) * 4..].copy_from_slice(&[0, 0, 255, 255]);
+            assert_eq!(pixels, expected);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_previous_frame_is_kept() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("keep.gif");
+
+            write_disposal_frames(
+                &path,
+                &[
+                    (0, 0, 4, 3, 0, gif::DisposalMethod::Keep),
+                    (3, 2, 1, 1, 1, gif::DisposalMethod::Keep),
+                ],
+            );
+
+            let ImageData { pixels, .. } = read_frame(&path, 1).unwrap();
+
+            let mut expected = [255, 0, 0, 255].repeat(12);
+            expected[(2 * 4 + 3) * 4..].copy_from_slice(&[0, 255, 0, 255]);
+            assert_eq!(pixels, expected);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn last_frame_when_index_is_out_of_range() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("frames.gif");
+
+            write_rgb_frames(&path);
+
+            let ImageData { pixels, .. } = read_frame(&path, 10).unwrap();
+
+            assert_eq!(pixels, [0, 0, 255, 255].repeat(4));
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod read_frames_from_bytes {
+        use super::*;
+
+        use std::borrow::Cow;
+
+        /// 2x2のフレームが3枚並ぶGIF
+        fn three_frames() -> Vec<u8> {
+            let mut bytes = Vec::new();
+            {
+                let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+                let mut encoder = gif::Encoder::new(&mut bytes, 2, 2, &palette).unwrap();
+                for index in 0..3 {
+                    let frame = gif::Frame {
+                        width: 2,
+                        height: 2,
+                        buffer: Cow::Owned(vec![index; 4]),
+                        ..Default::default()
+                    };
+                    encoder.write_frame(&frame).unwrap();
+                }
+            }
+
+            bytes
+        }
+
+        #[test]
+        fn checking_value_when_within_limits() {
+            let limits = DecodeLimits {
+                max_pixels: 2 * 2 * 3,
+                ..Default::default()
+            };
+
+            let frames = read_frames_from_bytes(&three_frames(), &limits).unwrap();
+
+            assert_eq!(frames.len(), 3);
+            assert_eq!(frames[2].0.pixels, [0, 0, 255, 255].repeat(4));
+        }
+
+        #[test]
+        fn limits_error_when_total_pixels_exceed_limits() {
+            let limits = DecodeLimits {
+                max_pixels: 2 * 2 * 3 - 1,
+                ..Default::default()
+            };
+
+            assert!(matches!(
+                read_frames_from_bytes(&three_frames(), &limits),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+        }
+    }
+
     mod read_image_data {
         use super::*;
 
+        use std::borrow::Cow;
+
+        use tempfile::tempdir;
+
         #[test]
         fn success_when_valid_gif_path() {
             let path =
                 PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.gif");
 
-            let (_data, width, height) = read_image_data(&path).unwrap();
+            let ImageData {
+                width,
+                height,
+                source_color,
+                ..
+            } = read_image_data(&path).unwrap();
 
             assert_eq!(width, 100);
             assert_eq!(height, 200);
+            assert_eq!(source_color, SourceColorType::Indexed);
+        }
+
+        #[test]
+        fn checking_value_when_first_frame_is_smaller_than_screen() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("small_frame.gif");
+
+            {
+                let fs = File::create(&path).unwrap();
+                let palette = [0, 0, 0, 255, 0, 0];
+                let mut encoder = gif::Encoder::new(fs, 4, 3, &palette).unwrap();
+                let frame = gif::Frame {
+                    left: 1,
+                    top: 1,
+                    width: 2,
+                    height: 2,
+                    buffer: Cow::Borrowed(&[1, 1, 1, 1]),
+                    ..Default::default()
+                };
+                encoder.write_frame(&frame).unwrap();
+            }
+
+            let ImageData {
+                pixels: data,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 4);
+            assert_eq!(height, 3);
+            assert_eq!(data.len(), 4 * 3 * 4);
+            // 左上は覆われていないので透明。
+            assert_eq!(data[..4], [0, 0, 0, 0]);
+            // (1, 1)は赤。
+            assert_eq!(data[(4 + 1) * 4..(4 + 2) * 4], [255, 0, 0, 255]);
+            // (3, 2)は覆われていないので透明。
+            assert_eq!(data[(2 * 4 + 3) * 4..], [0, 0, 0, 0]);
+
+            out_dir.close().unwrap();
         }
 
         #[test]
@@ -49,4 +560,31 @@ mod tests {
             assert!(read_image_data(&path).is_err());
         }
     }
+
+    mod read_image_data_from_bytes {
+        use super::*;
+
+        use std::borrow::Cow;
+
+        #[test]
+        fn limits_error_when_screen_is_too_large() {
+            let mut bytes = Vec::new();
+            {
+                let palette = [0, 0, 0, 255, 0, 0];
+                let mut encoder = gif::Encoder::new(&mut bytes, 65535, 65535, &palette).unwrap();
+                let frame = gif::Frame {
+                    width: 1,
+                    height: 1,
+                    buffer: Cow::Borrowed(&[1]),
+                    ..Default::default()
+                };
+                encoder.write_frame(&frame).unwrap();
+            }
+
+            assert!(matches!(
+                read_image_data_from_bytes(&bytes),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+        }
+    }
 }
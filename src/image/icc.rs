@@ -0,0 +1,138 @@
+//! ICCプロファイルを使った色の変換
+//!
+//! `icc` featureを有効にしたときだけ組み込まれる。
+//! 変換にCのライブラリ(Little CMS)を使うので、既定のビルドには含めない。
+
+use lcms2::{Intent, PixelFormat, Profile, Transform};
+
+use crate::error::ResizedPngError;
+
+/// インク量で表されたCMYKの並びを、プロファイルに従ってsRGBのRGBAの並びにする
+pub(crate) fn cmyk_to_rgba(cmyk: &[u8], icc_profile: &[u8]) -> Result<Vec<u8>, ResizedPngError> {
+    let input = Profile::new_icc(icc_profile)?;
+    let transform: Transform<[u8; 4], [u8; 3]> = Transform::new(
+        &input,
+        PixelFormat::CMYK_8,
+        &Profile::new_srgb(),
+        PixelFormat::RGB_8,
+        Intent::Perceptual,
+    )?;
+
+    let src: Vec<[u8; 4]> = cmyk
+        .chunks_exact(4)
+        .map(|v| [v[0], v[1], v[2], v[3]])
+        .collect();
+    let mut dist = vec![[0; 3]; src.len()];
+    transform.transform_pixels(&src, &mut dist);
+
+    Ok(dist
+        .into_iter()
+        .flat_map(|[r, g, b]| [r, g, b, u8::MAX])
+        .collect())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// CMYKからLabへの、格子点が2つだけのA2B0を持つICCプロファイル
+    ///
+    /// 素朴な変換よりくすんだ、印刷に近い色になる。
+    pub(crate) fn cmyk_test_profile() -> Vec<u8> {
+        let s15_fixed16 = |v: f64| ((v * 65536.0).round() as i32).to_be_bytes();
+        let d50 = [0.9642, 1.0, 0.8249];
+
+        // lut8Type。入力と出力の変換表は素通しにする。
+        let mut lut = b"mft1\0\0\0\0".to_vec();
+        lut.extend_from_slice(&[4, 3, 2, 0]);
+        for i in 0..9 {
+            lut.extend_from_slice(&s15_fixed16(if i % 4 == 0 { 1.0 } else { 0.0 }));
+        }
+        for _ in 0..4 {
+            lut.extend(0..=u8::MAX);
+        }
+        // 格子点はCが最も遅く変わる順に並べる。
+        for i in 0..16 {
+            let [c, m, y, k] = [i >> 3 & 1, i >> 2 & 1, i >> 1 & 1, i & 1].map(|v| v as f64);
+            let l =
+                100.0 * (1.0 - 0.45 * c) * (1.0 - 0.35 * m) * (1.0 - 0.08 * y) * (1.0 - 0.85 * k);
+            let a = -38.0 * c + 58.0 * m - 4.0 * y;
+            let b = -48.0 * c - 4.0 * m + 78.0 * y;
+
+            lut.extend_from_slice(&[
+                (l * 255.0 / 100.0).round() as u8,
+                (a + 128.0) as u8,
+                (b + 128.0) as u8,
+            ]);
+        }
+        for _ in 0..3 {
+            lut.extend(0..=u8::MAX);
+        }
+
+        let mut wtpt = b"XYZ \0\0\0\0".to_vec();
+        for v in d50 {
+            wtpt.extend_from_slice(&s15_fixed16(v));
+        }
+
+        let tags: [(&[u8; 4], &[u8]); 2] = [(b"A2B0", &lut), (b"wtpt", &wtpt)];
+        let table_len = 4 + 12 * tags.len();
+        let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+        let mut body = Vec::new();
+        for (signature, data) in tags {
+            let offset = 128 + table_len + body.len();
+            table.extend_from_slice(signature);
+            table.extend_from_slice(&(offset as u32).to_be_bytes());
+            table.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            body.extend_from_slice(data);
+            // タグは4バイトの境界に置く。
+            while body.len() % 4 != 0 {
+                body.push(0);
+            }
+        }
+
+        let mut header = ((128 + table_len + body.len()) as u32)
+            .to_be_bytes()
+            .to_vec();
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&[2, 0x10, 0, 0]);
+        header.extend_from_slice(b"prtrCMYKLab ");
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(b"acsp");
+        // プラットフォームからレンダリングインテントまで。
+        header.extend_from_slice(&[0; 28]);
+        for v in d50 {
+            header.extend_from_slice(&s15_fixed16(v));
+        }
+        header.resize(128, 0);
+
+        [header, table, body].concat()
+    }
+
+    mod cmyk_to_rgba {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_no_ink() {
+            let rgba = cmyk_to_rgba(&[0, 0, 0, 0], &cmyk_test_profile()).unwrap();
+
+            for v in &rgba[..3] {
+                assert!(*v >= 250, "{:?}", rgba);
+            }
+            assert_eq!(rgba[3], u8::MAX);
+        }
+
+        #[test]
+        fn checking_value_when_full_cyan() {
+            let rgba = cmyk_to_rgba(&[255, 0, 0, 0], &cmyk_test_profile()).unwrap();
+
+            // 素朴な変換では(0, 255, 255)になる。
+            assert!(rgba[1] < 200, "{:?}", rgba);
+            assert!(rgba[0] < rgba[1] && rgba[1] < rgba[2], "{:?}", rgba);
+        }
+
+        #[test]
+        fn failed_when_invalid_profile() {
+            assert!(cmyk_to_rgba(&[0, 0, 0, 0], b"not a profile").is_err());
+        }
+    }
+}
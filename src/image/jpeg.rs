@@ -1,36 +1,195 @@
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{prelude::Read, BufReader, BufWriter};
 use std::path::PathBuf;
 
-use jpeg_decoder::{Decoder, PixelFormat};
+use jpeg_decoder::{ColorTransform, Decoder, PixelFormat};
+use jpeg_encoder::{ColorType, Encoder};
 
 use crate::error::ResizedPngError;
 
-use super::ImageData;
+use super::{cmyk_to_rgb_value, u16_to_u8, ImageData, SourceColorType};
 
 pub(crate) fn read_image_data(path: &PathBuf) -> Result<ImageData, ResizedPngError> {
+    read_image_data_scaled(path, |_, _| None).map(|(image, _)| image)
+}
+
+/// メモリ上のバイト列から読み込む
+pub(crate) fn read_image_data_from_bytes(bytes: &[u8]) -> Result<ImageData, ResizedPngError> {
+    read_image_data_scaled_from_bytes(bytes, |_, _| None).map(|(image, _)| image)
+}
+
+/// min_sizeが返す大きさを下回らない範囲で、DCTの段階で縮小しながら読み込む
+///
+/// min_sizeには向きを補正した後の元の大きさが渡される。縮小する前の大きさも返す。
+pub(crate) fn read_image_data_scaled<F>(
+    path: &PathBuf,
+    min_size: F,
+) -> Result<(ImageData, (u32, u32)), ResizedPngError>
+where
+    F: FnOnce(u32, u32) -> Option<(u32, u32)>,
+{
+    let mut fs = File::open(path)?;
+    let mut bytes = Vec::new();
+    fs.read_to_end(&mut bytes)?;
+
+    read_image_data_scaled_from_bytes(&bytes, min_size)
+}
+
+/// メモリ上のバイト列から、read_image_data_scaledと同じように読み込む
+pub(crate) fn read_image_data_scaled_from_bytes<F>(
+    bytes: &[u8],
+    min_size: F,
+) -> Result<(ImageData, (u32, u32)), ResizedPngError>
+where
+    F: FnOnce(u32, u32) -> Option<(u32, u32)>,
+{
+    // 途中で切れているファイルは、終わりのマーカーを補って読めるところまで読む。
+    // 足りない部分は灰色になる。
+    let bytes = if bytes.ends_with(&[0xFF, 0xD9]) {
+        Cow::Borrowed(bytes)
+    } else {
+        Cow::Owned([bytes, &[0xFF, 0xD9]].concat())
+    };
+
+    let mut decoder = Decoder::new(bytes.as_ref());
+    decoder.read_info().map_err(decoding_error)?;
+    let header = decoder.info().ok_or(ResizedPngError::DecodingError(None))?;
+    let adobe_transform = read_adobe_transform(&bytes);
+
+    // transformが1の4チャンネルは、jpeg_decoderではエラーになるので、YCCKとして読む。
+    if header.pixel_format == PixelFormat::CMYK32 && adobe_transform == Some(1) {
+        decoder.set_color_transform(ColorTransform::YCCK);
+    }
+
+    let orientation = decoder
+        .exif_data()
+        .and_then(read_exif_orientation)
+        .unwrap_or(1);
+
+    let original_size = oriented_size(header.width as u32, header.height as u32, orientation);
+
+    if let Some((min_width, min_height)) = min_size(original_size.0, original_size.1) {
+        let (min_width, min_height) = oriented_size(min_width, min_height, orientation);
+        let (width, height) = scaled_size(
+            header.width as u32,
+            header.height as u32,
+            min_width,
+            min_height,
+        );
+        decoder.scale(width as u16, height as u16)?;
+    }
+
+    let raw_pixels = decoder.decode().map_err(decoding_error)?;
+    let metadata = decoder.info().ok_or(ResizedPngError::DecodingError(None))?;
+
+    #[cfg(feature = "icc")]
+    let buf = to_rgb_with_profile(
+        &raw_pixels,
+        &metadata.pixel_format,
+        adobe_transform,
+        decoder.icc_profile(),
+    )?;
+    #[cfg(not(feature = "icc"))]
+    let buf = to_rgb(&raw_pixels, &metadata.pixel_format, adobe_transform)?;
+    let (buf, width, height) = apply_orientation(
+        buf,
+        metadata.width as u32,
+        metadata.height as u32,
+        orientation,
+    );
+
+    let source_color = match metadata.pixel_format {
+        PixelFormat::L8 | PixelFormat::L16 => SourceColorType::Grayscale,
+        PixelFormat::RGB24 | PixelFormat::CMYK32 => SourceColorType::Rgb,
+    };
+
+    let image = ImageData {
+        pixels: buf,
+        width,
+        height,
+        source_color,
+        pixel_dims: None,
+        icc_profile: None,
+        text_chunks: Vec::new(),
+    };
+
+    Ok((image, original_size))
+}
+
+/// 画素を読まずに、向きを補正した後の幅と高さを返す
+pub(crate) fn read_dimensions(path: &PathBuf) -> Result<(u32, u32), ResizedPngError> {
     let fs = File::open(path)?;
-    let mut decoder = Decoder::new(BufReader::new(fs));
-    let raw_pixels = decoder.decode()?;
-    let metadata = decoder.info().expect("decoding already finished");
+    dimensions_from_reader(BufReader::new(fs))
+}
+
+/// メモリ上のバイト列から、画素を読まずに向きを補正した後の幅と高さを返す
+pub(crate) fn read_dimensions_from_bytes(bytes: &[u8]) -> Result<(u32, u32), ResizedPngError> {
+    dimensions_from_reader(bytes)
+}
+
+fn dimensions_from_reader<R: Read>(r: R) -> Result<(u32, u32), ResizedPngError> {
+    let mut decoder = Decoder::new(r);
+    decoder.read_info().map_err(decoding_error)?;
+    let header = decoder.info().ok_or(ResizedPngError::DecodingError(None))?;
+
+    let orientation = decoder
+        .exif_data()
+        .and_then(read_exif_orientation)
+        .unwrap_or(1);
+
+    Ok(oriented_size(
+        header.width as u32,
+        header.height as u32,
+        orientation,
+    ))
+}
+
+/// 向きを補正した後の幅と高さ。5から8は縦横が入れ替わる
+fn oriented_size(width: u32, height: u32, orientation: u16) -> (u32, u32) {
+    match orientation {
+        5..=8 => (height, width),
+        _ => (width, height),
+    }
+}
+
+/// メモリ上のデータを読んでいるので、読み込みのエラーはデータが足りないことを表す
+fn decoding_error(e: jpeg_decoder::Error) -> ResizedPngError {
+    match e {
+        jpeg_decoder::Error::Io(e) => ResizedPngError::DecodingError(Some(e.into())),
+        e => e.into(),
+    }
+}
+
+/// 1/8、1/4、1/2のうち、両方の辺が最小の大きさ以上になる最も小さい縮小後の大きさ
+fn scaled_size(width: u32, height: u32, min_width: u32, min_height: u32) -> (u32, u32) {
+    for denominator in [8, 4, 2] {
+        let scaled_width = width.div_ceil(denominator);
+        let scaled_height = height.div_ceil(denominator);
 
-    let buf = to_rgb(&raw_pixels, &metadata.pixel_format)?;
-    let width = metadata.width as u32;
-    let height = metadata.height as u32;
+        if scaled_width >= min_width && scaled_height >= min_height {
+            return (scaled_width, scaled_height);
+        }
+    }
 
-    Ok((buf, width, height))
+    (width, height)
 }
 
-fn to_rgb(raw_pixels: &[u8], pixel_format: &PixelFormat) -> Result<Vec<u8>, ResizedPngError> {
+fn to_rgb(
+    raw_pixels: &[u8],
+    pixel_format: &PixelFormat,
+    adobe_transform: Option<u8>,
+) -> Result<Vec<u8>, ResizedPngError> {
     match pixel_format {
         PixelFormat::L8 => Ok(raw_pixels
             .iter()
             .flat_map(|v| [*v, *v, *v, u8::MAX])
             .collect()),
+        // jpeg_decoderはL16をネイティブエンディアンで返す。
         PixelFormat::L16 => Ok(raw_pixels
-            .iter()
-            .step_by(2)
-            .flat_map(|v| [*v, *v, *v, u8::MAX])
+            .chunks_exact(2)
+            .map(|v| u16_to_u8(u16::from_ne_bytes([v[0], v[1]])))
+            .flat_map(|v| [v, v, v, u8::MAX])
             .collect()),
         PixelFormat::RGB24 => {
             let mut pixels = Vec::new();
@@ -45,26 +204,371 @@ fn to_rgb(raw_pixels: &[u8], pixel_format: &PixelFormat) -> Result<Vec<u8>, Resi
 
             Ok(pixels)
         }
-        PixelFormat::CMYK32 => Err(ResizedPngError::Unsupported),
+        PixelFormat::CMYK32 => Ok(to_ink(raw_pixels, adobe_transform)
+            .chunks_exact(4)
+            .flat_map(|v| {
+                [
+                    cmyk_to_rgb_value(v[0], v[3]),
+                    cmyk_to_rgb_value(v[1], v[3]),
+                    cmyk_to_rgb_value(v[2], v[3]),
+                    u8::MAX,
+                ]
+            })
+            .collect()),
+    }
+}
+
+/// ICCプロファイルが埋め込まれたCMYKは、プロファイルに従ってRGBAにする
+///
+/// それ以外と、壊れたプロファイルやCMYKでないプロファイルで変換できない場合は、to_rgbと同じく素朴に変換する。
+#[cfg(feature = "icc")]
+fn to_rgb_with_profile(
+    raw_pixels: &[u8],
+    pixel_format: &PixelFormat,
+    adobe_transform: Option<u8>,
+    icc_profile: Option<Vec<u8>>,
+) -> Result<Vec<u8>, ResizedPngError> {
+    match icc_profile {
+        Some(profile) if *pixel_format == PixelFormat::CMYK32 => {
+            super::icc::cmyk_to_rgba(&to_ink(raw_pixels, adobe_transform), &profile)
+                .or_else(|_| to_rgb(raw_pixels, pixel_format, adobe_transform))
+        }
+        _ => to_rgb(raw_pixels, pixel_format, adobe_transform),
+    }
+}
+
+/// jpeg_decoderが返したCMYKの並びを、APP14(Adobe)のtransformに従ってインク量の並びにする
+fn to_ink(raw_pixels: &[u8], adobe_transform: Option<u8>) -> Vec<u8> {
+    // jpeg_decoderは、CMYKは保存された値を反転して、
+    // YCCKはYCbCrから戻したCMYと反転したKを返す。
+    let inverted = match adobe_transform {
+        // Adobe製のCMYKは反転して保存されているので、返された値がそのままインク量になる。
+        Some(0) => false,
+        // YCCKはCMYをインク量のままYCbCrにして、Kを反転して保存している。
+        Some(1) | Some(2) => false,
+        // APP14がなければ、インク量がそのまま保存されている。
+        _ => true,
+    };
+
+    if inverted {
+        raw_pixels.iter().map(|v| u8::MAX - v).collect()
+    } else {
+        raw_pixels.to_vec()
+    }
+}
+
+/// APP14(Adobe)セグメントのtransformの値を読み取る
+fn read_adobe_transform(bytes: &[u8]) -> Option<u8> {
+    let mut rest = bytes.strip_prefix(&[0xFF, 0xD8])?;
+
+    loop {
+        match rest {
+            // 詰め物の0xFFは読み飛ばす。
+            [0xFF, 0xFF, ..] => rest = &rest[1..],
+            // SOSかEOIまでに見つからなければなし。
+            [0xFF, 0xDA, ..] | [0xFF, 0xD9, ..] => return None,
+            [0xFF, marker, len_high, len_low, tail @ ..] => {
+                let len = u16::from_be_bytes([*len_high, *len_low]) as usize;
+                let payload = tail.get(..len.checked_sub(2)?)?;
+
+                if *marker == 0xEE && payload.len() >= 12 && payload.starts_with(b"Adobe") {
+                    return Some(payload[11]);
+                }
+
+                rest = &tail[payload.len()..];
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// EXIFのOrientationタグの値を読み取る
+fn read_exif_orientation(exif: &[u8]) -> Option<u16> {
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes = [*exif.get(offset)?, *exif.get(offset + 1)?];
+        match &exif[..2] {
+            b"II" => Some(u16::from_le_bytes(bytes)),
+            _ => Some(u16::from_be_bytes(bytes)),
+        }
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = exif.get(offset..offset + 4)?.try_into().ok()?;
+        match &exif[..2] {
+            b"II" => Some(u32::from_le_bytes(bytes)),
+            _ => Some(u32::from_be_bytes(bytes)),
+        }
+    };
+
+    if !matches!(exif.get(..2)?, b"II" | b"MM") || read_u16(2)? != 42 {
+        return None;
+    }
+
+    let ifd_offset = read_u32(4)? as usize;
+    let entry_count = read_u16(ifd_offset)? as usize;
+
+    for i in 0..entry_count {
+        let entry_offset = ifd_offset + 2 + i * 12;
+
+        // Orientationタグ(0x0112)、SHORT型(3)のみを対象にする。
+        if read_u16(entry_offset)? == 0x0112 && read_u16(entry_offset + 2)? == 3 {
+            return match read_u16(entry_offset + 8)? {
+                v @ 1..=8 => Some(v),
+                _ => None,
+            };
+        }
+    }
+
+    None
+}
+
+/// EXIFのOrientationに従って画像を回転・反転する
+fn apply_orientation(
+    buf: Vec<u8>,
+    width: u32,
+    height: u32,
+    orientation: u16,
+) -> (Vec<u8>, u32, u32) {
+    let w = width as usize;
+    let h = height as usize;
+
+    if !(2..=8).contains(&orientation) || buf.len() < w * h * 4 {
+        return (buf, width, height);
+    }
+
+    // 5以上は縦横が入れ替わる。
+    let (new_width, new_height) = if orientation >= 5 { (h, w) } else { (w, h) };
+
+    let mut result = Vec::with_capacity(buf.len());
+    for y in 0..new_height {
+        for x in 0..new_width {
+            let (src_x, src_y) = match orientation {
+                2 => (w - 1 - x, y),
+                3 => (w - 1 - x, h - 1 - y),
+                4 => (x, h - 1 - y),
+                5 => (y, x),
+                6 => (y, h - 1 - x),
+                7 => (w - 1 - y, h - 1 - x),
+                _ => (w - 1 - y, x),
+            };
+            let index = (src_y * w + src_x) * 4;
+
+            result.extend_from_slice(&buf[index..index + 4]);
+        }
+    }
+
+    (result, new_width as u32, new_height as u32)
+}
+
+/// RGBAの並びを、qualityの画質(1から100)でjpegとして書き出す
+///
+/// jpegはアルファを持てないので、不透明でない画素がある場合はエラーにする。
+pub(crate) fn write_jpeg(
+    path: &PathBuf,
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    quality: u8,
+) -> Result<(), ResizedPngError> {
+    if buf.chunks_exact(4).any(|v| v[3] != u8::MAX) {
+        return Err(ResizedPngError::ParameterError(None));
     }
+
+    // jpegの幅と高さは16bitまで。
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(ResizedPngError::LimitsError(None));
+    };
+
+    let rgb: Vec<u8> = buf
+        .chunks_exact(4)
+        .flat_map(|v| [v[0], v[1], v[2]])
+        .collect();
+
+    let fs = File::create(path)?;
+    let w = BufWriter::new(fs);
+
+    Encoder::new(w, quality.clamp(1, 100)).encode(&rgb, width, height, ColorType::Rgb)?;
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn exif_segment(orientation: u16) -> Vec<u8> {
+        let mut tiff = Vec::new();
+        tiff.extend_from_slice(b"MM");
+        tiff.extend_from_slice(&42u16.to_be_bytes());
+        tiff.extend_from_slice(&8u32.to_be_bytes());
+        tiff.extend_from_slice(&1u16.to_be_bytes());
+        tiff.extend_from_slice(&0x0112u16.to_be_bytes());
+        tiff.extend_from_slice(&3u16.to_be_bytes());
+        tiff.extend_from_slice(&1u32.to_be_bytes());
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0, 0]);
+        tiff.extend_from_slice(&0u32.to_be_bytes());
+        tiff
+    }
+
+    mod write_jpeg {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn round_trip_when_valid_parameter() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("test.jpg");
+
+            let buf = [200, 100, 50, 255].repeat(16 * 8);
+            write_jpeg(&path, &buf, 16, 8, 90).unwrap();
+
+            let ImageData {
+                pixels,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 16);
+            assert_eq!(height, 8);
+            for (actual, expected) in pixels.iter().zip(&buf) {
+                assert!(actual.abs_diff(*expected) <= 4, "{} {}", actual, expected);
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_not_opaque() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("test.jpg");
+
+            let buf = [200, 100, 50, 128].repeat(4);
+
+            assert!(matches!(
+                write_jpeg(&path, &buf, 2, 2, 90),
+                Err(ResizedPngError::ParameterError(_))
+            ));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_too_large() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("test.jpg");
+
+            let buf = [0, 0, 0, 255].repeat(70000);
+
+            assert!(matches!(
+                write_jpeg(&path, &buf, 70000, 1, 90),
+                Err(ResizedPngError::LimitsError(_))
+            ));
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod read_dimensions {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_sample_jpeg() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+
+            assert_eq!(read_dimensions(&path).unwrap(), (100, 200));
+        }
+
+        #[test]
+        fn failed_when_invalid_jpeg_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+
+            assert!(read_dimensions(&path).is_err());
+        }
+    }
+
     mod read_image_data {
         use super::*;
 
+        use std::path::Path;
+
+        use tempfile::tempdir;
+
+        fn write_sample_with_orientation(dir: &Path, orientation: u16) -> PathBuf {
+            let sample_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let sample = std::fs::read(sample_path).unwrap();
+            let tiff = exif_segment(orientation);
+
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(&sample[..2]);
+            bytes.extend_from_slice(&[0xFF, 0xE1]);
+            bytes.extend_from_slice(&(tiff.len() as u16 + 8).to_be_bytes());
+            bytes.extend_from_slice(b"Exif\0\0");
+            bytes.extend_from_slice(&tiff);
+            bytes.extend_from_slice(&sample[2..]);
+
+            let path = dir.join("orientation.jpg");
+            std::fs::write(&path, bytes).unwrap();
+
+            path
+        }
+
         #[test]
         fn success_when_valid_jpg_path() {
             let path =
                 PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
 
-            let (_data, width, height) = read_image_data(&path).unwrap();
+            let ImageData {
+                width,
+                height,
+                source_color,
+                ..
+            } = read_image_data(&path).unwrap();
 
             assert_eq!(width, 100);
             assert_eq!(height, 200);
+            assert_eq!(source_color, SourceColorType::Rgb);
+        }
+
+        #[test]
+        fn checking_value_when_exif_orientation_is_rotate_90() {
+            let out_dir = tempdir().unwrap();
+            let path = write_sample_with_orientation(out_dir.path(), 6);
+
+            let ImageData { width, height, .. } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 200);
+            assert_eq!(height, 100);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_exif_orientation_is_mirrored() {
+            let out_dir = tempdir().unwrap();
+            let path = write_sample_with_orientation(out_dir.path(), 2);
+            let original_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+
+            let ImageData {
+                pixels: data,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+            let ImageData {
+                pixels: original, ..
+            } = read_image_data(&original_path).unwrap();
+
+            assert_eq!(width, 100);
+            assert_eq!(height, 200);
+            assert_eq!(data[..4], original[99 * 4..100 * 4]);
+
+            out_dir.close().unwrap();
         }
 
         #[test]
@@ -74,6 +578,252 @@ mod tests {
 
             assert!(read_image_data(&path).is_err());
         }
+
+        #[test]
+        fn partial_image_when_data_is_truncated() {
+            let out_dir = tempdir().unwrap();
+
+            let sample_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let sample = std::fs::read(&sample_path).unwrap();
+            let path = out_dir.path().join("truncated.jpg");
+            std::fs::write(&path, &sample[..sample.len() * 2 / 3]).unwrap();
+
+            let original = read_image_data(&sample_path).unwrap();
+            let truncated = read_image_data(&path).unwrap();
+
+            assert_eq!(truncated.width, original.width);
+            assert_eq!(truncated.height, original.height);
+            // 先頭の行は切れる前に読めている。
+            let line = original.width as usize * 4;
+            assert_eq!(truncated.pixels[..line], original.pixels[..line]);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn decoding_error_when_no_scan_data() {
+            let out_dir = tempdir().unwrap();
+
+            let sample_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let sample = std::fs::read(sample_path).unwrap();
+            let path = out_dir.path().join("truncated.jpg");
+            std::fs::write(&path, &sample[..20]).unwrap();
+
+            assert!(matches!(
+                read_image_data(&path),
+                Err(ResizedPngError::DecodingError(_))
+            ));
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod read_image_data_from_bytes {
+        use super::*;
+
+        #[test]
+        fn decoding_error_when_frame_has_no_scan() {
+            let sample_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let sample = std::fs::read(sample_path).unwrap();
+            // SOSの手前で切り、EOIで終える。フレームの情報はあるが画素はない。
+            let sos = sample.windows(2).position(|v| v == [0xFF, 0xDA]).unwrap();
+            let bytes = [&sample[..sos], &[0xFF, 0xD9]].concat();
+
+            assert!(matches!(
+                read_image_data_from_bytes(&bytes),
+                Err(ResizedPngError::DecodingError(_))
+            ));
+        }
+
+        #[test]
+        fn decoding_error_when_only_markers() {
+            assert!(matches!(
+                read_image_data_from_bytes(&[0xFF, 0xD8, 0xFF, 0xD9]),
+                Err(ResizedPngError::DecodingError(_))
+            ));
+        }
+
+        /// インク量で塗りつぶした、指定の形式で保存したCMYKのjpeg
+        fn cmyk_jpeg(ink: [u8; 4], color_type: ColorType) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            Encoder::new(&mut bytes, 100)
+                .encode(&ink.repeat(8 * 8), 8, 8, color_type)
+                .unwrap();
+
+            bytes
+        }
+
+        /// APP14(Adobe)のtransformを書き換える
+        fn set_adobe_transform(bytes: &mut [u8], transform: u8) {
+            let offset = bytes.windows(5).position(|v| v == b"Adobe").unwrap();
+            bytes[offset + 11] = transform;
+        }
+
+        fn assert_near(actual: &[u8], expected: [u8; 4]) {
+            assert!(
+                actual.iter().zip(expected).all(|(a, e)| a.abs_diff(e) <= 4),
+                "{:?} {:?}",
+                actual,
+                expected
+            );
+        }
+
+        #[test]
+        fn grayscale_source_when_luma_jpeg() {
+            let mut bytes = Vec::new();
+            Encoder::new(&mut bytes, 100)
+                .encode(&[128; 8 * 8], 8, 8, ColorType::Luma)
+                .unwrap();
+
+            let image = read_image_data_from_bytes(&bytes).unwrap();
+
+            assert_eq!(image.source_color, SourceColorType::Grayscale);
+            assert!(image
+                .pixels
+                .chunks_exact(4)
+                .all(|v| v[0] == v[1] && v[1] == v[2] && v[3] == u8::MAX));
+            assert_near(&image.pixels[..4], [128, 128, 128, 255]);
+        }
+
+        #[test]
+        fn checking_value_when_plain_cmyk() {
+            let bytes = cmyk_jpeg([255, 0, 0, 0], ColorType::Cmyk);
+            assert_eq!(read_adobe_transform(&bytes), Some(0));
+
+            let image = read_image_data_from_bytes(&bytes).unwrap();
+
+            // シアンは(0, 255, 255)になり、反転しない。
+            assert_near(&image.pixels[..4], [0, 255, 255, 255]);
+        }
+
+        #[test]
+        fn checking_value_when_ycck() {
+            let bytes = cmyk_jpeg([0, 255, 0, 64], ColorType::CmykAsYcck);
+            assert_eq!(read_adobe_transform(&bytes), Some(2));
+
+            let image = read_image_data_from_bytes(&bytes).unwrap();
+
+            // マゼンタにKを足した色で、反転しない。
+            assert_near(&image.pixels[..4], [191, 0, 191, 255]);
+        }
+
+        #[test]
+        fn checking_value_when_transform_is_ycbcr_with_four_channels() {
+            let mut bytes = cmyk_jpeg([0, 255, 0, 64], ColorType::CmykAsYcck);
+            set_adobe_transform(&mut bytes, 1);
+
+            let image = read_image_data_from_bytes(&bytes).unwrap();
+
+            assert_near(&image.pixels[..4], [191, 0, 191, 255]);
+        }
+    }
+
+    mod read_image_data_scaled {
+        use super::*;
+
+        #[test]
+        fn reduced_size_when_min_size_is_small() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+
+            let (image, original_size) = read_image_data_scaled(&path, |width, height| {
+                assert_eq!((width, height), (100, 200));
+                Some((10, 20))
+            })
+            .unwrap();
+
+            assert_eq!(original_size, (100, 200));
+            assert_eq!(image.width, 13);
+            assert_eq!(image.height, 25);
+            assert_eq!(image.pixels.len(), 13 * 25 * 4);
+        }
+
+        #[test]
+        fn original_size_when_min_size_is_none() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+
+            let (image, original_size) = read_image_data_scaled(&path, |_, _| None).unwrap();
+
+            assert_eq!(original_size, (100, 200));
+            assert_eq!(image.width, 100);
+            assert_eq!(image.height, 200);
+        }
+    }
+
+    #[cfg(feature = "icc")]
+    mod to_rgb_with_profile {
+        use super::*;
+
+        use crate::image::icc::tests::cmyk_test_profile;
+
+        /// インク量で塗りつぶしたCMYKのjpeg
+        fn cmyk_jpeg(ink: [u8; 4], icc_profile: Option<&[u8]>) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            let mut encoder = Encoder::new(&mut bytes, 100);
+            if let Some(profile) = icc_profile {
+                encoder.add_icc_profile(profile).unwrap();
+            }
+            encoder
+                .encode(&ink.repeat(8 * 8), 8, 8, ColorType::Cmyk)
+                .unwrap();
+
+            bytes
+        }
+
+        #[test]
+        fn checking_value_when_profile_is_embedded() {
+            let profile = cmyk_test_profile();
+
+            let naive = read_image_data_from_bytes(&cmyk_jpeg([255, 0, 0, 0], None)).unwrap();
+            let profiled =
+                read_image_data_from_bytes(&cmyk_jpeg([255, 0, 0, 0], Some(&profile))).unwrap();
+
+            // 素朴な変換ではシアンは(0, 255, 255)になり、プロファイルではくすむ。
+            assert!(naive.pixels[1] >= 250, "{:?}", &naive.pixels[..4]);
+            assert!(
+                naive.pixels[1] - profiled.pixels[1] > 50,
+                "{:?} {:?}",
+                &naive.pixels[..4],
+                &profiled.pixels[..4]
+            );
+        }
+
+        #[test]
+        fn checking_value_when_not_cmyk() {
+            let buf = [200, 100, 50, 255];
+
+            assert_eq!(
+                to_rgb_with_profile(&[200, 100, 50], &PixelFormat::RGB24, None, Some(Vec::new()))
+                    .unwrap(),
+                buf
+            );
+        }
+
+        #[test]
+        fn same_as_naive_when_profile_is_invalid() {
+            let naive = read_image_data_from_bytes(&cmyk_jpeg([255, 0, 0, 0], None)).unwrap();
+            let invalid =
+                read_image_data_from_bytes(&cmyk_jpeg([255, 0, 0, 0], Some(b"not a profile")))
+                    .unwrap();
+
+            assert_eq!(invalid.pixels, naive.pixels);
+        }
+    }
+
+    mod scaled_size {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_both_sides_are_satisfied() {
+            assert_eq!(scaled_size(100, 200, 10, 20), (13, 25));
+            assert_eq!(scaled_size(100, 200, 20, 20), (25, 50));
+            assert_eq!(scaled_size(100, 200, 50, 10), (50, 100));
+            assert_eq!(scaled_size(100, 200, 60, 10), (100, 200));
+        }
     }
 
     mod to_rgb {
@@ -84,7 +834,7 @@ mod tests {
             let buf = [0, 1, 2];
             let pixel_format = PixelFormat::L8;
 
-            let pixels = to_rgb(&buf, &pixel_format).unwrap();
+            let pixels = to_rgb(&buf, &pixel_format, None).unwrap();
 
             assert_eq!(
                 pixels,
@@ -94,12 +844,18 @@ mod tests {
 
         #[test]
         fn success_when_valid_l16_buffer() {
-            let buf = [0, 1, 2, 3];
+            let buf = [0x0080u16, 0x0081, 0xFF7F]
+                .iter()
+                .flat_map(|v| v.to_ne_bytes())
+                .collect::<Vec<u8>>();
             let pixel_format = PixelFormat::L16;
 
-            let pixels = to_rgb(&buf, &pixel_format).unwrap();
+            let pixels = to_rgb(&buf, &pixel_format, None).unwrap();
 
-            assert_eq!(pixels, vec![0, 0, 0, u8::MAX, 2, 2, 2, u8::MAX]);
+            assert_eq!(
+                pixels,
+                vec![0, 0, 0, u8::MAX, 1, 1, 1, u8::MAX, 255, 255, 255, u8::MAX]
+            );
         }
 
         #[test]
@@ -107,17 +863,179 @@ mod tests {
             let buf = [0, 1, 2];
             let pixel_format = PixelFormat::RGB24;
 
-            let pixels = to_rgb(&buf, &pixel_format).unwrap();
+            let pixels = to_rgb(&buf, &pixel_format, None).unwrap();
 
             assert_eq!(pixels, vec![0, 1, 2, u8::MAX]);
         }
 
         #[test]
-        fn failed_when_cmyk_buffer() {
-            let buf = [0, 1, 2, 3];
+        fn success_when_valid_cmyk_buffer() {
+            // jpeg_decoderの出力は反転されている。
+            let buf = [
+                u8::MAX,
+                0,
+                u8::MAX,
+                u8::MAX,
+                0,
+                0,
+                0,
+                0,
+                127,
+                u8::MAX,
+                u8::MAX,
+                127,
+            ];
+            let pixel_format = PixelFormat::CMYK32;
+
+            let pixels = to_rgb(&buf, &pixel_format, None).unwrap();
+
+            assert_eq!(
+                pixels,
+                vec![
+                    u8::MAX,
+                    0,
+                    u8::MAX,
+                    u8::MAX,
+                    0,
+                    0,
+                    0,
+                    u8::MAX,
+                    63,
+                    127,
+                    127,
+                    u8::MAX
+                ]
+            );
+        }
+
+        #[test]
+        fn success_when_valid_inverted_cmyk_buffer() {
+            let buf = [
+                0,
+                u8::MAX,
+                0,
+                0,
+                u8::MAX,
+                u8::MAX,
+                u8::MAX,
+                u8::MAX,
+                128,
+                0,
+                0,
+                128,
+            ];
             let pixel_format = PixelFormat::CMYK32;
 
-            assert!(to_rgb(&buf, &pixel_format).is_err());
+            let pixels = to_rgb(&buf, &pixel_format, Some(0)).unwrap();
+
+            assert_eq!(
+                pixels,
+                vec![
+                    u8::MAX,
+                    0,
+                    u8::MAX,
+                    u8::MAX,
+                    0,
+                    0,
+                    0,
+                    u8::MAX,
+                    63,
+                    127,
+                    127,
+                    u8::MAX
+                ]
+            );
+        }
+    }
+
+    mod read_adobe_transform {
+        use super::*;
+
+        #[test]
+        fn some_value_when_adobe_segment_exists() {
+            let bytes = [
+                0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xEE, 0x00, 0x0E, b'A', b'd',
+                b'o', b'b', b'e', 0x00, 0x64, 0x00, 0x00, 0x00, 0x00, 0x02, 0xFF, 0xDA,
+            ];
+
+            assert_eq!(read_adobe_transform(&bytes), Some(2));
+        }
+
+        #[test]
+        fn none_when_adobe_segment_does_not_exist() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let bytes = std::fs::read(path).unwrap();
+
+            assert_eq!(read_adobe_transform(&bytes), None);
+        }
+
+        #[test]
+        fn none_when_not_jpeg() {
+            let bytes = [0x89, b'P', b'N', b'G'];
+
+            assert_eq!(read_adobe_transform(&bytes), None);
+        }
+    }
+
+    mod read_exif_orientation {
+        use super::*;
+
+        #[test]
+        fn some_value_when_valid_exif() {
+            assert_eq!(read_exif_orientation(&exif_segment(6)), Some(6));
+        }
+
+        #[test]
+        fn none_when_invalid_orientation() {
+            assert_eq!(read_exif_orientation(&exif_segment(9)), None);
+        }
+
+        #[test]
+        fn none_when_malformed_exif() {
+            assert_eq!(read_exif_orientation(b"MM\0*\0\0\0\x08\0"), None);
+            assert_eq!(read_exif_orientation(&[]), None);
+        }
+    }
+
+    mod apply_orientation {
+        use super::*;
+
+        // 2x1の画像: 左が赤、右が青。
+        const BUF: [u8; 8] = [255, 0, 0, 255, 0, 0, 255, 255];
+        const RED: [u8; 4] = [255, 0, 0, 255];
+        const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+        #[test]
+        fn checking_value_when_no_operation() {
+            let (buf, width, height) = apply_orientation(BUF.to_vec(), 2, 1, 1);
+
+            assert_eq!(buf, BUF.to_vec());
+            assert_eq!((width, height), (2, 1));
+        }
+
+        #[test]
+        fn checking_value_when_mirrored() {
+            let (buf, width, height) = apply_orientation(BUF.to_vec(), 2, 1, 2);
+
+            assert_eq!(buf, [BLUE, RED].concat());
+            assert_eq!((width, height), (2, 1));
+        }
+
+        #[test]
+        fn checking_value_when_rotate_90() {
+            let (buf, width, height) = apply_orientation(BUF.to_vec(), 2, 1, 6);
+
+            assert_eq!(buf, [RED, BLUE].concat());
+            assert_eq!((width, height), (1, 2));
+        }
+
+        #[test]
+        fn checking_value_when_rotate_270() {
+            let (buf, width, height) = apply_orientation(BUF.to_vec(), 2, 1, 8);
+
+            assert_eq!(buf, [BLUE, RED].concat());
+            assert_eq!((width, height), (1, 2));
         }
     }
 }
@@ -1,27 +1,265 @@
-use std::io::BufWriter;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::PathBuf;
 use std::{fs::File, slice::Iter};
 
-use png::{BitDepth, ColorType, Decoder, Encoder, Info};
+use flate2::write::ZlibEncoder;
+use png::{
+    chunk, BitDepth, BlendOp, ColorType, Compression, Decoder, DisposeOp, Encoder, Info, Limits,
+    OutputInfo, PixelDimensions, Reader, Writer,
+};
 
 use crate::error::ResizedPngError;
 
-use super::ImageData;
+use super::{u16_to_u8, ImageData, SourceColorType, MAX_INPUT_PIXELS};
+
+mod quantize;
 
 pub(crate) fn read_image_data(path: &PathBuf) -> Result<ImageData, ResizedPngError> {
-    let fs = File::open(path)?;
-    let decoder = Decoder::new(fs);
+    let bytes = std::fs::read(path)?;
+    read_image_data_from_bytes(&bytes)
+}
+
+/// パレットの範囲外を指す番号の扱い
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum PaletteIndexMode {
+    /// DecodingErrorにする
+    #[default]
+    Strict,
+    /// 透明な黒のピクセルにする
+    Lenient,
+}
+
+/// メモリ上のバイト列から読み込む
+pub(crate) fn read_image_data_from_bytes(bytes: &[u8]) -> Result<ImageData, ResizedPngError> {
+    read_image_data_from_bytes_with(bytes, PaletteIndexMode::Strict)
+}
+
+/// メモリ上のバイト列から、パレットの範囲外の番号の扱いを指定して読み込む
+pub(crate) fn read_image_data_from_bytes_with(
+    bytes: &[u8],
+    palette_index: PaletteIndexMode,
+) -> Result<ImageData, ResizedPngError> {
+    let decoder = Decoder::new(bytes);
     let mut reader = decoder.read_info()?;
 
+    check_input_pixels(reader.info())?;
+
     let mut buf = vec![0; reader.output_buffer_size()];
-    let output_info = reader.next_frame(&mut buf)?;
+    let output_info = read_first_frame(&mut reader, &mut buf)?;
     let bytes = &buf[..output_info.buffer_size()];
 
     let info = reader.info();
 
-    let result = buf_to_rgba(bytes, info)?;
+    let result = buf_to_rgba(bytes, info, palette_index)?;
+
+    let source_color = match info.color_type {
+        ColorType::Grayscale => SourceColorType::Grayscale,
+        ColorType::GrayscaleAlpha => SourceColorType::GrayscaleAlpha,
+        ColorType::Rgb => SourceColorType::Rgb,
+        ColorType::Rgba => SourceColorType::Rgba,
+        ColorType::Indexed => SourceColorType::Indexed,
+    };
+
+    Ok(ImageData {
+        pixels: result,
+        width: info.width,
+        height: info.height,
+        source_color,
+        pixel_dims: info.pixel_dims,
+        icc_profile: info.icc_profile.as_ref().map(|v| v.to_vec()),
+        text_chunks: text_chunks(info),
+    })
+}
+
+/// 16bitのpngを、16bitのRGBAのまま読み込む。16bitでない場合はNone
+pub(crate) fn read_image_data16_from_bytes(
+    bytes: &[u8],
+) -> Result<Option<ImageData<u16>>, ResizedPngError> {
+    let decoder = Decoder::new(bytes);
+    let mut reader = decoder.read_info()?;
+
+    if reader.info().bit_depth != BitDepth::Sixteen {
+        return Ok(None);
+    }
+    check_input_pixels(reader.info())?;
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let output_info = read_first_frame(&mut reader, &mut buf)?;
+    let info = reader.info();
+
+    let samples: Vec<u16> = buf[..output_info.buffer_size()]
+        .chunks_exact(2)
+        .map(|v| u16::from_be_bytes([v[0], v[1]]))
+        .collect();
+    drop(buf);
+
+    // tRNSで指定された色と一致するピクセルは透明にする。
+    let channels = info.color_type.samples();
+    let key: Option<Vec<u16>> = info
+        .trns
+        .as_ref()
+        .map(|v| {
+            v.chunks_exact(2)
+                .map(|v| u16::from_be_bytes([v[0], v[1]]))
+                .collect::<Vec<u16>>()
+        })
+        .filter(|v| v.len() == channels);
+
+    let mut pixels = Vec::with_capacity(info.width as usize * info.height as usize * 4);
+    for s in samples.chunks_exact(channels) {
+        let mut pixel = match info.color_type {
+            ColorType::Grayscale => [s[0], s[0], s[0], u16::MAX],
+            ColorType::GrayscaleAlpha => [s[0], s[0], s[0], s[1]],
+            ColorType::Rgb => [s[0], s[1], s[2], u16::MAX],
+            ColorType::Rgba => [s[0], s[1], s[2], s[3]],
+            // パレットは16bitにならない。
+            ColorType::Indexed => return Err(ResizedPngError::DecodingError(None)),
+        };
+        if key.as_deref() == Some(s) {
+            pixel[3] = 0;
+        }
+
+        pixels.extend_from_slice(&pixel);
+    }
+
+    if pixels.len() != info.width as usize * info.height as usize * 4 {
+        return Err(ResizedPngError::DecodingError(None));
+    }
+
+    let source_color = match info.color_type {
+        ColorType::Grayscale => SourceColorType::Grayscale,
+        ColorType::GrayscaleAlpha => SourceColorType::GrayscaleAlpha,
+        ColorType::Rgb => SourceColorType::Rgb,
+        _ => SourceColorType::Rgba,
+    };
+
+    Ok(Some(ImageData {
+        pixels,
+        width: info.width,
+        height: info.height,
+        source_color,
+        pixel_dims: info.pixel_dims,
+        icc_profile: info.icc_profile.as_ref().map(|v| v.to_vec()),
+        text_chunks: text_chunks(info),
+    }))
+}
+
+/// IDATより前にあるtEXtとzTXtのキーワードと値。展開できないzTXtは飛ばす
+///
+/// iTXtはUTF-8で、tEXtとしては書き出せないので集めない。
+fn text_chunks(info: &Info) -> Vec<(String, String)> {
+    let uncompressed = info
+        .uncompressed_latin1_text
+        .iter()
+        .map(|v| (v.keyword.clone(), v.text.clone()));
+    let compressed = info
+        .compressed_latin1_text
+        .iter()
+        .filter_map(|v| Some((v.keyword.clone(), v.get_text().ok()?)));
+
+    uncompressed.chain(compressed).collect()
+}
+
+/// 最初のフレームを読み込む
+///
+/// APNGでIDATの前にfcTLがない場合、IDATの画像はアニメーションに含まれない既定の画像なので、
+/// 読み飛ばして最初のfdATのフレームを読む。
+fn read_first_frame<R: Read>(
+    reader: &mut Reader<R>,
+    buf: &mut [u8],
+) -> Result<OutputInfo, ResizedPngError> {
+    let info = reader.info();
+    let default_image_is_extra = info.animation_control.is_some() && info.frame_control.is_none();
+
+    let mut output_info = reader.next_frame(buf)?;
+    if default_image_is_extra {
+        output_info = reader.next_frame(buf)?;
+    }
+
+    // 最初のフレームは、IHDRと同じ大きさでなければならない。
+    let info = reader.info();
+    if (output_info.width, output_info.height) != (info.width, info.height) {
+        return Err(ResizedPngError::DecodingError(None));
+    }
+
+    Ok(output_info)
+}
+
+/// 確保する前に、IHDRの幅と高さを確かめる
+fn check_input_pixels(info: &Info) -> Result<(), ResizedPngError> {
+    if info.width as u64 * info.height as u64 > MAX_INPUT_PIXELS {
+        return Err(ResizedPngError::LimitsError(None));
+    }
+
+    Ok(())
+}
+
+/// 画素を読まずに幅と高さを返す
+pub(crate) fn read_dimensions(path: &PathBuf) -> Result<(u32, u32), ResizedPngError> {
+    let fs = File::open(path)?;
+    let reader = Decoder::new(BufReader::new(fs)).read_info()?;
+    let info = reader.info();
+
+    Ok((info.width, info.height))
+}
+
+/// メモリ上のバイト列から、画素を読まずに幅と高さを返す
+pub(crate) fn read_dimensions_from_bytes(bytes: &[u8]) -> Result<(u32, u32), ResizedPngError> {
+    let reader = Decoder::new(bytes).read_info()?;
+    let info = reader.info();
+
+    Ok((info.width, info.height))
+}
+
+/// pngの書き出しの設定
+#[derive(Debug, Clone)]
+pub(crate) struct WriteOptions {
+    /// 書き出す色形式。入力はRGBAの並びのまま渡す
+    ///
+    /// Indexedの場合はパレットを作って書き出す。256色を超える場合は、
+    /// `quantize`なら減色してパレットで、そうでなければRGBAで書き出す。
+    pub(crate) color_type: ColorType,
+    /// 8bitか16bitのみ対応
+    pub(crate) bit_depth: BitDepth,
+    pub(crate) compression: Compression,
+    /// pHYsとして書き出す、1ピクセルの物理的な大きさ
+    pub(crate) pixel_dims: Option<PixelDimensions>,
+    /// Adam7でインターレースする
+    pub(crate) interlaced: bool,
+    /// bKGDとして書き出す背景色(RGB)。Noneなら書き出さない
+    pub(crate) background: Option<[u8; 3]>,
+    /// iCCPとして書き出すICCプロファイル。Noneなら書き出さない
+    ///
+    /// それ以外の付随的なチャンクはpHYsとbKGDとtEXtしか書き出さないので、exifなどは残らない。
+    pub(crate) icc_profile: Option<Vec<u8>>,
+    /// tEXtとして書き出すキーワードと値。Latin-1で表せないものはEncodingErrorになる
+    pub(crate) text_chunks: Vec<(String, String)>,
+    /// 書き出した後にファイルを読み直し、壊れていればEncodingErrorにする
+    pub(crate) verify: bool,
+    /// Indexedで256色を超える場合、RGBAにせず256色に減色する
+    pub(crate) quantize: bool,
+    /// `quantize`で減色するときに、Floyd-Steinbergのディザリングを掛ける
+    pub(crate) dither: bool,
+}
 
-    Ok((result, info.width, info.height))
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            color_type: ColorType::Rgba,
+            bit_depth: BitDepth::Eight,
+            compression: Compression::Default,
+            pixel_dims: None,
+            interlaced: false,
+            background: None,
+            icc_profile: None,
+            text_chunks: Vec::new(),
+            verify: false,
+            quantize: false,
+            dither: false,
+        }
+    }
 }
 
 pub(crate) fn write_png(
@@ -29,29 +267,580 @@ pub(crate) fn write_png(
     buf: &[u8],
     width: u32,
     height: u32,
+    options: &WriteOptions,
+) -> Result<(), ResizedPngError> {
+    let fs = File::create(path)?;
+    let w = BufWriter::new(fs);
+
+    write_png_to(w, buf, width, height, options)?;
+
+    if options.verify {
+        verify_png(path, width, height)?;
+    }
+
+    Ok(())
+}
+
+/// 書き出したpngを読み直し、最後のチャンクまでCRCを含めて壊れていないことと、幅と高さを確かめる
+fn verify_png(path: &PathBuf, width: u32, height: u32) -> Result<(), ResizedPngError> {
+    let bytes = std::fs::read(path)?;
+
+    verify_png_bytes(&bytes, width, height)
+}
+
+fn verify_png_bytes(bytes: &[u8], width: u32, height: u32) -> Result<(), ResizedPngError> {
+    let corrupted = |_| ResizedPngError::EncodingError(None);
+
+    // 書き出したばかりの画像なので、読み込みの上限は設けない。
+    let decoder = Decoder::new_with_limits(bytes, Limits { bytes: usize::MAX });
+    let mut reader = decoder.read_info().map_err(corrupted)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut buf).map_err(corrupted)?;
+    reader.finish().map_err(corrupted)?;
+
+    let info = reader.info();
+    if (info.width, info.height) != (width, height) {
+        return Err(ResizedPngError::EncodingError(None));
+    }
+
+    Ok(())
+}
+
+/// pngにエンコードしたバイト列を返す
+pub(crate) fn encode_png(
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    options: &WriteOptions,
+) -> Result<Vec<u8>, ResizedPngError> {
+    let mut bytes = Vec::new();
+
+    write_png_to(&mut bytes, buf, width, height, options)?;
+
+    Ok(bytes)
+}
+
+fn write_png_to<W: Write>(
+    w: W,
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    options: &WriteOptions,
+) -> Result<(), ResizedPngError> {
+    if options.color_type == ColorType::Indexed {
+        // 減色した後は256色以下なので、必ずパレットにできる。
+        let low_bit_depth = !options.interlaced;
+        let indexed = match to_indexed(buf, width as usize, low_bit_depth) {
+            None if options.quantize => {
+                let quantized = if options.dither {
+                    quantize::quantize_dithered(buf, width as usize, 256)
+                } else {
+                    quantize::quantize(buf, 256)
+                };
+                to_indexed(&quantized, width as usize, low_bit_depth)
+            }
+            indexed => indexed,
+        };
+        return match indexed {
+            Some(indexed) => write_indexed_png_to(w, &indexed, width, height, options),
+            None => {
+                let options = WriteOptions {
+                    color_type: ColorType::Rgba,
+                    ..options.clone()
+                };
+                write_png_to(w, buf, width, height, &options)
+            }
+        };
+    }
+
+    let data = rgba_to_color_type(buf, options.color_type)?;
+    let data = match options.bit_depth {
+        BitDepth::Eight => data,
+        // 上位と下位に同じ値を入れると、0..=255が0..=65535に均等に広がる。
+        BitDepth::Sixteen => Cow::Owned(data.iter().flat_map(|v| [*v, *v]).collect()),
+        _ => return Err(ResizedPngError::ParameterError(None)),
+    };
+
+    write_samples_to(w, &data, width, height, options.bit_depth, options)
+}
+
+/// 同じ大きさの8bitのRGBAのフレームを、apngとして書き出す。各フレームの表示時間は1/100秒単位
+///
+/// 色形式とビット深度、インターレースの指定は見ずに、RGBAで書き出す。繰り返しの回数は無限。
+pub(crate) fn write_apng(
+    path: &PathBuf,
+    frames: &[(Vec<u8>, u16)],
+    width: u32,
+    height: u32,
+    options: &WriteOptions,
 ) -> Result<(), ResizedPngError> {
     let fs = File::create(path)?;
-    let w = &mut BufWriter::new(fs);
+    let w = BufWriter::new(fs);
 
-    let mut encoder = Encoder::new(w, width, height);
+    let options = WriteOptions {
+        color_type: ColorType::Rgba,
+        bit_depth: BitDepth::Eight,
+        interlaced: false,
+        ..options.clone()
+    };
+    let mut encoder = new_encoder(w, width, height, &options)?;
     encoder.set_color(ColorType::Rgba);
     encoder.set_depth(BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    // どのフレームも重ね終えた画面全体なので、前のフレームに重ねずに置き換える。
+    encoder.set_dispose_op(DisposeOp::None)?;
+    encoder.set_blend_op(BlendOp::Source)?;
+
+    let mut writer = encoder.write_header()?;
+    if let Some(background) = options.background {
+        let data = background_chunk(background, ColorType::Rgba, BitDepth::Eight);
+        writer.write_chunk(chunk::bKGD, &data)?;
+    }
+
+    for (data, delay) in frames {
+        writer.set_frame_delay(*delay, 100)?;
+        writer.write_image_data(data)?;
+    }
+    writer.finish()?;
+
+    if options.verify {
+        verify_png(path, width, height)?;
+    }
+
+    Ok(())
+}
+
+/// 16bitのRGBAを、16bitのpngとして書き出す
+///
+/// options.bit_depthは見ずに常に16bitで書き出す。Indexedは指定できない。
+pub(crate) fn write_png16(
+    path: &PathBuf,
+    buf: &[u16],
+    width: u32,
+    height: u32,
+    options: &WriteOptions,
+) -> Result<(), ResizedPngError> {
+    let fs = File::create(path)?;
+    let w = BufWriter::new(fs);
+
+    write_png16_to(w, buf, width, height, options)?;
+
+    if options.verify {
+        verify_png(path, width, height)?;
+    }
+
+    Ok(())
+}
+
+/// 16bitのRGBAを、16bitのpngのバイト列にする
+pub(crate) fn encode_png16(
+    buf: &[u16],
+    width: u32,
+    height: u32,
+    options: &WriteOptions,
+) -> Result<Vec<u8>, ResizedPngError> {
+    let mut bytes = Vec::new();
+
+    write_png16_to(&mut bytes, buf, width, height, options)?;
+
+    Ok(bytes)
+}
+
+fn write_png16_to<W: Write>(
+    w: W,
+    buf: &[u16],
+    width: u32,
+    height: u32,
+    options: &WriteOptions,
+) -> Result<(), ResizedPngError> {
+    let data: Vec<u8> = rgba_to_color_type(buf, options.color_type)?
+        .iter()
+        .flat_map(|v| v.to_be_bytes())
+        .collect();
+
+    write_samples_to(w, &data, width, height, BitDepth::Sixteen, options)
+}
+
+/// 色形式に合わせて並べたサンプルを、ビット深度を指定して書き出す
+fn write_samples_to<W: Write>(
+    w: W,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    bit_depth: BitDepth,
+    options: &WriteOptions,
+) -> Result<(), ResizedPngError> {
+    let mut encoder = new_encoder(w, width, height, options)?;
+    encoder.set_color(options.color_type);
+    encoder.set_depth(bit_depth);
 
     let mut writer = encoder.write_header()?;
-    writer.write_image_data(buf)?;
+    if let Some(background) = options.background {
+        let data = background_chunk(background, options.color_type, bit_depth);
+        writer.write_chunk(chunk::bKGD, &data)?;
+    }
+
+    let bytes_per_pixel = options.color_type.samples() * (bit_depth as usize / 8);
+    write_image_data(&mut writer, data, width, bytes_per_pixel, options)
+}
+
+fn new_encoder<'a, W: Write>(
+    w: W,
+    width: u32,
+    height: u32,
+    options: &WriteOptions,
+) -> Result<Encoder<'a, W>, ResizedPngError> {
+    let mut info = Info::with_size(width, height);
+    info.interlaced = options.interlaced;
+    info.icc_profile = options.icc_profile.clone().map(Cow::Owned);
+
+    let mut encoder = Encoder::with_info(w, info)?;
+    encoder.set_compression(options.compression);
+    encoder.set_pixel_dims(options.pixel_dims);
+    for (keyword, text) in &options.text_chunks {
+        encoder.add_text_chunk(keyword.clone(), text.clone())?;
+    }
+
+    Ok(encoder)
+}
+
+fn write_image_data<W: Write>(
+    writer: &mut Writer<W>,
+    data: &[u8],
+    width: u32,
+    bytes_per_pixel: usize,
+    options: &WriteOptions,
+) -> Result<(), ResizedPngError> {
+    if !options.interlaced {
+        writer.write_image_data(data)?;
+        return Ok(());
+    }
+
+    // pngクレートはインターレースの書き出しに対応していないので、IDATを自前で作る。
+    let idat = encode_adam7(data, width as usize, bytes_per_pixel, options.compression)?;
+    writer.write_chunk(chunk::IDAT, &idat)?;
+
+    Ok(())
+}
+
+/// Adam7の各パスの(開始x, 開始y, xの間隔, yの間隔)
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// 1ピクセルがbytes_per_pixelの行の並びを、Adam7の順に並べ替えて圧縮する
+fn encode_adam7(
+    data: &[u8],
+    width: usize,
+    bytes_per_pixel: usize,
+    compression: Compression,
+) -> Result<Vec<u8>, ResizedPngError> {
+    let stride = width * bytes_per_pixel;
+    let height = data.len().checked_div(stride).unwrap_or(0);
+
+    let mut raw = Vec::new();
+    for (start_x, start_y, step_x, step_y) in ADAM7_PASSES {
+        // 空のパスは行を持たない。
+        if start_x >= width || start_y >= height {
+            continue;
+        }
+
+        for row in data.chunks_exact(stride).skip(start_y).step_by(step_y) {
+            // フィルタは使わない。
+            raw.push(0);
+            for pixel in row
+                .chunks_exact(bytes_per_pixel)
+                .skip(start_x)
+                .step_by(step_x)
+            {
+                raw.extend_from_slice(pixel);
+            }
+        }
+    }
+
+    let level = match compression {
+        Compression::Fast => flate2::Compression::fast(),
+        Compression::Best => flate2::Compression::best(),
+        _ => flate2::Compression::default(),
+    };
+    let mut encoder = ZlibEncoder::new(Vec::new(), level);
+    encoder.write_all(&raw)?;
+
+    Ok(encoder.finish()?)
+}
+
+/// パレットを使う画像
+#[derive(Debug)]
+struct IndexedImage {
+    /// ビット深度に合わせて詰めた行の並び
+    data: Vec<u8>,
+    bit_depth: BitDepth,
+    /// RGBの並び
+    palette: Vec<u8>,
+    /// パレットの各色のアルファ。全て不透明なら空
+    trns: Vec<u8>,
+}
+
+/// RGBAの並びをパレットと番号に変換する。256色を超える場合はNone
+///
+/// low_bit_depthがfalseの場合は、色数が少なくても8bitにする。
+fn to_indexed(buf: &[u8], width: usize, low_bit_depth: bool) -> Option<IndexedImage> {
+    let mut colors: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut entries: Vec<[u8; 4]> = Vec::new();
+    let mut indices = Vec::with_capacity(buf.len() / 4);
+
+    for p in buf.chunks_exact(4) {
+        let color = [p[0], p[1], p[2], p[3]];
+        let index = match colors.get(&color) {
+            Some(v) => *v,
+            None => {
+                let index = u8::try_from(entries.len()).ok()?;
+                colors.insert(color, index);
+                entries.push(color);
+                index
+            }
+        };
+        indices.push(index);
+    }
+
+    let (bit_depth, data) = pack_indices(&indices, width, entries.len(), low_bit_depth);
+
+    let palette = entries.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+    let trns = if entries.iter().all(|c| c[3] == u8::MAX) {
+        Vec::new()
+    } else {
+        entries.iter().map(|c| c[3]).collect()
+    };
+
+    Some(IndexedImage {
+        data,
+        bit_depth,
+        palette,
+        trns,
+    })
+}
+
+/// 1ピクセル1byteの番号を、パレットの色数に合わせたビット深度で行ごとに詰める
+///
+/// low_bit_depthがfalseの場合は、色数が少なくても8bitにする。
+fn pack_indices(
+    indices: &[u8],
+    width: usize,
+    colors: usize,
+    low_bit_depth: bool,
+) -> (BitDepth, Vec<u8>) {
+    // 色数が少なければビット深度を下げて小さくする。
+    let (bit_depth, bits) = match colors {
+        _ if !low_bit_depth => (BitDepth::Eight, 8),
+        0..=2 => (BitDepth::One, 1),
+        3..=4 => (BitDepth::Two, 2),
+        5..=16 => (BitDepth::Four, 4),
+        _ => (BitDepth::Eight, 8),
+    };
+
+    let mut data = Vec::new();
+    for row in indices.chunks(width.max(1)) {
+        let mut byte = 0;
+        let mut used = 0;
+        for index in row {
+            byte |= index << (8 - bits - used);
+            used += bits;
+            if used == 8 {
+                data.push(byte);
+                byte = 0;
+                used = 0;
+            }
+        }
+        // 行の終わりは、余ったビットを0で埋める。
+        if used > 0 {
+            data.push(byte);
+        }
+    }
+
+    (bit_depth, data)
+}
+
+/// パレットのpngを、RGBAにせずに読み込んだもの
+pub(crate) struct PaletteImage {
+    /// 1ピクセル1byteの番号の並び
+    pub(crate) indices: Vec<u8>,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// 元のパレット。RGBの並び
+    pub(crate) palette: Vec<u8>,
+    /// 元のtRNS。なければ空
+    pub(crate) trns: Vec<u8>,
+    pub(crate) pixel_dims: Option<PixelDimensions>,
+    pub(crate) icc_profile: Option<Vec<u8>>,
+    pub(crate) text_chunks: Vec<(String, String)>,
+}
+
+/// パレットのpngを、番号と元のパレットのまま読み込む。そのまま扱えなければNone
+///
+/// パレットでない場合のほか、sBITで色を補正する場合や、パレットの範囲外を指す番号がある場合もNoneにして、
+/// RGBAで読み込む方に任せる。
+pub(crate) fn read_palette_image_from_bytes(
+    bytes: &[u8],
+) -> Result<Option<PaletteImage>, ResizedPngError> {
+    let decoder = Decoder::new(bytes);
+    let mut reader = decoder.read_info()?;
+
+    let info = reader.info();
+    let significant = matches!(info.sbit.as_deref(), Some(&[r, g, b]) if r.min(g).min(b) < 8);
+    if info.color_type != ColorType::Indexed || significant {
+        return Ok(None);
+    }
+    check_input_pixels(info)?;
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let output_info = read_first_frame(&mut reader, &mut buf)?;
+    let info = reader.info();
+
+    let palette = match &info.palette {
+        Some(v) if v.len() % 3 == 0 => v.to_vec(),
+        _ => return Err(ResizedPngError::DecodingError(None)),
+    };
+    let indices = read_bytes_for_usize(&buf[..output_info.buffer_size()], info)?;
+    if indices.iter().any(|v| *v >= palette.len() / 3) {
+        return Ok(None);
+    }
+
+    Ok(Some(PaletteImage {
+        indices: indices.iter().map(|v| *v as u8).collect(),
+        width: info.width,
+        height: info.height,
+        palette,
+        trns: info.trns.as_deref().map(|v| v.to_vec()).unwrap_or_default(),
+        pixel_dims: info.pixel_dims,
+        icc_profile: info.icc_profile.as_ref().map(|v| v.to_vec()),
+        text_chunks: text_chunks(info),
+    }))
+}
+
+/// 1ピクセル1byteの番号の並びを、与えたパレットとtRNSのまま、パレットのpngとして書き出す
+///
+/// options.color_typeとbit_depthは見ない。ビット深度はパレットの色数で決める。
+pub(crate) fn write_palette_png(
+    path: &PathBuf,
+    indices: &[u8],
+    width: u32,
+    height: u32,
+    palette: &[u8],
+    trns: &[u8],
+    options: &WriteOptions,
+) -> Result<(), ResizedPngError> {
+    let (bit_depth, data) = pack_indices(
+        indices,
+        width as usize,
+        palette.len() / 3,
+        !options.interlaced,
+    );
+    let indexed = IndexedImage {
+        data,
+        bit_depth,
+        palette: palette.to_vec(),
+        trns: trns.to_vec(),
+    };
+
+    let fs = File::create(path)?;
+    let w = BufWriter::new(fs);
+
+    write_indexed_png_to(w, &indexed, width, height, options)?;
+
+    if options.verify {
+        verify_png(path, width, height)?;
+    }
 
     Ok(())
 }
 
-fn buf_to_rgba(raw_bytes: &[u8], info: &Info) -> Result<Vec<u8>, ResizedPngError> {
+fn write_indexed_png_to<W: Write>(
+    w: W,
+    indexed: &IndexedImage,
+    width: u32,
+    height: u32,
+    options: &WriteOptions,
+) -> Result<(), ResizedPngError> {
+    let mut encoder = new_encoder(w, width, height, options)?;
+    encoder.set_color(ColorType::Indexed);
+    encoder.set_depth(indexed.bit_depth);
+    encoder.set_palette(indexed.palette.as_slice());
+    if !indexed.trns.is_empty() {
+        encoder.set_trns(indexed.trns.as_slice());
+    }
+
+    let mut writer = encoder.write_header()?;
+    // パレットにない色は指せないので、その場合はbKGDを書き出さない。
+    let background_index = options.background.and_then(|background| {
+        indexed
+            .palette
+            .chunks_exact(3)
+            .position(|c| c == background)
+    });
+    if let Some(index) = background_index {
+        writer.write_chunk(chunk::bKGD, &[index as u8])?;
+    }
+
+    // インターレースする場合は8bitなので、1ピクセルは1byte。
+    write_image_data(&mut writer, &indexed.data, width, 1, options)
+}
+
+/// bKGDの中身。グレースケールはサンプルが1つ、RGBは3つで、それぞれ2byte
+///
+/// グレースケールの値は、画素と同じくRを使う。パレットには使えない。
+fn background_chunk(background: [u8; 3], color_type: ColorType, bit_depth: BitDepth) -> Vec<u8> {
+    let samples = match color_type {
+        ColorType::Grayscale | ColorType::GrayscaleAlpha => &background[..1],
+        _ => &background[..],
+    };
+
+    samples
+        .iter()
+        .flat_map(|v| match bit_depth {
+            // 上位と下位に同じ値を入れると、0..=255が0..=65535に均等に広がる。
+            BitDepth::Sixteen => [*v, *v],
+            _ => [0, *v],
+        })
+        .collect()
+}
+
+/// RGBAの並びを指定された色形式の並びに変換する
+fn rgba_to_color_type<T: Copy>(
+    buf: &[T],
+    color_type: ColorType,
+) -> Result<Cow<'_, [T]>, ResizedPngError> {
+    let pixels = buf.chunks_exact(4);
+
+    match color_type {
+        ColorType::Rgba => Ok(Cow::Borrowed(buf)),
+        ColorType::Rgb => Ok(Cow::Owned(
+            pixels.flat_map(|p| [p[0], p[1], p[2]]).collect(),
+        )),
+        ColorType::Grayscale => Ok(Cow::Owned(pixels.map(|p| p[0]).collect())),
+        ColorType::GrayscaleAlpha => Ok(Cow::Owned(pixels.flat_map(|p| [p[0], p[3]]).collect())),
+        ColorType::Indexed => Err(ResizedPngError::ParameterError(None)),
+    }
+}
+
+fn buf_to_rgba(
+    raw_bytes: &[u8],
+    info: &Info,
+    palette_index: PaletteIndexMode,
+) -> Result<Vec<u8>, ResizedPngError> {
     let mut result = match info.color_type {
         ColorType::Grayscale => {
-            let bytes = read_bytes_for_bit_depth_8(raw_bytes, info)?;
+            let bytes = read_significant_bytes(raw_bytes, info)?;
 
             bytes.iter().flat_map(|v| [*v, *v, *v, u8::MAX]).collect()
         }
         ColorType::GrayscaleAlpha => {
-            let bytes = read_bytes_for_bit_depth_8(raw_bytes, info)?;
+            let bytes = read_significant_bytes(raw_bytes, info)?;
 
             let mut result = Vec::new();
             let mut bytes_iter = bytes.iter();
@@ -66,7 +855,7 @@ fn buf_to_rgba(raw_bytes: &[u8], info: &Info) -> Result<Vec<u8>, ResizedPngError
             result
         }
         ColorType::Rgb => {
-            let bytes = read_bytes_for_bit_depth_8(raw_bytes, info)?;
+            let bytes = read_significant_bytes(raw_bytes, info)?;
 
             let mut result = Vec::new();
             let mut bytes_iter = bytes.iter();
@@ -82,20 +871,47 @@ fn buf_to_rgba(raw_bytes: &[u8], info: &Info) -> Result<Vec<u8>, ResizedPngError
 
             result
         }
-        ColorType::Rgba => read_bytes_for_bit_depth_8(raw_bytes, info)?,
+        ColorType::Rgba => read_significant_bytes(raw_bytes, info)?,
         ColorType::Indexed => {
             let indices = read_bytes_for_usize(raw_bytes, info)?;
             let palette = match &info.palette {
                 Some(v) => split_palette(v)?,
-                None => return Err(ResizedPngError::DecodingError),
+                None => return Err(ResizedPngError::DecodingError(None)),
+            };
+            // パレットの色は8bitなので、sBITは8bitに対する有効なビット数。
+            let palette: Vec<[u8; 3]> = match info.sbit.as_deref() {
+                Some(&[r, g, b]) if r.min(g).min(b) < 8 => palette
+                    .iter()
+                    .map(|p| {
+                        [
+                            significant_to_u8(p[0] as usize, 8, r),
+                            significant_to_u8(p[1] as usize, 8, g),
+                            significant_to_u8(p[2] as usize, 8, b),
+                        ]
+                    })
+                    .collect(),
+                _ => palette,
             };
 
             let mut result = Vec::new();
             let mut indices_iter = indices.iter();
             let pixel_len = info.width as usize * info.height as usize;
             for _i in 0..pixel_len {
-                let index = *indices_iter.next().ok_or(ResizedPngError::DecodingError)?;
-                let target_palette = palette.get(index).ok_or(ResizedPngError::DecodingError)?;
+                let index = *indices_iter
+                    .next()
+                    .ok_or(ResizedPngError::DecodingError(None))?;
+                let Some(target_palette) = palette.get(index) else {
+                    match palette_index {
+                        PaletteIndexMode::Strict => {
+                            return Err(ResizedPngError::DecodingError(None))
+                        }
+                        PaletteIndexMode::Lenient => {
+                            result.extend_from_slice(&[0, 0, 0, 0]);
+                            continue;
+                        }
+                    }
+                };
+                // tRNSはパレットより短くてもよく、足りない分は不透明。長い分は使わない。
                 let alpha = info
                     .trns
                     .as_ref()
@@ -112,33 +928,115 @@ fn buf_to_rgba(raw_bytes: &[u8], info: &Info) -> Result<Vec<u8>, ResizedPngError
         }
     };
 
+    if matches!(info.color_type, ColorType::Grayscale | ColorType::Rgb) {
+        apply_trns_color_key(&mut result, raw_bytes, info)?;
+    }
+
     let rgba_len = (info.width as usize * info.height as usize) * 4;
 
-    if result.len() < rgba_len {
-        Err(ResizedPngError::DecodingError)
+    if result.len() != rgba_len {
+        Err(ResizedPngError::DecodingError(None))
     } else {
-        result.resize(rgba_len, 0);
         Ok(result)
     }
 }
 
-fn read_bytes_for_bit_depth_8(buf: &[u8], info: &Info) -> Result<Vec<u8>, ResizedPngError> {
-    let f = match &info.bit_depth {
-        BitDepth::One => read_byte_for_bit_depth_8_when_bit_depth_one,
-        BitDepth::Two => read_byte_for_bit_depth_8_when_bit_depth_two,
-        BitDepth::Four => read_byte_for_bit_depth_8_when_bit_depth_four,
-        BitDepth::Eight => return Ok(buf.to_vec()),
-        BitDepth::Sixteen => return Ok(buf.iter().step_by(2).copied().collect()),
+/// tRNSで指定された色と一致するピクセルを透明にする。比較は元のビット深度の値で行う
+fn apply_trns_color_key(
+    rgba: &mut [u8],
+    raw_bytes: &[u8],
+    info: &Info,
+) -> Result<(), ResizedPngError> {
+    let Some(trns) = &info.trns else {
+        return Ok(());
     };
 
-    let mut result = Vec::new();
-    let mut tmp = [0; 8];
-    let mut buf_iter = buf.iter();
+    // pngクレートは、16bit未満のときは各サンプルを1byteに詰めて保持している。
+    let key: Vec<usize> = match info.bit_depth {
+        BitDepth::Sixteen => trns
+            .chunks_exact(2)
+            .map(|v| u16::from_be_bytes([v[0], v[1]]) as usize)
+            .collect(),
+        _ => trns.iter().map(|v| *v as usize).collect(),
+    };
+    // 色形式と合わないtRNSは無視する。
+    if key.len() != info.color_type.samples() {
+        return Ok(());
+    }
 
-    let channel_len = info.color_type.samples();
-    let width = info.width as usize;
-    let height = info.height as usize;
-    let line_length = width * channel_len;
+    let samples = read_bytes_for_usize(raw_bytes, info)?;
+
+    for (pixel, sample) in rgba
+        .chunks_exact_mut(4)
+        .zip(samples.chunks_exact(key.len()))
+    {
+        if sample == key.as_slice() {
+            pixel[3] = 0;
+        }
+    }
+
+    Ok(())
+}
+
+/// read_bytes_for_bit_depth_8と同じ。sBITがある場合は、有効なビットだけを8bitに広げ直す
+///
+/// 有効なビットより下は埋め草で、0で埋められていることもあるため、そのまま広げると明るい側が足りなくなる。
+fn read_significant_bytes(buf: &[u8], info: &Info) -> Result<Vec<u8>, ResizedPngError> {
+    let depth = info.bit_depth as u8;
+    let Some(sbit) = info
+        .sbit
+        .as_deref()
+        .filter(|v| v.len() == info.color_type.samples() && v.iter().any(|s| *s < depth))
+    else {
+        return read_bytes_for_bit_depth_8(buf, info);
+    };
+
+    let samples = read_bytes_for_usize(buf, info)?;
+
+    Ok(samples
+        .chunks_exact(sbit.len())
+        .flat_map(|pixel| {
+            pixel
+                .iter()
+                .zip(sbit)
+                .map(|(v, significant)| significant_to_u8(*v, depth, *significant))
+        })
+        .collect())
+}
+
+/// depthのビット数のサンプルの上位significantビットを、0..=255に広げる
+fn significant_to_u8(v: usize, depth: u8, significant: u8) -> u8 {
+    let v = v >> (depth - significant);
+    let max = (1 << significant) - 1;
+
+    ((v * u8::MAX as usize + max / 2) / max) as u8
+}
+
+fn read_bytes_for_bit_depth_8(buf: &[u8], info: &Info) -> Result<Vec<u8>, ResizedPngError> {
+    // 8bit以上は行に余りがないので、長さがちょうどでなければ大きさが合っていない。
+    let samples = info.width as usize * info.height as usize * info.color_type.samples();
+    let f = match &info.bit_depth {
+        BitDepth::One => read_byte_for_bit_depth_8_when_bit_depth_one,
+        BitDepth::Two => read_byte_for_bit_depth_8_when_bit_depth_two,
+        BitDepth::Four => read_byte_for_bit_depth_8_when_bit_depth_four,
+        BitDepth::Eight if buf.len() == samples => return Ok(buf.to_vec()),
+        BitDepth::Sixteen if buf.len() == samples * 2 => {
+            return Ok(buf
+                .chunks_exact(2)
+                .map(|v| u16_to_u8(u16::from_be_bytes([v[0], v[1]])))
+                .collect())
+        }
+        BitDepth::Eight | BitDepth::Sixteen => return Err(ResizedPngError::DecodingError(None)),
+    };
+
+    let mut result = Vec::new();
+    let mut tmp = [0; 8];
+    let mut buf_iter = buf.iter();
+
+    let channel_len = info.color_type.samples();
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let line_length = width * channel_len;
 
     let mut line = Vec::new();
     for _ in 0..height {
@@ -155,61 +1053,47 @@ fn read_bytes_for_bit_depth_8(buf: &[u8], info: &Info) -> Result<Vec<u8>, Resize
             }
         }
         if line.len() < line_length {
-            return Err(ResizedPngError::DecodingError);
+            return Err(ResizedPngError::DecodingError(None));
         }
 
         result.extend_from_slice(&line);
     }
 
+    // 行の余ったビットは読み捨てているので、残ったバイトがあれば大きさが合っていない。
+    if buf_iter.next().is_some() {
+        return Err(ResizedPngError::DecodingError(None));
+    }
+
     Ok(result)
 }
 
 fn read_byte_for_bit_depth_8_when_bit_depth_one(t: u8, output: &mut [u8; 8]) -> usize {
     for (i, element) in output.iter_mut().enumerate().take(8) {
-        *element = if (t << i) | 0b01111111 == u8::MAX {
-            u8::MAX
-        } else {
-            0
-        };
+        // Vxxxxxxx -> VVVVVVVV
+        *element = ((t >> (7 - i)) & 0b00000001) * 0b11111111;
     }
     8
 }
 
 fn read_byte_for_bit_depth_8_when_bit_depth_two(t: u8, output: &mut [u8; 8]) -> usize {
     for (i, element) in output.iter_mut().enumerate().take(4) {
-        let mut v = 0;
-        if (t << (i * 2)) | 0b01111111 == u8::MAX {
-            v += 0b10000000;
-        }
-        if (t << (i * 2 + 1)) | 0b01111111 == u8::MAX {
-            v += 0b01111111;
-        }
-
-        *element = v;
+        // VVxxxxxx -> VVVVVVVV
+        *element = ((t >> (6 - i * 2)) & 0b00000011) * 0b01010101;
     }
     4
 }
 
 fn read_byte_for_bit_depth_8_when_bit_depth_four(t: u8, output: &mut [u8; 8]) -> usize {
     for (i, element) in output.iter_mut().enumerate().take(2) {
-        let mut v = 0;
-        if (t << (i * 4)) | 0b01111111 == u8::MAX {
-            v += 0b10000000;
-        }
-        if (t << (i * 4 + 1)) | 0b01111111 == u8::MAX {
-            v += 0b01000000;
-        }
-        if (t << (i * 4 + 2)) | 0b01111111 == u8::MAX {
-            v += 0b00100000;
-        }
-        if (t << (i * 4 + 3)) | 0b01111111 == u8::MAX {
-            v += 0b00011111;
-        }
-        *element = v;
+        // VVVVxxxx -> VVVVVVVV
+        *element = ((t >> (4 - i * 4)) & 0b00001111) * 0b00010001;
     }
     2
 }
 
+/// サンプルを1つずつusizeにして並べる
+///
+/// 各行はバイトの境界から始まるので、行の最後のバイトの余ったビットは読み捨てる。
 fn read_bytes_for_usize(buf: &[u8], info: &Info) -> Result<Vec<usize>, ResizedPngError> {
     let f = match &info.bit_depth {
         BitDepth::One => read_byte_for_usize_when_bit_depth_one,
@@ -241,144 +1125,924 @@ fn read_bytes_for_usize(buf: &[u8], info: &Info) -> Result<Vec<usize>, ResizedPn
             }
         }
         if line.len() < line_length {
-            return Err(ResizedPngError::DecodingError);
+            return Err(ResizedPngError::DecodingError(None));
+        }
+
+        result.extend_from_slice(&line);
+    }
+
+    // 行の余ったビットは読み捨てているので、残ったバイトがあれば大きさが合っていない。
+    if buf_iter.next().is_some() {
+        return Err(ResizedPngError::DecodingError(None));
+    }
+
+    Ok(result)
+}
+
+fn read_byte_for_usize_when_bit_depth_one(
+    buf_iter: &mut Iter<u8>,
+    output: &mut [usize; 8],
+) -> Result<Option<usize>, ResizedPngError> {
+    Ok(buf_iter.next().map(|t| {
+        for (i, element) in output.iter_mut().enumerate().take(8) {
+            *element = if (t << i) | 0b01111111 == u8::MAX {
+                1
+            } else {
+                0
+            };
+        }
+        8
+    }))
+}
+
+fn read_byte_for_usize_when_bit_depth_two(
+    buf_iter: &mut Iter<u8>,
+    output: &mut [usize; 8],
+) -> Result<Option<usize>, ResizedPngError> {
+    Ok(buf_iter.next().map(|t| {
+        for (i, element) in output.iter_mut().enumerate().take(4) {
+            // VVxxxxxx -> 000000VV
+            *element = (((t << (i * 2)) >> 6) & 0b00000011) as usize;
+        }
+        4
+    }))
+}
+
+fn read_byte_for_usize_when_bit_depth_four(
+    buf_iter: &mut Iter<u8>,
+    output: &mut [usize; 8],
+) -> Result<Option<usize>, ResizedPngError> {
+    Ok(buf_iter.next().map(|t| {
+        for (i, element) in output.iter_mut().enumerate().take(2) {
+            // VVVVxxxx -> 0000VVVV
+            *element = (((t << (i * 4)) >> 4) & 0b00001111) as usize;
+        }
+        2
+    }))
+}
+
+fn read_byte_for_usize_when_bit_depth_eight(
+    buf_iter: &mut Iter<u8>,
+    output: &mut [usize; 8],
+) -> Result<Option<usize>, ResizedPngError> {
+    Ok(buf_iter.next().map(|t| {
+        output[0] = *t as usize;
+        1
+    }))
+}
+
+fn read_byte_for_usize_when_bit_depth_sixteen(
+    buf_iter: &mut Iter<u8>,
+    output: &mut [usize; 8],
+) -> Result<Option<usize>, ResizedPngError> {
+    match (buf_iter.next(), buf_iter.next()) {
+        (Some(t1), Some(t2)) => {
+            // 1111111122222222
+            output[0] = u16::from_be_bytes([*t1, *t2]) as usize;
+
+            Ok(Some(1))
+        }
+        (Some(_), None) => Err(ResizedPngError::DecodingError(None)),
+        (None, _) => Ok(None),
+    }
+}
+
+fn split_palette(raw: &[u8]) -> Result<Vec<[u8; 3]>, ResizedPngError> {
+    let mut result = Vec::new();
+    let palette_chunked = raw.chunks(3);
+
+    for p in palette_chunked {
+        if p.len() != 3 {
+            return Err(ResizedPngError::DecodingError(None));
+        }
+
+        result.push([p[0], p[1], p[2]]);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod read_dimensions {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_sample_png() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert_eq!(read_dimensions(&path).unwrap(), (100, 200));
+        }
+
+        #[test]
+        fn failed_when_invalid_png_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+
+            assert!(read_dimensions(&path).is_err());
+        }
+    }
+
+    mod read_image_data_from_bytes {
+        use super::*;
+
+        /// IHDRの幅と高さだけが大きい、中身のないpngのバイト列
+        fn huge_header_png(width: u32, height: u32) -> Vec<u8> {
+            let chunk = |kind: &[u8], data: &[u8]| {
+                let mut crc = flate2::Crc::new();
+                crc.update(kind);
+                crc.update(data);
+
+                let mut bytes = (data.len() as u32).to_be_bytes().to_vec();
+                bytes.extend_from_slice(kind);
+                bytes.extend_from_slice(data);
+                bytes.extend_from_slice(&crc.sum().to_be_bytes());
+                bytes
+            };
+
+            let mut ihdr = width.to_be_bytes().to_vec();
+            ihdr.extend_from_slice(&height.to_be_bytes());
+            // 8bit、RGBA、圧縮、フィルタ、インターレースなし。
+            ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+            let mut bytes = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+            bytes.extend(chunk(b"IHDR", &ihdr));
+            bytes.extend(chunk(b"IDAT", &[0x78, 0x9C, 0x03, 0x00]));
+            bytes.extend(chunk(b"IEND", &[]));
+            bytes
+        }
+
+        #[test]
+        fn failed_when_header_size_is_huge() {
+            let bytes = huge_header_png(100000, 100000);
+
+            assert!(bytes.len() < 100);
+            // デコーダの制限ではなく、確保する前の確認で止まる。
+            assert!(matches!(
+                read_image_data_from_bytes(&bytes),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+        }
+
+        #[test]
+        fn failed_when_header_size_is_over_limit() {
+            // 1辺はpngの上限の範囲内でも、画素数が上限を越える。
+            let bytes = huge_header_png(20001, 20000);
+
+            assert!(matches!(
+                read_image_data_from_bytes(&bytes),
+                Err(ResizedPngError::LimitsError(_))
+            ));
+        }
+
+        #[test]
+        fn checking_value_when_sample_png() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let bytes = std::fs::read(&path).unwrap();
+
+            let from_bytes = read_image_data_from_bytes(&bytes).unwrap();
+            let from_path = read_image_data(&path).unwrap();
+
+            assert_eq!(from_bytes.pixels, from_path.pixels);
+            assert_eq!(from_bytes.width, from_path.width);
+            assert_eq!(from_bytes.height, from_path.height);
+            assert_eq!(from_bytes.source_color, from_path.source_color);
+        }
+
+        #[test]
+        fn failed_when_truncated_bytes() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let bytes = std::fs::read(&path).unwrap();
+
+            assert!(read_image_data_from_bytes(&bytes[..bytes.len() / 2]).is_err());
+        }
+
+        #[test]
+        fn full_range_when_sbit_is_declared() {
+            // 有効なビットは上位4bitで、下位は0で埋められている。
+            let mut bytes = Vec::new();
+            let mut encoder = Encoder::new(&mut bytes, 3, 1);
+            encoder.set_color(ColorType::Grayscale);
+            encoder.set_depth(BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_chunk(chunk::sBIT, &[4]).unwrap();
+            writer.write_image_data(&[0x00, 0x80, 0xF0]).unwrap();
+            writer.finish().unwrap();
+
+            let image = read_image_data_from_bytes(&bytes).unwrap();
+            let gray: Vec<u8> = image.pixels.chunks_exact(4).map(|v| v[0]).collect();
+
+            // 0x8は8/15なので136。0xFは最大の255になる。
+            assert_eq!(gray, [0, 136, 255]);
+        }
+    }
+
+    mod read_image_data16_from_bytes {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_16bit_grayscale() {
+            let buf = [
+                0x1234,
+                0x1234,
+                0x1234,
+                u16::MAX,
+                0xFEDC,
+                0xFEDC,
+                0xFEDC,
+                u16::MAX,
+            ];
+            let write_options = WriteOptions {
+                color_type: ColorType::Grayscale,
+                ..Default::default()
+            };
+            let bytes = encode_png16(&buf, 2, 1, &write_options).unwrap();
+
+            let image = read_image_data16_from_bytes(&bytes).unwrap().unwrap();
+
+            assert_eq!(image.pixels, buf);
+            assert_eq!((image.width, image.height), (2, 1));
+            assert_eq!(image.source_color, SourceColorType::Grayscale);
+        }
+
+        #[test]
+        fn none_when_8bit_png() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let bytes = std::fs::read(&path).unwrap();
+
+            assert!(read_image_data16_from_bytes(&bytes).unwrap().is_none());
+        }
+    }
+
+    mod read_image_data {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        fn write_with_trns(
+            path: &PathBuf,
+            data: &[u8],
+            width: u32,
+            color_type: ColorType,
+            bit_depth: BitDepth,
+            trns: &[u8],
+        ) {
+            let w = BufWriter::new(File::create(path).unwrap());
+            let mut encoder = Encoder::new(w, width, 1);
+            encoder.set_color(color_type);
+            encoder.set_depth(bit_depth);
+            encoder.set_trns(trns.to_vec());
+
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(data).unwrap();
+        }
+
+        #[test]
+        fn success_when_valid_png_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            let ImageData {
+                width,
+                height,
+                source_color,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 100);
+            assert_eq!(height, 200);
+            assert_eq!(source_color, SourceColorType::Rgba);
+        }
+
+        #[test]
+        fn first_frame_when_apng_has_extra_default_image() {
+            // IDATは青い既定の画像で、アニメーションは赤、緑の順。
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample_apng.png");
+
+            let ImageData {
+                pixels,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!((width, height), (4, 3));
+            assert_eq!(pixels[..4], [255, 0, 0, 255]);
+        }
+
+        #[test]
+        fn transparent_when_rgb_matches_trns() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("rgb_trns.png");
+
+            let data = [255, 0, 255, 10, 20, 30];
+            write_with_trns(
+                &path,
+                &data,
+                2,
+                ColorType::Rgb,
+                BitDepth::Eight,
+                &[0, 255, 0, 0, 0, 255],
+            );
+
+            let ImageData { pixels: data, .. } = read_image_data(&path).unwrap();
+
+            assert_eq!(data, [255, 0, 255, 0, 10, 20, 30, 255]);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn transparent_when_grayscale_matches_trns() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("grayscale_trns.png");
+
+            let data = [128, 0, 128];
+            write_with_trns(
+                &path,
+                &data,
+                3,
+                ColorType::Grayscale,
+                BitDepth::Eight,
+                &[0, 128],
+            );
+
+            let ImageData { pixels: data, .. } = read_image_data(&path).unwrap();
+
+            assert_eq!(data, [128, 128, 128, 0, 0, 0, 0, 255, 128, 128, 128, 0]);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn transparent_only_exact_value_when_16bit_rgb_matches_trns() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("rgb16_trns.png");
+
+            // 8bitに丸めると同じ色になるが、16bitでは異なる2つのピクセル。
+            let data = [0x12, 0x34, 0, 0, 0, 0, 0x12, 0x35, 0, 0, 0, 0];
+            write_with_trns(
+                &path,
+                &data,
+                2,
+                ColorType::Rgb,
+                BitDepth::Sixteen,
+                &[0x12, 0x34, 0, 0, 0, 0],
+            );
+
+            let ImageData { pixels: data, .. } = read_image_data(&path).unwrap();
+
+            assert_eq!(data[3], 0);
+            assert_eq!(data[7], 255);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_invalid_png_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+
+            assert!(read_image_data(&path).is_err());
+        }
+    }
+
+    mod verify_png_bytes {
+        use super::*;
+
+        fn sample_bytes() -> Vec<u8> {
+            encode_png(&[10; 4 * 4 * 4], 4, 4, &WriteOptions::default()).unwrap()
+        }
+
+        #[test]
+        fn success_when_valid_png() {
+            assert!(verify_png_bytes(&sample_bytes(), 4, 4).is_ok());
+        }
+
+        #[test]
+        fn failed_when_truncated() {
+            let bytes = sample_bytes();
+
+            for len in [bytes.len() - 1, bytes.len() - 12, bytes.len() / 2, 8] {
+                assert!(
+                    matches!(
+                        verify_png_bytes(&bytes[..len], 4, 4),
+                        Err(ResizedPngError::EncodingError(None))
+                    ),
+                    "{len}"
+                );
+            }
+        }
+
+        #[test]
+        fn failed_when_crc_is_broken() {
+            let mut bytes = sample_bytes();
+            // IENDの直前、IDATのCRCの最後のバイト。
+            let index = bytes.len() - 13;
+            bytes[index] ^= 0xFF;
+
+            assert!(matches!(
+                verify_png_bytes(&bytes, 4, 4),
+                Err(ResizedPngError::EncodingError(None))
+            ));
+        }
+
+        #[test]
+        fn failed_when_size_differs() {
+            assert!(matches!(
+                verify_png_bytes(&sample_bytes(), 4, 3),
+                Err(ResizedPngError::EncodingError(None))
+            ));
+        }
+    }
+
+    mod write_png {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn success_when_verify_and_valid_parameter() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            let buf = [1, 2, 3, 4, 5, 6, 7, 8];
+            let options = WriteOptions {
+                verify: true,
+                ..Default::default()
+            };
+
+            write_png(&path, &buf, 2, 1, &options).unwrap();
+            write_png16(&path, &[1, 2, 3, 4, 5, 6, 7, 8], 2, 1, &options).unwrap();
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn success_when_valid_parameter() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            let buf = [1, 2, 3, 4, 5, 6, 7, 8];
+            let width = 2;
+            let height = 1;
+
+            write_png(&path, &buf, width, height, &WriteOptions::default()).unwrap();
+
+            assert!(path.exists());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn round_trip_when_color_type_is_grayscale() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            let buf = [0, 0, 0, 255, 100, 100, 100, 255, 200, 200, 200, 255];
+            let width = 3;
+            let height = 1;
+
+            let options = WriteOptions {
+                color_type: ColorType::Grayscale,
+                ..Default::default()
+            };
+            write_png(&path, &buf, width, height, &options).unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Grayscale);
+
+            let ImageData { pixels: data, .. } = read_image_data(&path).unwrap();
+            assert_eq!(data, buf);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn round_trip_when_color_type_is_indexed() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            // 3x2で3色、1色は半透明。
+            let buf = [
+                [255, 0, 0, 255],
+                [0, 255, 0, 128],
+                [255, 0, 0, 255],
+                [0, 0, 255, 255],
+                [0, 0, 255, 255],
+                [0, 255, 0, 128],
+            ]
+            .concat();
+
+            let options = WriteOptions {
+                color_type: ColorType::Indexed,
+                ..Default::default()
+            };
+            write_png(&path, &buf, 3, 2, &options).unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Indexed);
+            assert_eq!(reader.info().bit_depth, BitDepth::Two);
+
+            let ImageData { pixels: data, .. } = read_image_data(&path).unwrap();
+            assert_eq!(data, buf);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn rgba_when_color_type_is_indexed_and_too_many_colors() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            let buf: Vec<u8> = (0..300u32)
+                .flat_map(|i| [(i % 256) as u8, (i / 256) as u8, 0, u8::MAX])
+                .collect();
+
+            let options = WriteOptions {
+                color_type: ColorType::Indexed,
+                ..Default::default()
+            };
+            write_png(&path, &buf, 300, 1, &options).unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Rgba);
+
+            let ImageData { pixels: data, .. } = read_image_data(&path).unwrap();
+            assert_eq!(data, buf);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn indexed_when_too_many_colors_and_quantize() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            // 32x32で1024色の、半透明を含むグラデーション。
+            let buf: Vec<u8> = (0..32u32)
+                .flat_map(|y| {
+                    (0..32u32)
+                        .flat_map(move |x| [(x * 8) as u8, (y * 8) as u8, 128, (255 - y * 4) as u8])
+                })
+                .collect();
+
+            let options = WriteOptions {
+                color_type: ColorType::Indexed,
+                quantize: true,
+                ..Default::default()
+            };
+            write_png(&path, &buf, 32, 32, &options).unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Indexed);
+            assert!(reader.info().palette.as_ref().unwrap().len() <= 256 * 3);
+            assert!(reader.info().trns.is_some());
+
+            let ImageData { pixels: data, .. } = read_image_data(&path).unwrap();
+            let error: u64 = data
+                .iter()
+                .zip(&buf)
+                .map(|(a, b)| a.abs_diff(*b) as u64)
+                .sum();
+            assert!((error as f64 / buf.len() as f64) < 8.0);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn indexed_when_too_many_colors_and_dither() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            // 32x32で1024色のグラデーション。
+            let buf: Vec<u8> = (0..32u32)
+                .flat_map(|y| {
+                    (0..32u32).flat_map(move |x| [(x * 8) as u8, (y * 8) as u8, 128, 255])
+                })
+                .collect();
+
+            let options = WriteOptions {
+                color_type: ColorType::Indexed,
+                quantize: true,
+                dither: true,
+                ..Default::default()
+            };
+            write_png(&path, &buf, 32, 32, &options).unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Indexed);
+            assert_eq!((reader.info().width, reader.info().height), (32, 32));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_background_is_set() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            let buf = [0, 0, 0, 0, 255, 255, 255, 255];
+
+            let options = WriteOptions {
+                background: Some([255, 0, 0]),
+                ..Default::default()
+            };
+            write_png(&path, &buf, 2, 1, &options).unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(
+                reader.info().bkgd.as_deref(),
+                Some([0, 255, 0, 0, 0, 0].as_slice())
+            );
+
+            let ImageData { pixels: data, .. } = read_image_data(&path).unwrap();
+            assert_eq!(data, buf);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_background_is_set_and_indexed() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            let buf = [0, 0, 255, 255, 255, 0, 0, 255];
+
+            let options = WriteOptions {
+                color_type: ColorType::Indexed,
+                background: Some([255, 0, 0]),
+                ..Default::default()
+            };
+            write_png(&path, &buf, 2, 1, &options).unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().bkgd.as_deref(), Some([1].as_slice()));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn no_background_by_default() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            write_png(&path, &[1, 2, 3, 4], 1, 1, &WriteOptions::default()).unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().bkgd, None);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_icc_profile_is_set() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            let icc_profile = b"dummy icc profile".to_vec();
+
+            let options = WriteOptions {
+                icc_profile: Some(icc_profile.clone()),
+                ..Default::default()
+            };
+            write_png(&path, &[1, 2, 3, 4], 1, 1, &options).unwrap();
+
+            let ImageData {
+                icc_profile: read_profile,
+                ..
+            } = read_image_data(&path).unwrap();
+            assert_eq!(read_profile, Some(icc_profile));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_text_chunks_are_set() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            let text_chunks = vec![
+                ("Author".to_string(), "tukinami seika".to_string()),
+                ("Copyright".to_string(), "MIT".to_string()),
+            ];
+
+            let options = WriteOptions {
+                text_chunks: text_chunks.clone(),
+                ..Default::default()
+            };
+            write_png(&path, &[1, 2, 3, 4], 1, 1, &options).unwrap();
+
+            let ImageData {
+                text_chunks: read_text_chunks,
+                ..
+            } = read_image_data(&path).unwrap();
+            assert_eq!(read_text_chunks, text_chunks);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn same_pixels_when_interlaced() {
+            let out_dir = tempdir().unwrap();
+
+            let width = 11;
+            let height = 9;
+            let buf: Vec<u8> = (0..width * height)
+                .flat_map(|i| [(i * 7) as u8, (i * 13) as u8, (i * 29) as u8, (i * 3) as u8])
+                .collect();
+
+            for (color_type, bit_depth) in [
+                (ColorType::Rgba, BitDepth::Eight),
+                (ColorType::Rgba, BitDepth::Sixteen),
+                (ColorType::Indexed, BitDepth::Eight),
+            ] {
+                let plain_path = out_dir.path().join("plain.png");
+                let interlaced_path = out_dir.path().join("interlaced.png");
+
+                let options = WriteOptions {
+                    color_type,
+                    bit_depth,
+                    ..Default::default()
+                };
+                write_png(&plain_path, &buf, width, height, &options).unwrap();
+                let options = WriteOptions {
+                    interlaced: true,
+                    ..options
+                };
+                write_png(&interlaced_path, &buf, width, height, &options).unwrap();
+
+                let decoder = Decoder::new(File::open(&interlaced_path).unwrap());
+                let reader = decoder.read_info().unwrap();
+                assert!(reader.info().interlaced);
+
+                let plain = read_image_data(&plain_path).unwrap();
+                let interlaced = read_image_data(&interlaced_path).unwrap();
+                assert_eq!(interlaced.width, width);
+                assert_eq!(interlaced.height, height);
+                assert_eq!(interlaced.pixels, plain.pixels);
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_bit_depth_is_sixteen() {
+            let out_dir = tempdir().unwrap();
+
+            let path = out_dir.path().join("test.png");
+            let buf = [0, 1, 2, 3, 128, 200, 254, 255];
+            let width = 2;
+            let height = 1;
+
+            let options = WriteOptions {
+                bit_depth: BitDepth::Sixteen,
+                ..Default::default()
+            };
+            write_png(&path, &buf, width, height, &options).unwrap();
+
+            let decoder = Decoder::new(File::open(&path).unwrap());
+            let mut reader = decoder.read_info().unwrap();
+            let mut data = vec![0; reader.output_buffer_size()];
+            let output_info = reader.next_frame(&mut data).unwrap();
+
+            assert_eq!(output_info.bit_depth, BitDepth::Sixteen);
+            assert_eq!(output_info.buffer_size(), buf.len() * 2);
+            for (sample, expected) in data.chunks(2).zip(buf.iter()) {
+                assert_eq!(sample[0], *expected);
+            }
+
+            out_dir.close().unwrap();
         }
 
-        result.extend_from_slice(&line);
-    }
+        #[test]
+        fn failed_when_bit_depth_is_not_eight_or_sixteen() {
+            let out_dir = tempdir().unwrap();
 
-    Ok(result)
-}
+            let path = out_dir.path().join("test.png");
+            let buf = [1, 2, 3, 4];
 
-fn read_byte_for_usize_when_bit_depth_one(
-    buf_iter: &mut Iter<u8>,
-    output: &mut [usize; 8],
-) -> Result<Option<usize>, ResizedPngError> {
-    Ok(buf_iter.next().map(|t| {
-        for (i, element) in output.iter_mut().enumerate().take(8) {
-            *element = if (t << i) | 0b01111111 == u8::MAX {
-                1
-            } else {
-                0
+            let options = WriteOptions {
+                bit_depth: BitDepth::Four,
+                ..Default::default()
             };
-        }
-        8
-    }))
-}
+            assert!(write_png(&path, &buf, 1, 1, &options).is_err());
 
-fn read_byte_for_usize_when_bit_depth_two(
-    buf_iter: &mut Iter<u8>,
-    output: &mut [usize; 8],
-) -> Result<Option<usize>, ResizedPngError> {
-    Ok(buf_iter.next().map(|t| {
-        for (i, element) in output.iter_mut().enumerate().take(4) {
-            // VVxxxxxx -> 000000VV
-            *element = (((t << (i * 2)) >> 6) & 0b00000011) as usize;
+            out_dir.close().unwrap();
         }
-        4
-    }))
-}
 
-fn read_byte_for_usize_when_bit_depth_four(
-    buf_iter: &mut Iter<u8>,
-    output: &mut [usize; 8],
-) -> Result<Option<usize>, ResizedPngError> {
-    Ok(buf_iter.next().map(|t| {
-        for (i, element) in output.iter_mut().enumerate().take(2) {
-            // VVVVxxxx -> 0000VVVV
-            *element = (((t << (i * 4)) >> 4) & 0b00001111) as usize;
-        }
-        2
-    }))
-}
+        #[test]
+        fn best_is_not_larger_than_fast() {
+            let out_dir = tempdir().unwrap();
 
-fn read_byte_for_usize_when_bit_depth_eight(
-    buf_iter: &mut Iter<u8>,
-    output: &mut [usize; 8],
-) -> Result<Option<usize>, ResizedPngError> {
-    Ok(buf_iter.next().map(|t| {
-        output[0] = *t as usize;
-        1
-    }))
-}
+            let fast_path = out_dir.path().join("fast.png");
+            let best_path = out_dir.path().join("best.png");
+            let width = 64;
+            let height = 64;
+            let buf: Vec<u8> = (0..width * height)
+                .flat_map(|i| [(i % width) as u8, (i / width) as u8, 0, u8::MAX])
+                .collect();
+
+            let fast_options = WriteOptions {
+                compression: Compression::Fast,
+                ..Default::default()
+            };
+            let best_options = WriteOptions {
+                compression: Compression::Best,
+                ..Default::default()
+            };
+            write_png(&fast_path, &buf, width, height, &fast_options).unwrap();
+            write_png(&best_path, &buf, width, height, &best_options).unwrap();
 
-fn read_byte_for_usize_when_bit_depth_sixteen(
-    buf_iter: &mut Iter<u8>,
-    output: &mut [usize; 8],
-) -> Result<Option<usize>, ResizedPngError> {
-    match (buf_iter.next(), buf_iter.next()) {
-        (Some(t1), Some(t2)) => {
-            // 1111111122222222
-            output[0] = u16::from_be_bytes([*t1, *t2]) as usize;
+            let fast_len = std::fs::metadata(&fast_path).unwrap().len();
+            let best_len = std::fs::metadata(&best_path).unwrap().len();
+            assert!(best_len <= fast_len);
 
-            Ok(Some(1))
+            out_dir.close().unwrap();
         }
-        (Some(_), None) => Err(ResizedPngError::DecodingError),
-        (None, _) => Ok(None),
     }
-}
 
-fn split_palette(raw: &[u8]) -> Result<Vec<[u8; 3]>, ResizedPngError> {
-    let mut result = Vec::new();
-    let palette_chunked = raw.chunks(3);
+    mod encode_adam7 {
+        use super::*;
 
-    for p in palette_chunked {
-        if p.len() != 3 {
-            return Err(ResizedPngError::DecodingError);
-        }
+        use std::io::Read;
 
-        result.push([p[0], p[1], p[2]]);
-    }
+        use flate2::read::ZlibDecoder;
 
-    Ok(result)
-}
+        fn decode(data: &[u8]) -> Vec<u8> {
+            let mut raw = Vec::new();
+            ZlibDecoder::new(data).read_to_end(&mut raw).unwrap();
+            raw
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        #[test]
+        fn only_first_pass_when_1x1() {
+            let idat = encode_adam7(&[5], 1, 1, Compression::Default).unwrap();
 
-    mod read_image_data {
-        use super::*;
+            assert_eq!(decode(&idat), [0, 5]);
+        }
 
         #[test]
-        fn success_when_valid_png_path() {
-            let path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
-
-            let (_data, width, height) = read_image_data(&path).unwrap();
+        fn checking_value_when_2x2() {
+            // 1番目のパスが(0, 0)、6番目が(1, 0)、7番目が下の行。
+            let idat = encode_adam7(&[1, 2, 3, 4], 2, 1, Compression::Default).unwrap();
 
-            assert_eq!(width, 100);
-            assert_eq!(height, 200);
+            assert_eq!(decode(&idat), [0, 1, 0, 2, 0, 3, 4]);
         }
+    }
+
+    mod encode_png {
+        use super::*;
 
         #[test]
-        fn failed_when_invalid_png_path() {
-            let path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+        fn checking_value_when_valid_parameter() {
+            let buf = [1, 2, 3, 4, 5, 6, 7, 8];
 
-            assert!(read_image_data(&path).is_err());
+            let bytes = encode_png(&buf, 2, 1, &WriteOptions::default()).unwrap();
+
+            let mut reader = Decoder::new(bytes.as_slice()).read_info().unwrap();
+            let mut decoded = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut decoded).unwrap();
+
+            assert_eq!(decoded, buf);
         }
     }
 
-    mod write_png {
+    mod encode_png16 {
         use super::*;
 
-        use tempfile::tempdir;
-
         #[test]
-        fn success_when_valid_parameter() {
-            let out_dir = tempdir().unwrap();
-
-            let path = out_dir.path().join("test.png");
-            let buf = [1, 2, 3, 4, 5, 6, 7, 8];
-            let width = 2;
-            let height = 1;
+        fn checking_value_when_rgb() {
+            let buf = [0x0102, 0x0304, 0x0506, u16::MAX];
+            let write_options = WriteOptions {
+                color_type: ColorType::Rgb,
+                ..Default::default()
+            };
 
-            write_png(&path, &buf, width, height).unwrap();
+            let bytes = encode_png16(&buf, 1, 1, &write_options).unwrap();
 
-            assert!(path.exists());
+            let mut reader = Decoder::new(bytes.as_slice()).read_info().unwrap();
+            assert_eq!(reader.info().bit_depth, BitDepth::Sixteen);
+            assert_eq!(reader.info().color_type, ColorType::Rgb);
+            let mut decoded = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut decoded).unwrap();
 
-            out_dir.close().unwrap();
+            assert_eq!(decoded, [1, 2, 3, 4, 5, 6]);
         }
     }
 
@@ -386,6 +2050,64 @@ mod tests {
         use super::*;
         use std::borrow::Cow;
 
+        #[test]
+        fn full_range_when_rgb16_with_sbit() {
+            // 上位10bitが有効。最大の値の下位6bitは0。
+            let buf = [0xFF, 0xC0, 0x80, 0x00, 0x00, 0x00];
+            let mut info = Info::with_size(1, 1);
+            info.color_type = ColorType::Rgb;
+            info.bit_depth = BitDepth::Sixteen;
+            info.sbit = Some(Cow::Borrowed(&[10, 10, 10]));
+
+            assert_eq!(
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
+                vec![255, 128, 0, 255]
+            );
+        }
+
+        #[test]
+        fn failed_when_rgba8_buffer_is_too_long() {
+            let buf = [1, 2, 3, 4, 5];
+            let mut info = Info::with_size(1, 1);
+            info.color_type = ColorType::Rgba;
+            info.bit_depth = BitDepth::Eight;
+
+            assert!(matches!(
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict),
+                Err(ResizedPngError::DecodingError(None))
+            ));
+        }
+
+        #[test]
+        fn full_range_when_palette_with_sbit() {
+            let buf = [0, 1];
+            let mut info = Info::with_size(2, 1);
+            info.color_type = ColorType::Indexed;
+            info.bit_depth = BitDepth::Eight;
+            // 有効なビットは上位5bit。
+            info.palette = Some(Cow::Borrowed(&[0xF8, 0xF8, 0xF8, 0x80, 0x00, 0x08]));
+            info.sbit = Some(Cow::Borrowed(&[5, 5, 5]));
+
+            assert_eq!(
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
+                vec![255, 255, 255, 255, 132, 0, 8, 255]
+            );
+        }
+
+        #[test]
+        fn unchanged_when_sbit_is_same_as_bit_depth() {
+            let buf = [0x12, 0x34];
+            let mut info = Info::with_size(1, 1);
+            info.color_type = ColorType::GrayscaleAlpha;
+            info.bit_depth = BitDepth::Eight;
+            info.sbit = Some(Cow::Borrowed(&[8, 8]));
+
+            assert_eq!(
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
+                vec![0x12, 0x12, 0x12, 0x34]
+            );
+        }
+
         #[test]
         fn success_when_valid_bytes_for_grayscale() {
             let buf = [0b10010000];
@@ -394,7 +2116,7 @@ mod tests {
             info.bit_depth = BitDepth::One;
 
             assert_eq!(
-                buf_to_rgba(&buf, &info).unwrap(),
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
                 vec![u8::MAX, u8::MAX, u8::MAX, u8::MAX, 0, 0, 0, u8::MAX,]
             );
         }
@@ -407,10 +2129,10 @@ mod tests {
             info.bit_depth = BitDepth::Two;
 
             assert_eq!(
-                buf_to_rgba(&buf, &info).unwrap(),
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
                 vec![
-                    0b10000000, 0b10000000, 0b10000000, 0b01111111, 0, 0, 0, 0b11111111,
-                    0b01111111, 0b01111111, 0b01111111, 0b10000000, 0b11111111, 0b11111111,
+                    0b10101010, 0b10101010, 0b10101010, 0b01010101, 0, 0, 0, 0b11111111,
+                    0b01010101, 0b01010101, 0b01010101, 0b10101010, 0b11111111, 0b11111111,
                     0b11111111, 0,
                 ]
             );
@@ -426,23 +2148,23 @@ mod tests {
             info.bit_depth = BitDepth::Four;
 
             assert_eq!(
-                buf_to_rgba(&buf, &info).unwrap(),
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
                 vec![
-                    0b10011111,
-                    0b00111111,
-                    0b01100000,
+                    0b10011001,
+                    0b00110011,
+                    0b01100110,
                     u8::MAX,
-                    0b11000000,
-                    0b10011111,
-                    0b00111111,
+                    0b11001100,
+                    0b10011001,
+                    0b00110011,
                     u8::MAX,
-                    0b01100000,
-                    0b11000000,
-                    0b10011111,
+                    0b01100110,
+                    0b11001100,
+                    0b10011001,
                     u8::MAX,
-                    0b00111111,
-                    0b01100000,
-                    0b11000000,
+                    0b00110011,
+                    0b01100110,
+                    0b11001100,
                     u8::MAX,
                 ]
             );
@@ -456,7 +2178,7 @@ mod tests {
             info.bit_depth = BitDepth::Eight;
 
             assert_eq!(
-                buf_to_rgba(&buf, &info).unwrap(),
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
                 vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
             );
         }
@@ -478,7 +2200,7 @@ mod tests {
             info.palette = Some(Cow::from(&palette_raw[..]));
 
             assert_eq!(
-                buf_to_rgba(&buf, &info).unwrap(),
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
                 vec![
                     1,
                     2,
@@ -516,7 +2238,7 @@ mod tests {
             palette_raw[5] = 6;
             info.palette = Some(Cow::from(&palette_raw[..]));
 
-            assert!(buf_to_rgba(&buf, &info).is_err());
+            assert!(buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).is_err());
         }
 
         #[test]
@@ -529,7 +2251,85 @@ mod tests {
             let palette_raw: [u8; 1] = [0; 1];
             info.palette = Some(Cow::from(&palette_raw[..]));
 
-            assert!(buf_to_rgba(&buf, &info).is_err());
+            assert!(buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).is_err());
+        }
+
+        #[test]
+        fn checking_value_when_indexed_row_does_not_end_on_byte_boundary() {
+            // 詰め物のビットが次の行に混ざると、2行目の色がずれる。
+            let buf = [0b100_11111, 0b011_11111];
+            let mut info = Info::with_size(3, 2);
+            info.color_type = ColorType::Indexed;
+            info.bit_depth = BitDepth::One;
+
+            let palette_raw = [10, 20, 30, 40, 50, 60];
+            info.palette = Some(Cow::from(&palette_raw[..]));
+
+            assert_eq!(
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
+                [
+                    [40, 50, 60, 255],
+                    [10, 20, 30, 255],
+                    [10, 20, 30, 255],
+                    [10, 20, 30, 255],
+                    [40, 50, 60, 255],
+                    [40, 50, 60, 255],
+                ]
+                .concat()
+            );
+        }
+
+        #[test]
+        fn checking_value_when_trns_is_shorter_than_palette() {
+            let buf = [0b0001_1000];
+            let mut info = Info::with_size(4, 1);
+            info.color_type = ColorType::Indexed;
+            info.bit_depth = BitDepth::Two;
+
+            let palette_raw = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+            info.palette = Some(Cow::from(&palette_raw[..]));
+            let trns_raw = [10];
+            info.trns = Some(Cow::from(&trns_raw[..]));
+
+            assert_eq!(
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).unwrap(),
+                [[1, 2, 3, 10], [4, 5, 6, 255], [7, 8, 9, 255], [1, 2, 3, 10]].concat()
+            );
+        }
+
+        #[test]
+        fn failed_when_index_is_out_of_palette_in_strict_mode() {
+            let buf = [0b0011_0000];
+            let mut info = Info::with_size(4, 1);
+            info.color_type = ColorType::Indexed;
+            info.bit_depth = BitDepth::Two;
+
+            let palette_raw = [1, 2, 3, 4, 5, 6];
+            info.palette = Some(Cow::from(&palette_raw[..]));
+
+            assert!(matches!(
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Strict),
+                Err(ResizedPngError::DecodingError(None))
+            ));
+        }
+
+        #[test]
+        fn checking_value_when_index_is_out_of_palette_in_lenient_mode() {
+            let buf = [0b0011_0100];
+            let mut info = Info::with_size(4, 1);
+            info.color_type = ColorType::Indexed;
+            info.bit_depth = BitDepth::Two;
+
+            let palette_raw = [1, 2, 3, 4, 5, 6];
+            info.palette = Some(Cow::from(&palette_raw[..]));
+            // tRNSがパレットより長くても、範囲外の番号には使わない。
+            let trns_raw = [255, 255, 255, 255];
+            info.trns = Some(Cow::from(&trns_raw[..]));
+
+            assert_eq!(
+                buf_to_rgba(&buf, &info, PaletteIndexMode::Lenient).unwrap(),
+                [[1, 2, 3, 255], [0, 0, 0, 0], [4, 5, 6, 255], [1, 2, 3, 255]].concat()
+            );
         }
 
         #[test]
@@ -541,7 +2341,7 @@ mod tests {
 
             info.palette = None;
 
-            assert!(buf_to_rgba(&buf, &info).is_err());
+            assert!(buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).is_err());
         }
 
         #[test]
@@ -551,7 +2351,7 @@ mod tests {
             info.color_type = ColorType::Rgba;
             info.bit_depth = BitDepth::Eight;
 
-            assert!(buf_to_rgba(&buf, &info).is_err());
+            assert!(buf_to_rgba(&buf, &info, PaletteIndexMode::Strict).is_err());
         }
     }
 
@@ -597,7 +2397,7 @@ mod tests {
 
             assert_eq!(
                 read_bytes_for_bit_depth_8(&buf, &info).unwrap(),
-                vec![0b10000000, 0b01111111, 0b11111111, 0b00000000, 0b01111111, 0b10000000,]
+                vec![0b10101010, 0b01010101, 0b11111111, 0b00000000, 0b01010101, 0b10101010,]
             );
         }
 
@@ -610,10 +2410,63 @@ mod tests {
 
             assert_eq!(
                 read_bytes_for_bit_depth_8(&buf, &info).unwrap(),
-                vec![0b10011111, 0b11000000, 0b01100000, 0b00111111]
+                vec![0b10011001, 0b11001100, 0b01100110, 0b00110011]
+            );
+        }
+
+        #[test]
+        fn checking_value_when_rows_have_padding_bits() {
+            // 1行は3ビットなので、各行のバイトの残りの5ビットは読み捨てる。
+            let buf = [0b10111111, 0b01000000];
+            let mut info = Info::with_size(3, 2);
+            info.color_type = ColorType::Grayscale;
+            info.bit_depth = BitDepth::One;
+
+            assert_eq!(
+                read_bytes_for_bit_depth_8(&buf, &info).unwrap(),
+                vec![u8::MAX, 0, u8::MAX, 0, u8::MAX, 0]
             );
         }
 
+        #[test]
+        fn failed_when_buffer_is_too_long() {
+            let buf = [0b10000000, 0b01000000, 0b11111111];
+            let mut info = Info::with_size(2, 2);
+            info.color_type = ColorType::Grayscale;
+            info.bit_depth = BitDepth::One;
+
+            assert!(matches!(
+                read_bytes_for_bit_depth_8(&buf, &info),
+                Err(ResizedPngError::DecodingError(None))
+            ));
+        }
+
+        #[test]
+        fn failed_when_buffer_is_too_long_and_bit_depth_eight() {
+            let buf = [1, 2, 3];
+            let mut info = Info::with_size(1, 2);
+            info.color_type = ColorType::Grayscale;
+            info.bit_depth = BitDepth::Eight;
+
+            assert!(matches!(
+                read_bytes_for_bit_depth_8(&buf, &info),
+                Err(ResizedPngError::DecodingError(None))
+            ));
+        }
+
+        #[test]
+        fn failed_when_buffer_is_too_short_and_bit_depth_sixteen() {
+            let buf = [0, 1, 2];
+            let mut info = Info::with_size(2, 1);
+            info.color_type = ColorType::Grayscale;
+            info.bit_depth = BitDepth::Sixteen;
+
+            assert!(matches!(
+                read_bytes_for_bit_depth_8(&buf, &info),
+                Err(ResizedPngError::DecodingError(None))
+            ));
+        }
+
         #[test]
         fn checking_value_when_bit_depth_eight() {
             let buf = [0b10011100, 0b01100011];
@@ -639,11 +2492,52 @@ mod tests {
                 vec![0b10011100]
             );
         }
+
+        #[test]
+        fn checking_rounded_value_when_bit_depth_sixteen() {
+            let buf = [0x00, 0x80, 0x00, 0x81, 0xFF, 0x00, 0xFF, 0x7F, 0x12, 0xFF];
+            let mut info = Info::with_size(5, 1);
+            info.color_type = ColorType::Grayscale;
+            info.bit_depth = BitDepth::Sixteen;
+
+            assert_eq!(
+                read_bytes_for_bit_depth_8(&buf, &info).unwrap(),
+                vec![0, 1, 254, 255, 0x13]
+            );
+        }
+    }
+
+    mod read_byte_for_bit_depth_8_when_bit_depth_two {
+        use super::*;
+
+        #[test]
+        fn uniform_ramp_when_each_level() {
+            let mut output = [0; 8];
+
+            let size = read_byte_for_bit_depth_8_when_bit_depth_two(0b00011011, &mut output);
+
+            assert_eq!(size, 4);
+            assert_eq!(output[..4], [0, 85, 170, 255]);
+        }
     }
 
     mod read_bytes_for_usize {
         use super::*;
 
+        #[test]
+        fn checking_value_when_row_does_not_end_on_byte_boundary() {
+            // 1行3ピクセルで、残りの5ビットは詰め物。詰め物は1にしておく。
+            let buf = [0b101_11111, 0b011_11111];
+            let mut info = Info::with_size(3, 2);
+            info.color_type = ColorType::Indexed;
+            info.bit_depth = BitDepth::One;
+
+            assert_eq!(
+                read_bytes_for_usize(&buf, &info).unwrap(),
+                vec![1, 0, 1, 0, 1, 1]
+            )
+        }
+
         #[test]
         fn success_when_bit_depth_one() {
             let buf = [0b10011100, 0b01100011];
@@ -709,6 +2603,19 @@ mod tests {
             )
         }
 
+        #[test]
+        fn failed_when_buffer_is_too_long() {
+            let buf = [0b101_11111, 0b011_11111, 0];
+            let mut info = Info::with_size(3, 2);
+            info.color_type = ColorType::Indexed;
+            info.bit_depth = BitDepth::One;
+
+            assert!(matches!(
+                read_bytes_for_usize(&buf, &info),
+                Err(ResizedPngError::DecodingError(None))
+            ));
+        }
+
         #[test]
         fn failed_when_bit_depth_sixteen_and_invalid_bytes() {
             let buf = [0b10011100, 0b01100011, 0b0011001111];
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+
+/// 色とその色のピクセルの数
+type ColorCount = ([u8; 4], u64);
+
+/// 完全に透明なピクセルを置き換える色
+const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+
+/// RGBAの並びを、max_colors色以下になるようにメディアンカットで減色する
+///
+/// 完全に透明なピクセルは色を問わず(0, 0, 0, 0)にし、その1色をパレットに残す。
+/// 元からmax_colors色以下なら、透明なピクセル以外はそのまま返す。
+pub(super) fn quantize(buf: &[u8], max_colors: usize) -> Vec<u8> {
+    let mut mapping: HashMap<[u8; 4], [u8; 4]> = HashMap::new();
+    for colors in &median_cut(buf, max_colors) {
+        let representative = average(colors);
+        for (color, _) in colors {
+            mapping.insert(*color, representative);
+        }
+    }
+
+    buf.chunks_exact(4)
+        .flat_map(|p| match p[3] {
+            0 => TRANSPARENT,
+            _ => mapping[&[p[0], p[1], p[2], p[3]]],
+        })
+        .collect()
+}
+
+/// quantizeと同じパレットに、Floyd-Steinbergのディザリングで減色する。widthは1行のピクセルの数
+///
+/// 誤差は右と次の行に拡散し、画像の外に出る分は捨てる。完全に透明なピクセルは誤差を受け取らず、拡散もしない。
+pub(super) fn quantize_dithered(buf: &[u8], width: usize, max_colors: usize) -> Vec<u8> {
+    let palette: Vec<[u8; 4]> = median_cut(buf, max_colors)
+        .iter()
+        .map(|colors| average(colors))
+        .collect();
+    if width == 0 || palette.is_empty() {
+        return quantize(buf, max_colors);
+    }
+
+    // 誤差は16倍して持ち、足すときに16で割る。
+    let mut current = vec![[0i32; 4]; width];
+    let mut next = vec![[0i32; 4]; width];
+    let mut dist = Vec::with_capacity(buf.len());
+
+    for line in buf.chunks_exact(width * 4) {
+        for (x, p) in line.chunks_exact(4).enumerate() {
+            if p[3] == 0 {
+                dist.extend_from_slice(&TRANSPARENT);
+                continue;
+            }
+
+            let mut value = [0i32; 4];
+            for ((v, source), error) in value.iter_mut().zip(p).zip(current[x]) {
+                *v = (*source as i32 + error / 16).clamp(0, 255);
+            }
+            let nearest = nearest_color(&palette, value);
+            dist.extend_from_slice(&nearest);
+
+            let error: [i32; 4] = std::array::from_fn(|c| value[c] - nearest[c] as i32);
+            for (channel, e) in error.into_iter().enumerate() {
+                if x + 1 < width {
+                    current[x + 1][channel] += e * 7;
+                    next[x + 1][channel] += e;
+                }
+                if x > 0 {
+                    next[x - 1][channel] += e * 3;
+                }
+                next[x][channel] += e * 5;
+            }
+        }
+
+        std::mem::swap(&mut current, &mut next);
+        next.fill([0; 4]);
+    }
+
+    dist
+}
+
+/// paletteのうち、RGBAの距離の二乗が最も小さい色
+fn nearest_color(palette: &[[u8; 4]], value: [i32; 4]) -> [u8; 4] {
+    *palette
+        .iter()
+        .min_by_key(|color| {
+            color
+                .iter()
+                .zip(value)
+                .map(|(c, v)| (*c as i32 - v).pow(2))
+                .sum::<i32>()
+        })
+        .unwrap()
+}
+
+/// 透明なピクセルを除いた色を、max_colors色以下の箱に分ける。透明なピクセルがあればその分を1色減らす
+fn median_cut(buf: &[u8], max_colors: usize) -> Vec<Vec<ColorCount>> {
+    let mut counts: HashMap<[u8; 4], u64> = HashMap::new();
+    let mut has_transparent = false;
+    for p in buf.chunks_exact(4) {
+        if p[3] == 0 {
+            has_transparent = true;
+        } else {
+            *counts.entry([p[0], p[1], p[2], p[3]]).or_insert(0) += 1;
+        }
+    }
+
+    let max_colors = max_colors.saturating_sub(has_transparent as usize).max(1);
+    let mut colors: Vec<ColorCount> = counts.into_iter().collect();
+    // HashMapの順に左右されないよう、並べてから分ける。
+    colors.sort_unstable();
+
+    let mut boxes = vec![colors];
+    while boxes.len() < max_colors {
+        let Some((index, channel)) = widest_box(&boxes) else {
+            break;
+        };
+        let (lower, upper) = split_box(boxes.swap_remove(index), channel);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    // 全て透明なら色の箱はいらない。
+    boxes.retain(|colors| !colors.is_empty());
+
+    boxes
+}
+
+/// 2色以上を持つ箱のうち、ピクセルの数と色の幅の積が最も大きいものの番号と、幅の広いチャンネル
+fn widest_box(boxes: &[Vec<ColorCount>]) -> Option<(usize, usize)> {
+    boxes
+        .iter()
+        .enumerate()
+        .filter(|(_, colors)| colors.len() > 1)
+        .map(|(index, colors)| {
+            let (channel, range) = (0..4)
+                .map(|channel| {
+                    let values = colors.iter().map(|(color, _)| color[channel]);
+                    let range = values.clone().max().unwrap() - values.min().unwrap();
+                    (channel, range)
+                })
+                .max_by_key(|(_, range)| *range)
+                .unwrap();
+            let pixels: u64 = colors.iter().map(|(_, count)| count).sum();
+
+            (index, channel, range as u64 * pixels)
+        })
+        .max_by_key(|(_, _, score)| *score)
+        .map(|(index, channel, _)| (index, channel))
+}
+
+/// channelの値で並べ、ピクセルの数が半分になるところで2つに分ける。どちらも1色以上になる
+fn split_box(mut colors: Vec<ColorCount>, channel: usize) -> (Vec<ColorCount>, Vec<ColorCount>) {
+    colors.sort_by_key(|(color, _)| color[channel]);
+
+    let half = colors.iter().map(|(_, count)| count).sum::<u64>() / 2;
+    let mut seen = 0;
+    let median = colors
+        .iter()
+        .position(|(_, count)| {
+            seen += count;
+            seen > half
+        })
+        .unwrap_or(0);
+    let upper = colors.split_off(median.clamp(1, colors.len() - 1));
+
+    (colors, upper)
+}
+
+/// ピクセルの数で重みをつけた平均の色。RGBは、アルファでも重みをつけて透明に近い色に引っ張られないようにする
+fn average(colors: &[ColorCount]) -> [u8; 4] {
+    let mut rgb = [0u64; 3];
+    let mut rgb_weight = 0u64;
+    let mut alpha = 0u64;
+    let mut pixels = 0u64;
+
+    for (color, count) in colors {
+        let weight = count * color[3] as u64;
+        for (sum, v) in rgb.iter_mut().zip(color) {
+            *sum += *v as u64 * weight;
+        }
+        rgb_weight += weight;
+        alpha += color[3] as u64 * count;
+        pixels += count;
+    }
+
+    let rounded = |sum: u64, total: u64| ((sum + total / 2) / total) as u8;
+
+    [
+        rounded(rgb[0], rgb_weight),
+        rounded(rgb[1], rgb_weight),
+        rounded(rgb[2], rgb_weight),
+        rounded(alpha, pixels),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 64x64の、赤と緑と青とアルファがそれぞれ変わるグラデーション
+    fn gradient() -> Vec<u8> {
+        (0..64u32)
+            .flat_map(|y| {
+                (0..64u32).flat_map(move |x| {
+                    [
+                        (x * 4) as u8,
+                        (y * 4) as u8,
+                        ((x + y) * 2) as u8,
+                        (255 - x * 2) as u8,
+                    ]
+                })
+            })
+            .collect()
+    }
+
+    fn color_count(buf: &[u8]) -> usize {
+        let colors: std::collections::HashSet<&[u8]> = buf.chunks_exact(4).collect();
+        colors.len()
+    }
+
+    mod quantize {
+        use super::*;
+
+        #[test]
+        fn close_to_source_when_gradient() {
+            let buf = gradient();
+            assert!(color_count(&buf) > 256);
+
+            let quantized = quantize(&buf, 256);
+
+            assert_eq!(quantized.len(), buf.len());
+            assert!(color_count(&quantized) <= 256);
+
+            let error: u64 = buf
+                .iter()
+                .zip(&quantized)
+                .map(|(a, b)| a.abs_diff(*b) as u64)
+                .sum();
+            let average = error as f64 / buf.len() as f64;
+            assert!(average < 8.0, "{average}");
+        }
+
+        #[test]
+        fn unchanged_when_few_colors() {
+            let buf = [[255, 0, 0, 255], [0, 255, 0, 128], [255, 0, 0, 255]].concat();
+
+            assert_eq!(quantize(&buf, 256), buf);
+        }
+
+        #[test]
+        fn transparent_is_kept_when_over_max_colors() {
+            let mut buf = gradient();
+            buf[..4].copy_from_slice(&[12, 34, 56, 0]);
+
+            let quantized = quantize(&buf, 16);
+
+            assert_eq!(quantized[..4], [0, 0, 0, 0]);
+            assert!(color_count(&quantized) <= 16);
+            assert!(quantized[4..].chunks_exact(4).all(|v| v[3] != 0));
+        }
+
+        #[test]
+        fn transparent_when_all_transparent() {
+            let buf = [[12, 34, 56, 0], [78, 90, 12, 0]].concat();
+
+            assert_eq!(quantize(&buf, 256), [0; 8]);
+        }
+    }
+
+    mod quantize_dithered {
+        use super::*;
+
+        /// 256x16の、左から右へ黒から白になるグレーのグラデーション
+        fn gray_gradient() -> Vec<u8> {
+            (0..16)
+                .flat_map(|_| (0..=255u8).flat_map(|x| [x, x, x, 255]))
+                .collect()
+        }
+
+        /// 16ピクセル幅の帯ごとに使われている色の数の合計。帯の中が1色なら縞になっている
+        fn colors_in_strips(buf: &[u8]) -> usize {
+            (0..16)
+                .map(|strip| {
+                    let colors: std::collections::HashSet<&[u8]> = buf
+                        .chunks_exact(256 * 4)
+                        .flat_map(|line| line[strip * 64..(strip + 1) * 64].chunks_exact(4))
+                        .collect();
+                    colors.len()
+                })
+                .sum()
+        }
+
+        #[test]
+        fn more_colors_in_strips_than_without_dithering() {
+            let buf = gray_gradient();
+
+            let plain = quantize(&buf, 4);
+            let dithered = quantize_dithered(&buf, 256, 4);
+
+            assert_eq!(dithered.len(), buf.len());
+            assert!(color_count(&dithered) <= 4);
+            assert!(
+                colors_in_strips(&dithered) > colors_in_strips(&plain),
+                "{} {}",
+                colors_in_strips(&dithered),
+                colors_in_strips(&plain)
+            );
+        }
+
+        #[test]
+        fn same_palette_as_without_dithering() {
+            let buf = gray_gradient();
+
+            let plain = quantize(&buf, 4);
+            let plain: std::collections::HashSet<&[u8]> = plain.chunks_exact(4).collect();
+            let dithered = quantize_dithered(&buf, 256, 4);
+
+            assert!(dithered.chunks_exact(4).all(|v| plain.contains(v)));
+        }
+
+        #[test]
+        fn unchanged_when_few_colors() {
+            let buf = [[255, 0, 0, 255], [0, 255, 0, 128], [255, 0, 0, 255], [0; 4]].concat();
+
+            assert_eq!(quantize_dithered(&buf, 2, 256), buf);
+        }
+    }
+
+    mod split_box {
+        use super::*;
+
+        #[test]
+        fn both_have_colors_when_one_color_is_dominant() {
+            let colors = vec![([0, 0, 0, 255], 1000), ([255, 0, 0, 255], 1)];
+
+            let (lower, upper) = split_box(colors, 0);
+
+            assert_eq!(lower, [([0, 0, 0, 255], 1000)]);
+            assert_eq!(upper, [([255, 0, 0, 255], 1)]);
+        }
+    }
+}
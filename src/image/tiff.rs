@@ -0,0 +1,145 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::PathBuf,
+};
+
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::ColorType;
+
+use crate::error::ResizedPngError;
+
+use super::{cmyk_to_rgb_value, u16_to_u8, ImageData, SourceColorType};
+
+pub(crate) fn read_image_data(path: &PathBuf) -> Result<ImageData, ResizedPngError> {
+    let bytes = std::fs::read(path)?;
+    read_image_data_from_bytes(&bytes)
+}
+
+/// メモリ上のバイト列から読み込む
+pub(crate) fn read_image_data_from_bytes(bytes: &[u8]) -> Result<ImageData, ResizedPngError> {
+    let mut decoder = Decoder::new(Cursor::new(bytes))?;
+
+    let (width, height) = decoder.dimensions()?;
+    let color_type = decoder.colortype()?;
+
+    let samples = match decoder.read_image()? {
+        DecodingResult::U8(v) => v,
+        DecodingResult::U16(v) => v.into_iter().map(u16_to_u8).collect(),
+        _ => return Err(ResizedPngError::Unsupported(None)),
+    };
+
+    let (pixels, source_color) = match color_type {
+        ColorType::Gray(8 | 16) => (
+            samples.iter().flat_map(|v| [*v, *v, *v, u8::MAX]).collect(),
+            SourceColorType::Grayscale,
+        ),
+        ColorType::GrayA(8 | 16) => (
+            samples
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            SourceColorType::GrayscaleAlpha,
+        ),
+        ColorType::RGB(8 | 16) => (
+            samples
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], u8::MAX])
+                .collect(),
+            SourceColorType::Rgb,
+        ),
+        ColorType::RGBA(8 | 16) => (samples, SourceColorType::Rgba),
+        ColorType::CMYK(8 | 16) => (
+            samples
+                .chunks_exact(4)
+                .flat_map(|p| {
+                    [
+                        cmyk_to_rgb_value(p[0], p[3]),
+                        cmyk_to_rgb_value(p[1], p[3]),
+                        cmyk_to_rgb_value(p[2], p[3]),
+                        u8::MAX,
+                    ]
+                })
+                .collect(),
+            SourceColorType::Rgb,
+        ),
+        _ => return Err(ResizedPngError::Unsupported(None)),
+    };
+
+    if pixels.len() < width as usize * height as usize * 4 {
+        return Err(ResizedPngError::DecodingError(None));
+    }
+
+    Ok(ImageData {
+        pixels,
+        width,
+        height,
+        source_color,
+        pixel_dims: None,
+        icc_profile: None,
+        text_chunks: Vec::new(),
+    })
+}
+
+/// 画素を読まずに幅と高さを返す
+pub(crate) fn read_dimensions(path: &PathBuf) -> Result<(u32, u32), ResizedPngError> {
+    let fs = File::open(path)?;
+    let mut decoder = Decoder::new(BufReader::new(fs))?;
+
+    Ok(decoder.dimensions()?)
+}
+
+/// メモリ上のバイト列から、画素を読まずに幅と高さを返す
+pub(crate) fn read_dimensions_from_bytes(bytes: &[u8]) -> Result<(u32, u32), ResizedPngError> {
+    let mut decoder = Decoder::new(Cursor::new(bytes))?;
+
+    Ok(decoder.dimensions()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod read_dimensions {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_sample_tiff() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.tif");
+
+            assert_eq!(read_dimensions(&path).unwrap(), (100, 200));
+        }
+
+        #[test]
+        fn failed_when_invalid_tiff_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+
+            assert!(read_dimensions(&path).is_err());
+        }
+    }
+
+    mod read_image_data {
+        use super::*;
+
+        #[test]
+        fn success_when_valid_tiff_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.tif");
+
+            let ImageData { width, height, .. } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 100);
+            assert_eq!(height, 200);
+        }
+
+        #[test]
+        fn failed_when_invalid_tiff_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert!(read_image_data(&path).is_err());
+        }
+    }
+}
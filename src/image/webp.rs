@@ -1,60 +1,399 @@
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Cursor, Read},
+    path::PathBuf,
+};
 
-use image_webp::{DecodingError, WebPDecoder};
+use image_webp::{ColorType, DecodingError, EncodingError, WebPDecoder, WebPEncoder};
 
 use crate::error::ResizedPngError;
 
-use super::ImageData;
+use super::{ImageData, SourceColorType};
 
 impl From<DecodingError> for ResizedPngError {
     fn from(value: DecodingError) -> Self {
         match value {
-            DecodingError::IoError(_) => ResizedPngError::IoError,
-            _ => ResizedPngError::DecodingError,
+            DecodingError::IoError(e) => ResizedPngError::IoError(Some(e.into())),
+            // 壊れているのではなく、image_webpが対応していないだけのもの。
+            DecodingError::UnsupportedFeature(_) => {
+                ResizedPngError::Unsupported(Some(value.into()))
+            }
+            DecodingError::ImageTooLarge | DecodingError::MemoryLimitExceeded => {
+                ResizedPngError::LimitsError(Some(value.into()))
+            }
+            _ => ResizedPngError::DecodingError(Some(value.into())),
+        }
+    }
+}
+
+impl From<EncodingError> for ResizedPngError {
+    fn from(value: EncodingError) -> Self {
+        match value {
+            EncodingError::IoError(e) => e.into(),
+            _ => ResizedPngError::EncodingError(Some(value.into())),
         }
     }
 }
 
 pub(crate) fn read_image_data(path: &PathBuf) -> Result<ImageData, ResizedPngError> {
-    let fs = File::open(path)?;
-    let buf_reader = BufReader::new(fs);
-    let mut decoder = WebPDecoder::new(buf_reader)?;
+    let bytes = std::fs::read(path)?;
+    read_image_data_from_bytes(&bytes)
+}
+
+/// メモリ上のバイト列から読み込む
+pub(crate) fn read_image_data_from_bytes(bytes: &[u8]) -> Result<ImageData, ResizedPngError> {
+    let mut decoder = WebPDecoder::new(Cursor::new(bytes))?;
+
+    if decoder.is_animated() {
+        // アニメーションは最初のフレームを、背景色ではなく透明なキャンバスに重ねる。
+        decoder.set_background_color([0, 0, 0, 0])?;
+    }
 
-    let output_buffer_size = decoder
-        .output_buffer_size()
-        .ok_or(ResizedPngError::Unsupported)?;
-    let mut buffer = vec![0; output_buffer_size];
+    let (width, height) = decoder.dimensions();
+    let mut buffer = vec![0; buffer_size(width, height, decoder.has_alpha())?];
 
     decoder.read_image(&mut buffer)?;
+    let buffer = to_rgba(buffer, width, height, decoder.has_alpha())?;
+
+    let source_color = if decoder.has_alpha() {
+        SourceColorType::Rgba
+    } else {
+        SourceColorType::Rgb
+    };
+
+    Ok(ImageData {
+        pixels: buffer,
+        width,
+        height,
+        source_color,
+        pixel_dims: None,
+        icc_profile: None,
+        text_chunks: Vec::new(),
+    })
+}
+
+/// 読み込みに必要なバイト数。image_webpのoutput_buffer_sizeと同じ計算
+///
+/// output_buffer_sizeがNoneになるのは、このバイト数がusizeに収まらないときだけ。
+/// 32bitのDLLでは大きな画像で起こり得るので、形式の問題ではなく大きさの問題としてLimitsErrorにする。
+fn buffer_size(width: u32, height: u32, has_alpha: bool) -> Result<usize, ResizedPngError> {
+    let bytes_per_pixel = if has_alpha { 4 } else { 3 };
 
-    if !decoder.has_alpha() {
-        buffer = buffer.chunks(3).fold(Vec::new(), |mut acc, item| {
-            acc.extend(item);
-            acc.push(u8::MAX);
-            acc
-        });
+    (width as u64)
+        .checked_mul(height as u64)
+        .and_then(|v| v.checked_mul(bytes_per_pixel))
+        .and_then(|v| usize::try_from(v).ok())
+        .ok_or(ResizedPngError::LimitsError(None))
+}
+
+/// image_webpが返した画素の並びを、RGBAにする
+///
+/// image_webpは8bitのRGBかRGBAでしか返さない。それ以外を3バイトずつに区切って色を崩さないよう、
+/// 幅と高さに合わない長さはDecodingErrorにする。
+fn to_rgba(
+    buffer: Vec<u8>,
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+) -> Result<Vec<u8>, ResizedPngError> {
+    if buffer.len() != buffer_size(width, height, has_alpha)? {
+        return Err(ResizedPngError::DecodingError(None));
+    }
+
+    if has_alpha {
+        return Ok(buffer);
     }
-    let (width, height) = decoder.dimensions();
 
-    Ok((buffer, width, height))
+    Ok(buffer
+        .chunks_exact(3)
+        .flat_map(|v| [v[0], v[1], v[2], u8::MAX])
+        .collect())
+}
+
+/// フレームの数を返す。アニメーションでなければ1
+pub(crate) fn frame_count(path: &PathBuf) -> Result<u32, ResizedPngError> {
+    let fs = File::open(path)?;
+    let decoder = WebPDecoder::new(BufReader::new(fs))?;
+
+    if decoder.is_animated() {
+        Ok(decoder.num_frames())
+    } else {
+        Ok(1)
+    }
+}
+
+/// 画素を読まずに幅と高さを返す
+pub(crate) fn read_dimensions(path: &PathBuf) -> Result<(u32, u32), ResizedPngError> {
+    let mut head = Vec::new();
+    File::open(path)?.take(30).read_to_end(&mut head)?;
+
+    dimensions_from_header(&head).ok_or(ResizedPngError::DecodingError(None))
+}
+
+/// メモリ上のバイト列から、画素を読まずに幅と高さを返す
+pub(crate) fn read_dimensions_from_bytes(bytes: &[u8]) -> Result<(u32, u32), ResizedPngError> {
+    dimensions_from_header(bytes).ok_or(ResizedPngError::DecodingError(None))
+}
+
+/// RIFFヘッダと最初のチャンクから、キャンバスの幅と高さを読み取る
+fn dimensions_from_header(head: &[u8]) -> Option<(u32, u32)> {
+    let u24 = |v: &[u8]| u32::from_le_bytes([v[0], v[1], v[2], 0]);
+    let u14 = |v: &[u8]| u16::from_le_bytes([v[0], v[1]]) as u32 & 0x3FFF;
+
+    match head {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', rest @ ..] => match rest {
+            // 拡張形式。キャンバスの幅と高さから1を引いた値を持つ。
+            [b'V', b'P', b'8', b'X', _, _, _, _, _, _, _, _, size @ ..] => {
+                let size = size.get(..6)?;
+                Some((u24(&size[..3]) + 1, u24(&size[3..]) + 1))
+            }
+            // 非可逆。フレームの開始コードの後に幅と高さがある。
+            [b'V', b'P', b'8', b' ', _, _, _, _, _, _, _, 0x9D, 0x01, 0x2A, size @ ..] => {
+                let size = size.get(..4)?;
+                Some((u14(&size[..2]), u14(&size[2..])))
+            }
+            // 可逆。署名の後に14bitずつ、幅と高さから1を引いた値を持つ。
+            [b'V', b'P', b'8', b'L', _, _, _, _, 0x2F, size @ ..] => {
+                let bits = u32::from_le_bytes(size.get(..4)?.try_into().ok()?);
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// RGBAの並びを、可逆圧縮のwebpとして書き出す
+pub(crate) fn write_webp(
+    path: &PathBuf,
+    buf: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<(), ResizedPngError> {
+    let fs = File::create(path)?;
+    let w = BufWriter::new(fs);
+
+    WebPEncoder::new(w).encode(buf, width, height, ColorType::Rgba8)?;
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::path::Path;
+
+    /// 4x3のキャンバスの(2, 0)に、赤い2x2のフレームがframes枚あるアニメーションWebPを書き出す。
+    pub(super) fn write_animated_webp(path: &Path, frames: usize) {
+        let mut simple = Vec::new();
+        WebPEncoder::new(&mut simple)
+            .encode(&[255, 0, 0, 255].repeat(4), 2, 2, ColorType::Rgba8)
+            .unwrap();
+        // RIFFヘッダとVP8Lチャンクヘッダを除いたビットストリーム。
+        let vp8l = &simple[20..];
+
+        let chunk = |fourcc: &[u8], data: &[u8]| {
+            let mut bytes = fourcc.to_vec();
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            bytes
+        };
+
+        let mut vp8x = vec![0b0001_0010, 0, 0, 0];
+        vp8x.extend_from_slice(&3u32.to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&2u32.to_le_bytes()[..3]);
+        let vp8x = chunk(b"VP8X", &vp8x);
+
+        // 背景色は不透明な白にしておく。
+        let anim = chunk(b"ANIM", &[255, 255, 255, 255, 0, 0]);
+
+        let mut anmf = Vec::new();
+        anmf.extend_from_slice(&1u32.to_le_bytes()[..3]);
+        anmf.extend_from_slice(&0u32.to_le_bytes()[..3]);
+        anmf.extend_from_slice(&1u32.to_le_bytes()[..3]);
+        anmf.extend_from_slice(&1u32.to_le_bytes()[..3]);
+        anmf.extend_from_slice(&100u32.to_le_bytes()[..3]);
+        anmf.push(0);
+        anmf.extend(chunk(b"VP8L", vp8l));
+        let anmf = chunk(b"ANMF", &anmf);
+
+        let mut body = b"WEBP".to_vec();
+        body.extend(vp8x);
+        body.extend(anim);
+        for _ in 0..frames {
+            body.extend_from_slice(&anmf);
+        }
+
+        std::fs::write(path, chunk(b"RIFF", &body)).unwrap();
+    }
+
+    mod from_decoding_error {
+        use super::*;
+
+        #[test]
+        fn unsupported_when_unsupported_feature() {
+            let e = DecodingError::UnsupportedFeature("something".to_string());
+            assert!(matches!(
+                ResizedPngError::from(e),
+                ResizedPngError::Unsupported(Some(_))
+            ));
+        }
+
+        #[test]
+        fn limits_error_when_image_too_large() {
+            assert!(matches!(
+                ResizedPngError::from(DecodingError::ImageTooLarge),
+                ResizedPngError::LimitsError(Some(_))
+            ));
+        }
+
+        #[test]
+        fn decoding_error_when_corrupted() {
+            assert!(matches!(
+                ResizedPngError::from(DecodingError::HuffmanError),
+                ResizedPngError::DecodingError(Some(_))
+            ));
+        }
+    }
+
+    mod to_rgba {
+        use super::*;
+
+        #[test]
+        fn opaque_when_rgb() {
+            let buffer = vec![1, 2, 3, 4, 5, 6];
+            assert_eq!(
+                to_rgba(buffer, 2, 1, false).unwrap(),
+                [1, 2, 3, 255, 4, 5, 6, 255]
+            );
+        }
+
+        #[test]
+        fn unchanged_when_rgba() {
+            let buffer = vec![1, 2, 3, 4, 5, 6, 7, 8];
+            assert_eq!(to_rgba(buffer.clone(), 2, 1, true).unwrap(), buffer);
+        }
+
+        #[test]
+        fn decoding_error_when_length_does_not_match() {
+            // 16bitのRGBのように、1ピクセルが6バイトの並び。
+            let buffer = vec![0; 2 * 6];
+            assert!(matches!(
+                to_rgba(buffer, 2, 1, false),
+                Err(ResizedPngError::DecodingError(None))
+            ));
+        }
+    }
+
+    mod buffer_size {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_rgb() {
+            assert_eq!(buffer_size(4, 3, false).unwrap(), 36);
+        }
+
+        #[test]
+        fn checking_value_when_rgba() {
+            assert_eq!(buffer_size(4, 3, true).unwrap(), 48);
+        }
+
+        #[test]
+        fn limits_error_when_overflowed() {
+            assert!(matches!(
+                buffer_size(u32::MAX, u32::MAX, true),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+        }
+    }
+
+    mod frame_count {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn checking_value_when_static_webp() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.webp");
+
+            assert_eq!(frame_count(&path).unwrap(), 1);
+        }
+
+        #[test]
+        fn checking_value_when_animated_webp() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("animated.webp");
+
+            write_animated_webp(&path, 3);
+
+            assert_eq!(frame_count(&path).unwrap(), 3);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_invalid_webp_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert!(frame_count(&path).is_err());
+        }
+    }
+
     mod read_image_data {
         use super::*;
 
+        use tempfile::tempdir;
+
         #[test]
         fn success_when_valid_webp_path() {
             let path =
                 PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.webp");
 
-            let (_data, width, height) = read_image_data(&path).unwrap();
+            let ImageData {
+                width,
+                height,
+                source_color,
+                ..
+            } = read_image_data(&path).unwrap();
 
             assert_eq!(width, 100);
             assert_eq!(height, 200);
+            assert_eq!(source_color, SourceColorType::Rgb);
+        }
+
+        #[test]
+        fn checking_value_when_animated_webp() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("animated.webp");
+
+            write_animated_webp(&path, 1);
+
+            let ImageData {
+                pixels: data,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            // フレームではなくキャンバスの大きさになる。
+            assert_eq!(width, 4);
+            assert_eq!(height, 3);
+            assert_eq!(data.len(), 4 * 3 * 4);
+
+            let pixel = |x: usize, y: usize| &data[(y * 4 + x) * 4..(y * 4 + x) * 4 + 4];
+            assert_eq!(pixel(0, 0), [0, 0, 0, 0]);
+            assert_eq!(pixel(1, 1), [0, 0, 0, 0]);
+            assert_eq!(pixel(2, 0), [255, 0, 0, 255]);
+            assert_eq!(pixel(3, 1), [255, 0, 0, 255]);
+            assert_eq!(pixel(2, 2), [0, 0, 0, 0]);
+
+            out_dir.close().unwrap();
         }
 
         #[test]
@@ -65,4 +404,89 @@ mod tests {
             assert!(read_image_data(&path).is_err());
         }
     }
+
+    mod read_dimensions {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn checking_value_when_sample_webp() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.webp");
+
+            assert_eq!(read_dimensions(&path).unwrap(), (100, 200));
+        }
+
+        #[test]
+        fn checking_value_when_extended_webp() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("animated.webp");
+
+            write_animated_webp(&path, 1);
+
+            assert_eq!(read_dimensions(&path).unwrap(), (4, 3));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_invalid_webp_path() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert!(matches!(
+                read_dimensions(&path),
+                Err(ResizedPngError::DecodingError(_))
+            ));
+        }
+    }
+
+    mod dimensions_from_header {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_lossy() {
+            let mut head = b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0".to_vec();
+            head.extend_from_slice(&[0, 0, 0, 0x9D, 0x01, 0x2A]);
+            // 上位2bitは拡大の指定なので無視する。
+            head.extend_from_slice(&(100u16 | 0xC000).to_le_bytes());
+            head.extend_from_slice(&200u16.to_le_bytes());
+
+            assert_eq!(dimensions_from_header(&head), Some((100, 200)));
+        }
+
+        #[test]
+        fn none_when_too_short() {
+            assert_eq!(dimensions_from_header(b"RIFF\0\0\0\0WEBPVP8L"), None);
+        }
+    }
+
+    mod write_webp {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn round_trip_when_valid_parameter() {
+            let out_dir = tempdir().unwrap();
+            let path = out_dir.path().join("test.webp");
+
+            let buf = [1, 2, 3, 4, 5, 6, 7, 8];
+            write_webp(&path, &buf, 2, 1).unwrap();
+
+            let ImageData {
+                pixels,
+                width,
+                height,
+                ..
+            } = read_image_data(&path).unwrap();
+
+            assert_eq!(width, 2);
+            assert_eq!(height, 1);
+            assert_eq!(pixels, buf);
+
+            out_dir.close().unwrap();
+        }
+    }
 }
@@ -1,3 +1,11 @@
+/// `log`の機能が有効なときだけ、debugレベルで出力する。無効なときは何も残らない
+macro_rules! debug_log {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+    };
+}
+
 mod chars;
 mod error;
 mod image;
@@ -14,12 +22,27 @@ use winapi::um::winnt::{
     DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH, DLL_THREAD_ATTACH, DLL_THREAD_DETACH,
 };
 
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::slice;
 use std::sync::OnceLock;
 
 use crate::request::{SaoriCommand, SaoriRequest};
 use crate::response::SaoriResponse;
 
+pub use crate::error::ResizedPngError;
+pub use crate::image::png::PaletteIndexMode;
+pub use crate::image::{
+    read_image_data, read_image_data_from_bytes, supported_formats, DecodeLimits, ImageData,
+    ImageFormat,
+};
+pub use crate::resized_png::{
+    detect_format_from_bytes, parse_dimension_command, to_square_png, would_upscale, FitMode,
+    OutputColor, OutputFormat, PadFill, PngCompression, ResizeFilter, ResizeOptions, ResizeQuality,
+    ResizeResult, Rotation, SizeCommand, UnsharpMask,
+};
+
 static DLL_PATH: OnceLock<String> = OnceLock::new();
 
 #[no_mangle]
@@ -118,6 +141,187 @@ pub unsafe extern "cdecl" fn request(h: HGLOBAL, len: *mut c_long) -> HGLOBAL {
     slice_i8_to_hglobal(len, &response_bytes)
 }
 
+/// メモリ上の画像を拡大縮小し、pngのバイト列で返す
+///
+/// 形式は先頭のバイト列から判別する。幅と高さの指定はSAORIのArgument3、Argument4と同じで、
+/// 0は元の大きさ、-1はもう片方の拡大率に従う。両方とも-1の場合は空のバイト列を返す。
+pub fn resize_image_bytes(
+    input: &[u8],
+    width_command: i64,
+    height_command: i64,
+) -> Result<Vec<u8>, ResizedPngError> {
+    resized_png::to_resized_png_bytes(
+        input,
+        SizeCommand::Pixel(width_command),
+        SizeCommand::Pixel(height_command),
+        &ResizeOptions::default(),
+    )
+}
+
+/// resize_image_bytesと同じ。幅と高さを、百分率も表せるSizeCommandで指定する
+///
+/// SizeCommandは`parse_dimension_command`で文字列から作れる。
+pub fn resize_image_bytes_by_command(
+    input: &[u8],
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+) -> Result<Vec<u8>, ResizedPngError> {
+    resized_png::to_resized_png_bytes(
+        input,
+        width_command,
+        height_command,
+        &ResizeOptions::default(),
+    )
+}
+
+/// resize_image_bytes_by_commandと同じ。拡大縮小の設定をoptionsで指定する
+///
+/// 出力形式はoptionsに従うので、png以外のバイト列を返すこともある。
+pub fn resize_image_bytes_with_options(
+    input: &[u8],
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Result<Vec<u8>, ResizedPngError> {
+    resized_png::to_resized_png_bytes(input, width_command, height_command, options)
+}
+
+/// 画像ファイルを、optionsの設定で拡大縮小して書き出す
+///
+/// SAORIの`ToResizedPng`と同じ処理。両方とも-1の場合など、サイズが計算できないときは何も書き出さない。
+pub fn resize_image_file(
+    src_path: &PathBuf,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Result<(), ResizedPngError> {
+    resized_png::to_resized_png(src_path, dist_path, width_command, height_command, options)
+}
+
+/// メモリ上の画像を、pngのバイト列がmax_bytes以下になるように縦横比を保って縮めて返す
+///
+/// 元の大きさで収まればそのまま返す。1%まで縮めても収まらない場合は、その大きさのpngを返す。
+pub fn resize_image_bytes_within(
+    input: &[u8],
+    max_bytes: usize,
+) -> Result<Vec<u8>, ResizedPngError> {
+    resized_png::to_resized_png_bytes_within(input, max_bytes, &ResizeOptions::default())
+}
+
+/// 複数の画像ファイルを同じ指定で拡大縮小し、pngで書き出す。結果は入力と同じ順で返す
+///
+/// inputsは元の画像と出力先の組。途中で失敗しても、残りの画像の処理は続ける。
+pub fn resize_image_files(
+    inputs: &[(PathBuf, PathBuf)],
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+) -> Vec<Result<(), ResizedPngError>> {
+    resized_png::resize_batch(
+        inputs,
+        width_command,
+        height_command,
+        &ResizeOptions::default(),
+    )
+}
+
+/// resize_image_filesと同じ。workersの数のスレッドで並列に行う。結果は入力と同じ順で返す
+pub fn resize_image_files_parallel(
+    inputs: &[(PathBuf, PathBuf)],
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    workers: NonZeroUsize,
+) -> Vec<Result<(), ResizedPngError>> {
+    resized_png::resize_batch_parallel(
+        inputs,
+        width_command,
+        height_command,
+        &ResizeOptions::default(),
+        workers,
+    )
+}
+
+/// 画素を読まずに、拡大縮小したときの幅と高さを求める。両方とも-1の場合などサイズが計算できないときはNone
+pub fn planned_output_size(
+    src_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+) -> Result<Option<(u32, u32)>, ResizedPngError> {
+    resized_png::planned_output_size(
+        src_path,
+        width_command,
+        height_command,
+        &ResizeOptions::default(),
+    )
+}
+
+/// 画像ファイルを拡大縮小してpngで書き出し、読み込んだ画像の形式や大きさ、書き出したバイト数を返す
+///
+/// 両方とも-1の場合など、サイズが計算できず何も書き出さなかったときはNone。
+pub fn resize_image_file_with_result(
+    src_path: &PathBuf,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+) -> Result<Option<ResizeResult>, ResizedPngError> {
+    resized_png::to_resized_png_with_result(
+        src_path,
+        dist_path,
+        width_command,
+        height_command,
+        &ResizeOptions::default(),
+    )
+}
+
+/// 画像ファイルを拡大縮小し、pngで書き出す。読み込み、拡大縮小、書き出しが終わるたびにprogressを呼ぶ
+///
+/// progressには進み具合を0.0から1.0で渡す。サイズが計算できないときは読み込みの後で終わる。
+pub fn resize_image_file_with_progress(
+    src_path: &PathBuf,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    progress: impl Fn(f32),
+) -> Result<(), ResizedPngError> {
+    resized_png::to_resized_png_with_progress(
+        src_path,
+        dist_path,
+        width_command,
+        height_command,
+        &ResizeOptions::default(),
+        progress,
+    )
+}
+
+/// 一つの画像ファイルを、targetsの出力先と大きさのそれぞれに拡大縮小し、pngで書き出す
+///
+/// 画像は一度だけ読み込む。読み込みに失敗した場合だけ全体をエラーにし、
+/// 出力先ごとの結果はtargetsと同じ順で返す。
+pub fn resize_image_file_to_sizes(
+    src_path: &PathBuf,
+    targets: &[(PathBuf, SizeCommand, SizeCommand)],
+) -> Result<Vec<Result<(), ResizedPngError>>, ResizedPngError> {
+    resized_png::to_resized_pngs(src_path, targets, &ResizeOptions::default())
+}
+
+/// readerから最後まで読み込んだ画像を拡大縮小し、pngで書き出す
+///
+/// 標準入力などから流し込めるように。形式は読み込んだバイト列の先頭から判別する。
+pub fn resize_image_reader(
+    reader: impl Read,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+) -> Result<(), ResizedPngError> {
+    resized_png::to_resized_png_from_reader(
+        reader,
+        dist_path,
+        width_command,
+        height_command,
+        &ResizeOptions::default(),
+    )
+}
+
 fn slice_i8_to_hglobal(h_len: *mut c_long, data: &[i8]) -> HGLOBAL {
     let data_len = data.len();
 
@@ -146,3 +350,656 @@ fn hglobal_to_vec_u8(h: HGLOBAL, len: c_long) -> Vec<u8> {
 
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod resize_image_bytes {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn checking_value_when_each_sample() {
+            for name in [
+                "sample.png",
+                "sample.bmp",
+                "sample.gif",
+                "sample.jpg",
+                "sample.webp",
+                "sample.tif",
+            ] {
+                let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("test_target/image")
+                    .join(name);
+                let input = std::fs::read(path).unwrap();
+
+                let bytes = resize_image_bytes(&input, 50, -1).unwrap();
+
+                let decoder = png::Decoder::new(bytes.as_slice());
+                let reader = decoder.read_info().unwrap();
+                assert_eq!(reader.info().width, 50, "{}", name);
+                assert_eq!(reader.info().height, 100, "{}", name);
+            }
+        }
+
+        #[test]
+        fn failed_when_not_image() {
+            assert!(resize_image_bytes(b"[package]", 50, 100).is_err());
+        }
+    }
+
+    mod read_image_data_from_bytes {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn same_as_path_when_sample_png() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let bytes = std::fs::read(&path).unwrap();
+
+            let from_path = read_image_data(&path).unwrap();
+            let from_bytes = read_image_data_from_bytes(&bytes).unwrap();
+
+            assert_eq!(from_bytes.width(), from_path.width());
+            assert_eq!(from_bytes.height(), from_path.height());
+            assert_eq!(from_bytes.pixels(), from_path.pixels());
+            assert_eq!(
+                from_bytes.pixels().len(),
+                (from_bytes.width() * from_bytes.height() * 4) as usize
+            );
+        }
+
+        #[test]
+        fn failed_when_not_image() {
+            assert!(read_image_data_from_bytes(b"[package]").is_err());
+        }
+    }
+
+    mod resize_image_bytes_by_command {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn half_size_when_parsed_percent() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let input = std::fs::read(path).unwrap();
+            let command = parse_dimension_command("50%").unwrap();
+
+            let bytes = resize_image_bytes_by_command(&input, command, command).unwrap();
+
+            let decoder = png::Decoder::new(bytes.as_slice());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().width, 50);
+            assert_eq!(reader.info().height, 100);
+        }
+    }
+
+    mod resize_image_files {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn checking_value_when_one_is_not_image() {
+            let dir = tempfile::tempdir().unwrap();
+            let image_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image");
+            let inputs = vec![
+                (image_dir.join("sample.png"), dir.path().join("a.png")),
+                (
+                    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"),
+                    dir.path().join("b.png"),
+                ),
+                (image_dir.join("sample.bmp"), dir.path().join("c.png")),
+            ];
+
+            let results =
+                resize_image_files(&inputs, SizeCommand::Pixel(50), SizeCommand::Pixel(-1));
+
+            assert_eq!(results.len(), 3);
+            assert!(results[0].is_ok());
+            assert!(results[1].is_err());
+            assert!(results[2].is_ok());
+            for name in ["a.png", "c.png"] {
+                let data = read_image_data(&dir.path().join(name)).unwrap();
+                assert_eq!((data.width(), data.height()), (50, 100), "{}", name);
+            }
+        }
+    }
+
+    mod resize_image_files_parallel {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn same_as_serial_when_two_workers() {
+            let dir = tempfile::tempdir().unwrap();
+            let image_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image");
+            let inputs: Vec<_> = ["sample.png", "sample.gif", "sample.jpg"]
+                .iter()
+                .map(|name| {
+                    (
+                        image_dir.join(name),
+                        dir.path().join(name).with_extension("png"),
+                    )
+                })
+                .collect();
+
+            let results = resize_image_files_parallel(
+                &inputs,
+                SizeCommand::Pixel(-1),
+                SizeCommand::Pixel(50),
+                NonZeroUsize::new(2).unwrap(),
+            );
+
+            assert_eq!(results.len(), inputs.len());
+            for ((_, dist_path), result) in inputs.iter().zip(results) {
+                assert!(result.is_ok(), "{:?}", dist_path);
+                let data = read_image_data(dist_path).unwrap();
+                assert_eq!((data.width(), data.height()), (25, 50), "{:?}", dist_path);
+            }
+        }
+    }
+
+    mod planned_output_size {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn checking_value_when_sample_png() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert_eq!(
+                planned_output_size(&path, SizeCommand::Pixel(50), SizeCommand::Pixel(-1)).unwrap(),
+                Some((50, 100))
+            );
+            assert_eq!(
+                planned_output_size(&path, SizeCommand::Pixel(-1), SizeCommand::Pixel(-1)).unwrap(),
+                None
+            );
+        }
+    }
+
+    mod resize_image_file_with_progress {
+        use super::*;
+
+        use std::cell::RefCell;
+        use std::path::PathBuf;
+
+        #[test]
+        fn finished_when_sample_png() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = dir.path().join("dist.png");
+            let reported = RefCell::new(Vec::new());
+
+            resize_image_file_with_progress(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(-1),
+                |v| reported.borrow_mut().push(v),
+            )
+            .unwrap();
+
+            assert_eq!(reported.into_inner().last(), Some(&1.0));
+            let data = read_image_data(&dist_path).unwrap();
+            assert_eq!((data.width(), data.height()), (50, 100));
+        }
+    }
+
+    mod resize_image_file_to_sizes {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn checking_value_when_two_targets() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let targets = vec![
+                (
+                    dir.path().join("small.png"),
+                    SizeCommand::Pixel(10),
+                    SizeCommand::Pixel(-1),
+                ),
+                (
+                    dir.path().join("large.png"),
+                    SizeCommand::Percent(200),
+                    SizeCommand::Percent(200),
+                ),
+            ];
+
+            let results = resize_image_file_to_sizes(&src_path, &targets).unwrap();
+
+            assert!(results.iter().all(|v| v.is_ok()));
+            let small = read_image_data(&targets[0].0).unwrap();
+            assert_eq!((small.width(), small.height()), (10, 20));
+            let large = read_image_data(&targets[1].0).unwrap();
+            assert_eq!((large.width(), large.height()), (200, 400));
+        }
+
+        #[test]
+        fn failed_when_not_image() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+            let targets = vec![(
+                dir.path().join("dist.png"),
+                SizeCommand::Pixel(10),
+                SizeCommand::Pixel(10),
+            )];
+
+            assert!(resize_image_file_to_sizes(&src_path, &targets).is_err());
+        }
+    }
+
+    mod resize_image_reader {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn checking_value_when_sample_jpg() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let dist_path = dir.path().join("dist.png");
+            let file = std::fs::File::open(src_path).unwrap();
+
+            resize_image_reader(
+                file,
+                &dist_path,
+                SizeCommand::Pixel(-1),
+                SizeCommand::Pixel(40),
+            )
+            .unwrap();
+
+            let data = read_image_data(&dist_path).unwrap();
+            assert_eq!((data.width(), data.height()), (20, 40));
+        }
+
+        #[test]
+        fn failed_when_not_image() {
+            let dir = tempfile::tempdir().unwrap();
+            let dist_path = dir.path().join("dist.png");
+
+            assert!(resize_image_reader(
+                &b"[package]"[..],
+                &dist_path,
+                SizeCommand::Pixel(10),
+                SizeCommand::Pixel(10),
+            )
+            .is_err());
+            assert!(!dist_path.exists());
+        }
+    }
+
+    mod resize_image_file_with_result {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn checking_value_when_sample_gif() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.gif");
+            let dist_path = dir.path().join("dist.png");
+
+            let result = resize_image_file_with_result(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(-1),
+            )
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(result.source_format(), ImageFormat::Gif);
+            assert_eq!(result.source_size(), (100, 200));
+            assert_eq!(result.output_size(), (50, 100));
+            assert_eq!(
+                result.output_bytes_written(),
+                std::fs::metadata(&dist_path).unwrap().len()
+            );
+        }
+
+        #[test]
+        fn none_when_both_are_auto() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.gif");
+            let dist_path = dir.path().join("dist.png");
+
+            let result = resize_image_file_with_result(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(-1),
+                SizeCommand::Pixel(-1),
+            )
+            .unwrap();
+
+            assert_eq!(result, None);
+            assert!(!dist_path.exists());
+        }
+    }
+
+    mod resize_image_bytes_with_options {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn source_size_when_no_upscale() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let input = std::fs::read(path).unwrap();
+            let options = ResizeOptions::default().with_no_upscale(true);
+
+            let bytes = resize_image_bytes_with_options(
+                &input,
+                SizeCommand::Pixel(400),
+                SizeCommand::Pixel(-1),
+                &options,
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(bytes.as_slice());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().width, 100);
+            assert_eq!(reader.info().height, 200);
+        }
+
+        #[test]
+        fn not_larger_when_best_compression_than_fast() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let input = std::fs::read(path).unwrap();
+            let resize = |compression| {
+                let options = ResizeOptions::default().with_compression(compression);
+                resize_image_bytes_with_options(
+                    &input,
+                    SizeCommand::Pixel(200),
+                    SizeCommand::Pixel(-1),
+                    &options,
+                )
+                .unwrap()
+            };
+
+            let fast = resize(PngCompression::Fast);
+            let best = resize(PngCompression::Best);
+
+            assert!(best.len() <= fast.len());
+        }
+
+        #[test]
+        fn sixteen_bit_when_16bit_output() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let input = std::fs::read(path).unwrap();
+            let options = ResizeOptions::default().with_16bit_output(true);
+
+            let bytes = resize_image_bytes_with_options(
+                &input,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(-1),
+                &options,
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(bytes.as_slice());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().bit_depth, png::BitDepth::Sixteen);
+        }
+
+        #[test]
+        fn grayscale_when_output_color_is_grayscale() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let input = std::fs::read(path).unwrap();
+            let options = ResizeOptions::default().with_output_color(OutputColor::Grayscale);
+
+            let bytes = resize_image_bytes_with_options(
+                &input,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(-1),
+                &options,
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(bytes.as_slice());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, png::ColorType::Grayscale);
+        }
+
+        #[test]
+        fn shrunk_when_over_max_megapixels() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let input = std::fs::read(path).unwrap();
+            let options = ResizeOptions::default().with_max_megapixels(0.005);
+
+            let bytes = resize_image_bytes_with_options(
+                &input,
+                SizeCommand::Pixel(0),
+                SizeCommand::Pixel(0),
+                &options,
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(bytes.as_slice());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().width, 50);
+            assert_eq!(reader.info().height, 100);
+        }
+
+        #[test]
+        fn limits_error_when_over_decode_limits() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let input = std::fs::read(path).unwrap();
+            let options = ResizeOptions::default()
+                .with_decode_limits(DecodeLimits::default().with_max_pixels(100 * 200 - 1));
+
+            assert!(matches!(
+                resize_image_bytes_with_options(
+                    &input,
+                    SizeCommand::Pixel(50),
+                    SizeCommand::Pixel(-1),
+                    &options,
+                ),
+                Err(ResizedPngError::LimitsError(None))
+            ));
+        }
+
+        #[test]
+        fn success_when_index_is_out_of_palette_in_lenient_mode() {
+            let mut input = Vec::new();
+            let mut encoder = png::Encoder::new(&mut input, 2, 1);
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(vec![1, 2, 3]);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[0, 5]).unwrap();
+            writer.finish().unwrap();
+            let resize = |options: &ResizeOptions| {
+                resize_image_bytes_with_options(
+                    &input,
+                    SizeCommand::Pixel(0),
+                    SizeCommand::Pixel(0),
+                    options,
+                )
+            };
+
+            assert!(resize(&ResizeOptions::default()).is_err());
+            assert!(resize(
+                &ResizeOptions::default().with_palette_index(PaletteIndexMode::Lenient)
+            )
+            .is_ok());
+        }
+    }
+
+    mod resize_image_file {
+        use super::*;
+
+        use std::path::PathBuf;
+
+        #[test]
+        fn checking_value_when_sample_bmp() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+            let dist_path = dir.path().join("dist.png");
+
+            resize_image_file(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(-1),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            let data = read_image_data(&dist_path).unwrap();
+            assert_eq!((data.width(), data.height()), (50, 100));
+        }
+
+        #[test]
+        fn requested_size_when_fit_is_cover() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+            let dist_path = dir.path().join("dist.png");
+            let options = ResizeOptions::default().with_fit(FitMode::Cover);
+
+            resize_image_file(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(80),
+                SizeCommand::Pixel(80),
+                &options,
+            )
+            .unwrap();
+
+            let data = read_image_data(&dist_path).unwrap();
+            assert_eq!((data.width(), data.height()), (80, 80));
+        }
+
+        #[test]
+        fn background_on_padding_when_fit_is_contain() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+            let dist_path = dir.path().join("dist.png");
+            let options = ResizeOptions::default()
+                .with_fit(FitMode::Contain)
+                .with_background([255, 0, 0, 255]);
+
+            resize_image_file(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(80),
+                SizeCommand::Pixel(80),
+                &options,
+            )
+            .unwrap();
+
+            let data = read_image_data(&dist_path).unwrap();
+            assert_eq!((data.width(), data.height()), (80, 80));
+            assert_eq!(&data.pixels()[..4], &[255, 0, 0, 255]);
+        }
+
+        #[test]
+        fn swapped_size_when_rotation_is_cw90() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+            let dist_path = dir.path().join("dist.png");
+            let options = ResizeOptions::default().with_rotation(Rotation::Cw90);
+
+            resize_image_file(
+                &src_path,
+                &dist_path,
+                SizeCommand::Percent(50),
+                SizeCommand::Percent(50),
+                &options,
+            )
+            .unwrap();
+
+            let data = read_image_data(&dist_path).unwrap();
+            assert_eq!((data.width(), data.height()), (100, 50));
+        }
+
+        #[test]
+        fn checking_value_when_from_quality() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+            let dist_path = dir.path().join("dist.png");
+            let options = ResizeOptions::from_quality(ResizeQuality::Fast);
+
+            resize_image_file(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(-1),
+                &options,
+            )
+            .unwrap();
+
+            let data = read_image_data(&dist_path).unwrap();
+            assert_eq!((data.width(), data.height()), (50, 100));
+        }
+
+        #[test]
+        fn fitted_size_when_fit_is_inside() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+            let dist_path = dir.path().join("dist.png");
+            let options = ResizeOptions::default().with_fit(FitMode::Inside);
+
+            resize_image_file(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(80),
+                SizeCommand::Pixel(80),
+                &options,
+            )
+            .unwrap();
+
+            let data = read_image_data(&dist_path).unwrap();
+            assert_eq!((data.width(), data.height()), (40, 80));
+        }
+
+        #[test]
+        fn webp_when_output_format_is_webp() {
+            let dir = tempfile::tempdir().unwrap();
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+            let dist_path = dir.path().join("dist.webp");
+            let options = ResizeOptions::default().with_output_format(OutputFormat::Webp);
+
+            resize_image_file(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(-1),
+                &options,
+            )
+            .unwrap();
+
+            let bytes = std::fs::read(&dist_path).unwrap();
+            assert_eq!(detect_format_from_bytes(&bytes), "WEBP");
+        }
+    }
+}
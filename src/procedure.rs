@@ -1,7 +1,11 @@
 use std::path::PathBuf;
 
+use crate::error::ResizedPngError;
 use crate::request::*;
-use crate::resized_png::{get_image_info, get_image_type, to_resized_png};
+use crate::resized_png::{
+    get_image_info, get_image_type, to_resized_png, OutputFormat, ResizeFilter, ResizeOptions,
+    ResizeQuality, SizeCommand,
+};
 use crate::response::*;
 
 /// load時に呼ばれる関数
@@ -39,7 +43,7 @@ pub fn execute(path: &str, request: &SaoriRequest, response: &mut SaoriResponse)
                 if let Some(input_path_str) = args.get(1) {
                     let input_path = path.join(input_path_str);
 
-                    let (width, height) = match get_image_info(&input_path) {
+                    let info = match get_image_info(&input_path) {
                         Ok(v) => v,
                         Err(e) => {
                             response.set_result(format!("{}", e.to_code()));
@@ -48,7 +52,12 @@ pub fn execute(path: &str, request: &SaoriRequest, response: &mut SaoriResponse)
                     };
 
                     response.set_result(0.to_string());
-                    response.set_value(vec![width.to_string(), height.to_string()]);
+                    response.set_value(vec![
+                        info.width.to_string(),
+                        info.height.to_string(),
+                        (info.is_animated() as u8).to_string(),
+                        info.frame_count.to_string(),
+                    ]);
                 }
             }
             "ToResizedPng" => {
@@ -59,22 +68,31 @@ pub fn execute(path: &str, request: &SaoriRequest, response: &mut SaoriResponse)
                     Some(height_str),
                 ) = (args.get(1), args.get(2), args.get(3), args.get(4))
                 {
-                    let Ok(width_command) = width_str.parse::<i64>() else {
+                    let Some(width_command) = SizeCommand::parse(width_str) else {
                         return;
                     };
-                    let Ok(height_command) = height_str.parse::<i64>() else {
+                    let Some(height_command) = SizeCommand::parse(height_str) else {
                         return;
                     };
 
                     let input_path = path.clone().join(input_path_str);
                     let output_path = path.join(output_path_str);
 
-                    let v = match to_resized_png(
-                        &input_path,
-                        &output_path,
-                        width_command,
-                        height_command,
-                    ) {
+                    let options = resize_options(
+                        args.get(5).map(String::as_str),
+                        args.get(6).map(String::as_str),
+                        args.get(7).map(String::as_str),
+                    );
+
+                    let v = match options.and_then(|options| {
+                        to_resized_png(
+                            &input_path,
+                            &output_path,
+                            width_command,
+                            height_command,
+                            &options,
+                        )
+                    }) {
                         Ok(()) => 0,
                         Err(e) => e.to_code(),
                     };
@@ -86,3 +104,108 @@ pub fn execute(path: &str, request: &SaoriRequest, response: &mut SaoriResponse)
         }
     }
 }
+
+/// ToResizedPngの省略可能な引数から設定を作る
+///
+/// フィルタは、指定がないか空のときはLanczos3。Fast、Balanced、Highの目安も指定できる。
+/// 省略時にBalancedの目安(Catrom)を使わないのは、これまでと同じ出力にするため。
+/// 出力形式は、指定がないか空のときはpng。出力先の拡張子では決めない。
+/// 出力先のフォルダは、`1`のときだけ作る。指定がないか空、`0`のときは作らない。
+/// いずれも不明な値はParameterErrorにする。
+fn resize_options(
+    filter: Option<&str>,
+    format: Option<&str>,
+    create_dirs: Option<&str>,
+) -> Result<ResizeOptions, ResizedPngError> {
+    let base = match filter.filter(|v| !v.is_empty()) {
+        Some(v) => match (ResizeFilter::parse(v), ResizeQuality::parse(v)) {
+            (Some(filter), _) => ResizeOptions {
+                filter,
+                ..Default::default()
+            },
+            (None, Some(quality)) => ResizeOptions::from_quality(quality),
+            (None, None) => return Err(ResizedPngError::ParameterError(None)),
+        },
+        None => ResizeOptions::default(),
+    };
+    let output_format = match format.filter(|v| !v.is_empty()) {
+        Some(v) => OutputFormat::parse(v).ok_or(ResizedPngError::ParameterError(None))?,
+        None => OutputFormat::Png,
+    };
+    let create_parent_dirs = match create_dirs.filter(|v| !v.is_empty()) {
+        Some("1") => true,
+        Some("0") | None => false,
+        Some(_) => return Err(ResizedPngError::ParameterError(None)),
+    };
+
+    Ok(ResizeOptions {
+        output_format,
+        create_parent_dirs,
+        ..base
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod resize_options {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_filter_and_format_are_valid() {
+            let options = resize_options(Some("Point"), Some("webp"), Some("1")).unwrap();
+
+            assert_eq!(options.filter, ResizeFilter::Point);
+            assert_eq!(options.output_format, OutputFormat::Webp);
+            assert!(options.create_parent_dirs);
+
+            let options = resize_options(Some("High"), Some("JPEG"), Some("0")).unwrap();
+
+            assert_eq!(options.filter, ResizeFilter::Lanczos3);
+            assert!(options.linear_light);
+            assert_eq!(options.output_format, OutputFormat::Jpeg);
+            assert!(!options.create_parent_dirs);
+        }
+
+        #[test]
+        fn failed_when_filter_is_invalid() {
+            assert!(matches!(
+                resize_options(Some("Bicubic"), None, None),
+                Err(ResizedPngError::ParameterError(None))
+            ));
+        }
+
+        #[test]
+        fn failed_when_format_is_invalid() {
+            assert!(matches!(
+                resize_options(Some("Point"), Some("gif"), None),
+                Err(ResizedPngError::ParameterError(None))
+            ));
+        }
+
+        #[test]
+        fn failed_when_create_dirs_is_invalid() {
+            assert!(matches!(
+                resize_options(None, None, Some("yes")),
+                Err(ResizedPngError::ParameterError(None))
+            ));
+        }
+
+        #[test]
+        fn default_value_when_absent() {
+            let options = resize_options(None, None, None).unwrap();
+
+            assert_eq!(options.filter, ResizeFilter::Lanczos3);
+            assert_eq!(options.output_format, OutputFormat::Png);
+            assert!(!options.create_parent_dirs);
+
+            // 空の場合も省略と同じ。形式だけを指定するときに使う。
+            let options = resize_options(Some(""), Some(""), Some("")).unwrap();
+
+            assert_eq!(options.filter, ResizeFilter::Lanczos3);
+            assert_eq!(options.output_format, OutputFormat::Png);
+            assert!(!options.create_parent_dirs);
+        }
+    }
+}
@@ -1,263 +1,6329 @@
-use std::{num::NonZeroU32, path::PathBuf};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::Read,
+    num::{NonZeroU32, NonZeroUsize},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
 
-use rgb::FromSlice;
+use png::{BitDepth, ColorType, Compression, PixelDimensions};
+use rgb::{FromSlice, RGBA16, RGBA8};
 
 use crate::error::ResizedPngError;
 use crate::image;
+use crate::image::png::{PaletteIndexMode, WriteOptions};
+use crate::image::{DecodeLimits, ImageData, ImageFormat};
 
+/// 拡大縮小に使うフィルタ
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default)]
+pub enum ResizeFilter {
+    Point,
+    Triangle,
+    /// Catmull-Rom。Lanczos3より輪郭の縁のリンギングが少なく、写真の縮小に向く
+    Catrom,
+    /// Mitchell-Netravali。Catromより柔らかく、リンギングもギザギザもほとんど出ない
+    Mitchell,
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub(crate) fn parse(s: &str) -> Option<ResizeFilter> {
+        match s {
+            "Point" => Some(ResizeFilter::Point),
+            "Triangle" => Some(ResizeFilter::Triangle),
+            "Catrom" | "CatmullRom" | "Catmull-Rom" => Some(ResizeFilter::Catrom),
+            "Mitchell" | "MitchellNetravali" | "Mitchell-Netravali" => Some(ResizeFilter::Mitchell),
+            "Lanczos3" => Some(ResizeFilter::Lanczos3),
+            _ => None,
+        }
+    }
+
+    fn to_type(self) -> resize::Type {
+        match self {
+            ResizeFilter::Point => resize::Type::Point,
+            ResizeFilter::Triangle => resize::Type::Triangle,
+            ResizeFilter::Catrom => resize::Type::Catrom,
+            ResizeFilter::Mitchell => resize::Type::Mitchell,
+            ResizeFilter::Lanczos3 => resize::Type::Lanczos3,
+        }
+    }
+}
+
+/// 速さと質のどちらを優先するかの目安
+///
+/// 指定されたときだけ使う。省略時のフィルタは、これまでの出力を変えないようにLanczos3のままにするので、
+/// 既定の目安は持たない。
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ResizeQuality {
+    /// サムネイルなど、速さを優先する
+    Fast,
+    Balanced,
+    /// 保存用など、遅くても質を優先する
+    High,
+}
+
+impl ResizeQuality {
+    pub(crate) fn parse(s: &str) -> Option<ResizeQuality> {
+        match s {
+            "Fast" => Some(ResizeQuality::Fast),
+            "Balanced" => Some(ResizeQuality::Balanced),
+            "High" => Some(ResizeQuality::High),
+            _ => None,
+        }
+    }
+
+    fn filter(self) -> ResizeFilter {
+        match self {
+            ResizeQuality::Fast => ResizeFilter::Triangle,
+            ResizeQuality::Balanced => ResizeFilter::Catrom,
+            ResizeQuality::High => ResizeFilter::Lanczos3,
+        }
+    }
+
+    /// Highだけは、リニアに変換してから拡大縮小する
+    fn linear_light(self) -> bool {
+        self == ResizeQuality::High
+    }
+}
+
+type ResizeRgba8 = Box<dyn FnMut(&[RGBA8], &mut [RGBA8]) -> Result<(), resize::Error>>;
+type ResizeRgba16 = Box<dyn FnMut(&[RGBA16], &mut [RGBA16]) -> Result<(), resize::Error>>;
+/// 拡大縮小の元と先の幅と高さ、フィルタ
+type ResizerKey = (usize, usize, usize, usize, ResizeFilter);
+
+/// 同じ大きさの拡大縮小で使い回せるように、作ったresizerを持っておく
+#[derive(Default)]
+pub(crate) struct ResizerCache {
+    rgba8: HashMap<ResizerKey, ResizeRgba8>,
+    rgba16: HashMap<ResizerKey, ResizeRgba16>,
+}
+
+impl ResizerCache {
+    fn resize_rgba8(
+        &mut self,
+        key: ResizerKey,
+        src: &[RGBA8],
+        dist: &mut [RGBA8],
+    ) -> Result<(), ResizedPngError> {
+        let resize = match self.rgba8.entry(key) {
+            Entry::Occupied(v) => v.into_mut(),
+            Entry::Vacant(v) => {
+                let (src_width, src_height, dist_width, dist_height, filter) = key;
+                if filter == ResizeFilter::Point {
+                    v.insert(Box::new(move |src, dist| {
+                        resize_nearest(
+                            src,
+                            (src_width, src_height),
+                            dist,
+                            (dist_width, dist_height),
+                        )
+                    }))
+                } else {
+                    let mut resizer = resize::new(
+                        src_width,
+                        src_height,
+                        dist_width,
+                        dist_height,
+                        resize::Pixel::RGBA8P,
+                        filter.to_type(),
+                    )?;
+
+                    v.insert(Box::new(move |src, dist| resizer.resize(src, dist)))
+                }
+            }
+        };
+
+        Ok(resize(src, dist)?)
+    }
+
+    fn resize_rgba16(
+        &mut self,
+        key: ResizerKey,
+        src: &[RGBA16],
+        dist: &mut [RGBA16],
+    ) -> Result<(), ResizedPngError> {
+        let resize = match self.rgba16.entry(key) {
+            Entry::Occupied(v) => v.into_mut(),
+            Entry::Vacant(v) => {
+                let (src_width, src_height, dist_width, dist_height, filter) = key;
+                if filter == ResizeFilter::Point {
+                    v.insert(Box::new(move |src, dist| {
+                        resize_nearest(
+                            src,
+                            (src_width, src_height),
+                            dist,
+                            (dist_width, dist_height),
+                        )
+                    }))
+                } else {
+                    let mut resizer = resize::new(
+                        src_width,
+                        src_height,
+                        dist_width,
+                        dist_height,
+                        resize::Pixel::RGBA16P,
+                        filter.to_type(),
+                    )?;
+
+                    v.insert(Box::new(move |src, dist| resizer.resize(src, dist)))
+                }
+            }
+        };
+
+        Ok(resize(src, dist)?)
+    }
+}
+
+/// 出力のピクセルの中心に最も近い、元のピクセルの番号
+fn nearest_index(i: usize, input: usize, output: usize) -> usize {
+    ((2 * i as u64 + 1) * input as u64 / (2 * output as u64)) as usize
+}
+
+/// 出力のピクセルごとに、中心に最も近い元のピクセルを写す
+///
+/// resizeのPointは中心からずれた位置を選ぶので、拡大するとドット絵の境目が片寄る。
+fn resize_nearest<P: Copy>(
+    src: &[P],
+    (src_width, src_height): (usize, usize),
+    dist: &mut [P],
+    (dist_width, dist_height): (usize, usize),
+) -> Result<(), resize::Error> {
+    if src_width == 0
+        || src_height == 0
+        || dist_width == 0
+        || dist_height == 0
+        || src.len() < src_width * src_height
+        || dist.len() < dist_width * dist_height
+    {
+        return Err(resize::Error::InvalidParameters);
+    }
+
+    let columns: Vec<usize> = (0..dist_width)
+        .map(|x| nearest_index(x, src_width, dist_width))
+        .collect();
+    for (y, row) in dist
+        .chunks_exact_mut(dist_width)
+        .take(dist_height)
+        .enumerate()
+    {
+        let src_row = &src[nearest_index(y, src_height, dist_height) * src_width..];
+        for (pixel, x) in row.iter_mut().zip(&columns) {
+            *pixel = src_row[*x];
+        }
+    }
+
+    Ok(())
+}
+
+/// 拡大縮小で扱う1チャンネルの型。8bitか16bit
+trait Channel: Copy + PartialEq {
+    const MAX: Self;
+
+    /// 8bitの値をこの型の値に広げる
+    fn from_u8(v: u8) -> Self;
+
+    /// 計算のためにu64にする
+    fn to_u64(self) -> u64;
+
+    /// u64で計算した値を戻す。範囲に収まっている値だけを渡す
+    fn from_u64(v: u64) -> Self;
+
+    /// RGBAのバッファを拡大縮小する。元のバッファは拡大縮小が終わった時点で手放す
+    fn resize(
+        cache: &mut ResizerCache,
+        key: ResizerKey,
+        src: Vec<Self>,
+        buffer_size: usize,
+        linear_light: bool,
+    ) -> Result<Vec<Self>, ResizedPngError>;
+}
+
+impl Channel for u8 {
+    const MAX: Self = u8::MAX;
+
+    fn from_u8(v: u8) -> Self {
+        v
+    }
+
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+
+    fn from_u64(v: u64) -> Self {
+        v as u8
+    }
+
+    fn resize(
+        cache: &mut ResizerCache,
+        key: ResizerKey,
+        src: Vec<Self>,
+        buffer_size: usize,
+        linear_light: bool,
+    ) -> Result<Vec<Self>, ResizedPngError> {
+        if !linear_light {
+            let mut dist = vec![0; buffer_size];
+
+            cache.resize_rgba8(key, src.as_rgba(), dist.as_rgba_mut())?;
+
+            return Ok(dist);
+        }
+
+        // 8bitのままリニアにすると暗い部分の階調が潰れるので、16bitで扱う。
+        let table = srgb_to_linear_table();
+        let src_linear = srgb_to_linear(&src, &table);
+        drop(src);
+        let mut dist_linear = vec![0; buffer_size];
+
+        cache.resize_rgba16(key, src_linear.as_rgba(), dist_linear.as_rgba_mut())?;
+        drop(src_linear);
+
+        Ok(linear_to_srgb(&dist_linear, &table))
+    }
+}
+
+impl Channel for u16 {
+    const MAX: Self = u16::MAX;
+
+    fn from_u8(v: u8) -> Self {
+        v as u16 * 257
+    }
+
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+
+    fn from_u64(v: u64) -> Self {
+        v as u16
+    }
+
+    fn resize(
+        cache: &mut ResizerCache,
+        key: ResizerKey,
+        src: Vec<Self>,
+        buffer_size: usize,
+        linear_light: bool,
+    ) -> Result<Vec<Self>, ResizedPngError> {
+        let src = if linear_light {
+            map_rgb16(src, srgb_to_linear_value)
+        } else {
+            src
+        };
+        let mut dist = vec![0; buffer_size];
+
+        cache.resize_rgba16(key, src.as_rgba(), dist.as_rgba_mut())?;
+        drop(src);
+
+        Ok(if linear_light {
+            map_rgb16(dist, linear_to_srgb_value)
+        } else {
+            dist
+        })
+    }
+}
+
+/// pngで書き出す色形式
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum OutputColor {
+    /// 元の画像と設定から決める
+    #[default]
+    Auto,
+    Rgba,
+    /// アルファは背景色に合成する
+    Rgb,
+    /// RGBは輝度にし、アルファは背景色に合成する
+    Grayscale,
+    /// RGBは輝度にする
+    GrayscaleAlpha,
+}
+
+/// 縮小した画像に掛けるアンシャープマスクの強さ
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct UnsharpMask {
+    /// 元の画像とぼかした画像の差を足す割合
+    pub(crate) amount: f32,
+    /// ぼかしに使うガウス関数の標準偏差(ピクセル)
+    pub(crate) radius: f32,
+}
+
+/// アンシャープマスクのぼかしの半径の上限。カーネルの大きさが半径に比例するので抑える
+const MAX_UNSHARP_RADIUS: f32 = 100.0;
+
+impl UnsharpMask {
+    /// 割合は0以上、半径は0より大きく100以下。範囲外はParameterError
+    pub fn new(amount: f32, radius: f32) -> Result<UnsharpMask, ResizedPngError> {
+        let valid_amount = amount.is_finite() && amount >= 0.0;
+        let valid_radius = radius > 0.0 && radius <= MAX_UNSHARP_RADIUS;
+        if valid_amount && valid_radius {
+            Ok(UnsharpMask { amount, radius })
+        } else {
+            Err(ResizedPngError::ParameterError(None))
+        }
+    }
+}
+
+/// 出力する画像形式
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    /// 可逆圧縮のwebp
+    Webp,
+    /// 不透明な画像だけを書き出せる
+    Jpeg,
+}
+
+impl OutputFormat {
+    /// `png`、`webp`、`jpeg`(`jpg`)の名前を読み取る。大文字と小文字は区別しない
+    pub(crate) fn parse(s: &str) -> Option<OutputFormat> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Some(OutputFormat::Png),
+            "webp" => Some(OutputFormat::Webp),
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            _ => None,
+        }
+    }
+}
+
+/// 縦横比が元の画像と異なる大きさを指定されたときの合わせ方
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum FitMode {
+    /// 指定の大きさに引き伸ばす
+    #[default]
+    Stretch,
+    /// 縦横比を保って指定の大きさを覆うように拡大縮小し、はみ出した部分を中央で切り取る
+    Cover,
+    /// 縦横比を保って指定の大きさに収まるように拡大縮小し、余白を背景色で埋める
+    Contain,
+    /// 縦横比を保って指定の大きさに収まるように拡大縮小し、その大きさのまま出力する
+    Inside,
+}
+
+/// `FitMode::Contain`の余白の埋め方
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum PadFill {
+    /// `ResizeOptions::with_background`の色で埋める
+    #[default]
+    Fixed,
+    /// 拡大縮小した画像の縁のピクセルの平均の色で埋める
+    EdgeSample,
+    /// 拡大縮小した画像で最も多い色で埋める
+    Dominant,
+}
+
+/// 拡大縮小する前に元の画像を時計回りに回転する角度
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+/// 出力する大きさの指定
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SizeCommand {
+    /// 画素数。0は元の大きさ、-1はもう片方の拡大率に従う。それ以外の負の数は誤り
+    Pixel(i64),
+    /// 元の大きさに対する百分率
+    Percent(u32),
+}
+
+impl SizeCommand {
+    /// `50`のような数値か、`50%`のような百分率を読み取る
+    pub(crate) fn parse(s: &str) -> Option<SizeCommand> {
+        match s.strip_suffix('%') {
+            Some(v) => v.parse::<u32>().ok().map(SizeCommand::Percent),
+            None => s.parse::<i64>().ok().map(SizeCommand::Pixel),
+        }
+    }
+
+    /// 画素数の指定に直す。計算した画素数が0になる場合は1にする
+    ///
+    /// 0%は大きさを表さないので、-1未満の画素数と同じく指定の誤りとする。
+    fn to_pixel(self, input: NonZeroU32) -> Result<i64, ResizedPngError> {
+        match self {
+            SizeCommand::Pixel(v) => Ok(v),
+            SizeCommand::Percent(0) => Err(ResizedPngError::ParameterError(None)),
+            SizeCommand::Percent(v) => Ok((input.get() as i64 * v as i64 / 100).max(1)),
+        }
+    }
+}
+
+/// `200`のような数値、`50%`のような百分率、`auto`か`x`の文字列を、幅と高さの指定に直す
+///
+/// 0は元の大きさ、`auto`と`x`はもう片方の拡大率に従う`Pixel(-1)`になる。
+/// -1未満の数と0%は誤りとする。
+pub fn parse_dimension_command(s: &str) -> Result<SizeCommand, ResizedPngError> {
+    if s.eq_ignore_ascii_case("auto") || s.eq_ignore_ascii_case("x") {
+        return Ok(SizeCommand::Pixel(-1));
+    }
+
+    match SizeCommand::parse(s) {
+        Some(SizeCommand::Pixel(v)) if v >= -1 => Ok(SizeCommand::Pixel(v)),
+        Some(SizeCommand::Percent(v)) if v > 0 => Ok(SizeCommand::Percent(v)),
+        _ => Err(ResizedPngError::ParameterError(None)),
+    }
+}
+
+/// GetImageInfoで返す画像の情報
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) struct ImageInfo {
+    pub(crate) width: i64,
+    pub(crate) height: i64,
+    /// フレームの数。アニメーションに対応していない形式は1
+    pub(crate) frame_count: u32,
+}
+
+/// to_resized_png_with_resultで返す、拡大縮小の結果
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct ResizeResult {
+    /// 読み込んだ画像の形式
+    pub(crate) source_format: ImageFormat,
+    /// 読み込んだ画像の、回転する前の幅と高さ
+    pub(crate) source_size: (u32, u32),
+    /// 書き出した画像の幅と高さ
+    pub(crate) output_size: (u32, u32),
+    /// 書き出したファイルのバイト数
+    pub(crate) output_bytes_written: u64,
+}
+
+impl ImageInfo {
+    /// 2フレーム以上あるか。最初のフレームしか使われないことを知らせるため
+    pub(crate) fn is_animated(&self) -> bool {
+        self.frame_count > 1
+    }
+}
+
+impl ResizeResult {
+    pub fn source_format(&self) -> ImageFormat {
+        self.source_format
+    }
+
+    /// 回転する前の幅と高さ
+    pub fn source_size(&self) -> (u32, u32) {
+        self.source_size
+    }
+
+    pub fn output_size(&self) -> (u32, u32) {
+        self.output_size
+    }
+
+    pub fn output_bytes_written(&self) -> u64 {
+        self.output_bytes_written
+    }
+}
+
+/// 出力する画像の画素数の上限の既定値
+pub(crate) const DEFAULT_MAX_PIXELS: u64 = 10000 * 10000;
+
+/// 読み込み、拡大縮小、書き出しが終わったときの進み具合
+const PROGRESS_DECODED: f32 = 1.0 / 3.0;
+const PROGRESS_RESIZED: f32 = 2.0 / 3.0;
+const PROGRESS_ENCODED: f32 = 1.0;
+
+/// 書き出すpngの圧縮の強さ
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default)]
+pub enum PngCompression {
+    /// 速さを優先する。頻繁に書き出す一時的な画像向け
+    Fast,
+    #[default]
+    Default,
+    /// 大きさを優先する。配布する画像向け
+    Best,
+}
+
+impl PngCompression {
+    fn to_compression(self) -> Compression {
+        match self {
+            PngCompression::Fast => Compression::Fast,
+            PngCompression::Default => Compression::Default,
+            PngCompression::Best => Compression::Best,
+        }
+    }
+}
+
+/// 拡大縮小の設定
+///
+/// `ResizeOptions::default()`から、`with_`で始まるメソッドで項目を変えて使う。
+#[derive(Debug, Clone)]
+pub struct ResizeOptions {
+    pub(crate) filter: ResizeFilter,
+    /// 出力がグレースケールで表せる場合、グレースケールのpngとして書き出す
+    pub(crate) preserve_grayscale: bool,
+    /// 元がパレットの画像でフィルタがPointの場合、パレットのpngとして書き出す
+    pub(crate) preserve_indexed: bool,
+    /// 出力するpngのビット深度。8bitか16bit
+    pub(crate) bit_depth: BitDepth,
+    pub(crate) compression: Compression,
+    /// Adam7でインターレースしたpngとして書き出す
+    pub(crate) interlaced: bool,
+    /// 元の画像より大きくしない
+    pub(crate) no_upscale: bool,
+    /// 出力する画像の画素数の上限
+    pub(crate) max_pixels: u64,
+    pub(crate) output_format: OutputFormat,
+    pub(crate) fit: FitMode,
+    /// `FitMode::Contain`の余白の色(RGBA)
+    pub(crate) background: [u8; 4],
+    /// `FitMode::Contain`の余白を、`background`と画像から決める色のどちらで埋めるか
+    pub(crate) pad_fill: PadFill,
+    pub(crate) rotation: Rotation,
+    /// 回転の後に左右を反転する
+    pub(crate) flip_horizontal: bool,
+    /// 回転の後に上下を反転する
+    pub(crate) flip_vertical: bool,
+    /// 元のpngのpHYsを、拡大縮小の比率に合わせて書き出す。既定では書き出さない
+    pub(crate) preserve_pixel_dims: bool,
+    /// RGBをリニアに変換してから拡大縮小する。正確だが遅い
+    pub(crate) linear_light: bool,
+    /// jpegで書き出すときの画質。1から100
+    pub(crate) jpeg_quality: u8,
+    /// jpegなど不透明な形式で書き出すときに、アルファを合成する背景色(RGB)
+    pub(crate) opaque_background: [u8; 3],
+    /// gifのアニメーションで使うフレームの番号(0から)。足りない場合は最後のフレーム
+    pub(crate) gif_frame: usize,
+    /// 出力先のフォルダがなければ作る
+    pub(crate) create_parent_dirs: bool,
+    /// 16bitのpngをpngで書き出す場合、読み込みから書き出しまで16bitのまま扱う
+    pub(crate) preserve_16bit: bool,
+    /// pngのパレットの範囲外を指す番号の扱い
+    pub(crate) palette_index: PaletteIndexMode,
+    /// 元の画像によらず、この色形式で書き出す。アルファを落とす場合は`opaque_background`に合成する
+    pub(crate) output_color: OutputColor,
+    /// 出力の画素数の上限(メガピクセル)。越える場合は縦横比を保って縮める
+    ///
+    /// `max_pixels`と違い、越えてもエラーにはしない。
+    pub(crate) max_megapixels: Option<f64>,
+    /// pngのbKGDとして書き出す背景色(RGB)。Noneなら書き出さない
+    pub(crate) png_background: Option<[u8; 3]>,
+    /// 元のpngのICCプロファイルを書き出す。falseならexifなどと同じく捨てる
+    pub(crate) preserve_icc_profile: bool,
+    /// 元のpngのtEXtとzTXtを、tEXtとして書き出す。falseなら捨てる
+    pub(crate) preserve_text_chunks: bool,
+    /// 縮小した場合だけ、拡大縮小の後にアンシャープマスクを掛ける
+    pub(crate) sharpen: Option<UnsharpMask>,
+    /// 読み込む画像の大きさの上限
+    pub(crate) decode_limits: DecodeLimits,
+    /// 大きさを計算する前に、まわりの完全に透明な部分を切り取る
+    pub(crate) auto_trim: bool,
+    /// pngで書き出した後にファイルを読み直し、壊れていないか確かめる
+    pub(crate) verify_output: bool,
+    /// 色数によらず、256色に減色してパレットのpngで書き出す。質より大きさを優先する
+    pub(crate) quantize: bool,
+    /// `quantize`で減色するときに、Floyd-Steinbergのディザリングを掛けてグラデーションの縞を目立たなくする
+    pub(crate) dither: bool,
+    /// 拡大縮小する前に、RGBを輝度(Rec.709)にして色を抜く。書き出す色形式は変えない
+    pub(crate) desaturate: bool,
+    /// 元がpngで大きさが変わらない場合は、読み込み直さずにそのまま写す
+    ///
+    /// 回転や反転、色を変える指定がある場合は写さない。圧縮などの書き出しの設定は反映されない。
+    pub(crate) copy_through: bool,
+    /// 2フレーム以上のgifをpngで書き出す場合、全てのフレームを拡大縮小してapngにする
+    ///
+    /// `gif_frame`は使わない。apngは8bitのRGBAで書き出し、`auto_trim`は行わない。
+    pub(crate) animate_gif: bool,
+}
+
+impl ResizeOptions {
+    /// 目安に合わせてフィルタなどを決めた設定。それ以外は既定値
+    pub fn from_quality(quality: ResizeQuality) -> ResizeOptions {
+        ResizeOptions {
+            filter: quality.filter(),
+            linear_light: quality.linear_light(),
+            ..Default::default()
+        }
+    }
+
+    /// 元の画像より大きくしない
+    pub fn with_no_upscale(mut self, no_upscale: bool) -> Self {
+        self.no_upscale = no_upscale;
+        self
+    }
+
+    /// 拡大縮小に使うフィルタ。既定はLanczos3
+    pub fn with_filter(mut self, filter: ResizeFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// 出力がグレースケールで表せる場合、グレースケールのpngとして書き出す
+    pub fn with_preserve_grayscale(mut self, preserve_grayscale: bool) -> Self {
+        self.preserve_grayscale = preserve_grayscale;
+        self
+    }
+
+    /// 書き出すpngの圧縮の強さ
+    pub fn with_compression(mut self, compression: PngCompression) -> Self {
+        self.compression = compression.to_compression();
+        self
+    }
+
+    /// 16bitのpngとして書き出す。既定は8bit
+    pub fn with_16bit_output(mut self, sixteen_bit: bool) -> Self {
+        self.bit_depth = if sixteen_bit {
+            BitDepth::Sixteen
+        } else {
+            BitDepth::Eight
+        };
+        self
+    }
+
+    /// 出力する画像の画素数の上限。越える場合はエラーにする
+    pub fn with_max_pixels(mut self, max_pixels: u64) -> Self {
+        self.max_pixels = max_pixels;
+        self
+    }
+
+    /// 書き出す画像形式。既定はpng
+    pub fn with_output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// 縦横比が元の画像と異なる大きさを指定されたときの合わせ方。既定は引き伸ばす
+    pub fn with_fit(mut self, fit: FitMode) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// `FitMode::Contain`の余白の色(RGBA)
+    pub fn with_background(mut self, background: [u8; 4]) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// 拡大縮小する前に元の画像を時計回りに回転する角度
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// 回転の後に左右を反転する
+    pub fn with_flip_horizontal(mut self, flip_horizontal: bool) -> Self {
+        self.flip_horizontal = flip_horizontal;
+        self
+    }
+
+    /// 回転の後に上下を反転する
+    pub fn with_flip_vertical(mut self, flip_vertical: bool) -> Self {
+        self.flip_vertical = flip_vertical;
+        self
+    }
+
+    /// 元のpngのpHYsを、拡大縮小の比率に合わせて書き出す。既定では書き出さない
+    pub fn with_preserve_pixel_dims(mut self, preserve_pixel_dims: bool) -> Self {
+        self.preserve_pixel_dims = preserve_pixel_dims;
+        self
+    }
+
+    /// RGBをリニアに変換してから拡大縮小する。正確だが遅い
+    pub fn with_linear_light(mut self, linear_light: bool) -> Self {
+        self.linear_light = linear_light;
+        self
+    }
+
+    /// 元がパレットの画像でフィルタがPointの場合、パレットのpngとして書き出す
+    pub fn with_preserve_indexed(mut self, preserve_indexed: bool) -> Self {
+        self.preserve_indexed = preserve_indexed;
+        self
+    }
+
+    /// Adam7でインターレースしたpngとして書き出す
+    pub fn with_interlaced(mut self, interlaced: bool) -> Self {
+        self.interlaced = interlaced;
+        self
+    }
+
+    /// jpegで書き出すときの画質。1から100で、範囲外はその端に丸める。既定は90
+    pub fn with_jpeg_quality(mut self, jpeg_quality: u8) -> Self {
+        self.jpeg_quality = jpeg_quality;
+        self
+    }
+
+    /// jpegなど不透明な形式で書き出すときに、アルファを合成する背景色(RGB)。既定は白
+    pub fn with_opaque_background(mut self, opaque_background: [u8; 3]) -> Self {
+        self.opaque_background = opaque_background;
+        self
+    }
+
+    /// gifのアニメーションで使うフレームの番号(0から)。足りない場合は最後のフレーム
+    pub fn with_gif_frame(mut self, gif_frame: usize) -> Self {
+        self.gif_frame = gif_frame;
+        self
+    }
+
+    /// 出力先のフォルダがなければ作る
+    pub fn with_create_parent_dirs(mut self, create_parent_dirs: bool) -> Self {
+        self.create_parent_dirs = create_parent_dirs;
+        self
+    }
+
+    /// 16bitのpngをpngで書き出す場合、読み込みから書き出しまで16bitのまま扱う
+    pub fn with_preserve_16bit(mut self, preserve_16bit: bool) -> Self {
+        self.preserve_16bit = preserve_16bit;
+        self
+    }
+
+    /// pngのパレットの範囲外を指す番号の扱い。既定はエラーにする
+    pub fn with_palette_index(mut self, palette_index: PaletteIndexMode) -> Self {
+        self.palette_index = palette_index;
+        self
+    }
+
+    /// 元の画像によらず、この色形式のpngで書き出す。アルファを落とす場合は`opaque_background`に合成する
+    pub fn with_output_color(mut self, output_color: OutputColor) -> Self {
+        self.output_color = output_color;
+        self
+    }
+
+    /// 出力の画素数の上限(メガピクセル)。越える場合は縦横比を保って縮める
+    ///
+    /// `with_max_pixels`と違い、越えてもエラーにはしない。正の数でない場合は、拡大縮小するときにParameterErrorになる。
+    pub fn with_max_megapixels(mut self, max_megapixels: f64) -> Self {
+        self.max_megapixels = Some(max_megapixels);
+        self
+    }
+
+    /// pngのbKGDとして書き出す背景色(RGB)。既定では書き出さない
+    pub fn with_png_background(mut self, png_background: [u8; 3]) -> Self {
+        self.png_background = Some(png_background);
+        self
+    }
+
+    /// 元のpngのICCプロファイルを書き出す。既定では捨てる
+    pub fn with_preserve_icc_profile(mut self, preserve_icc_profile: bool) -> Self {
+        self.preserve_icc_profile = preserve_icc_profile;
+        self
+    }
+
+    /// 縮小したときに掛けるアンシャープマスク。既定では掛けない
+    pub fn with_sharpen(mut self, sharpen: UnsharpMask) -> Self {
+        self.sharpen = Some(sharpen);
+        self
+    }
+
+    /// 読み込む画像の大きさの上限
+    pub fn with_decode_limits(mut self, decode_limits: DecodeLimits) -> Self {
+        self.decode_limits = decode_limits;
+        self
+    }
+
+    /// 大きさを計算する前に、まわりの完全に透明な部分を切り取る
+    pub fn with_auto_trim(mut self, auto_trim: bool) -> Self {
+        self.auto_trim = auto_trim;
+        self
+    }
+
+    /// pngで書き出した後にファイルを読み直し、壊れていないか確かめる
+    pub fn with_verify_output(mut self, verify_output: bool) -> Self {
+        self.verify_output = verify_output;
+        self
+    }
+
+    /// 色数によらず、256色に減色してパレットのpngで書き出す。質より大きさを優先する
+    pub fn with_quantize(mut self, quantize: bool) -> Self {
+        self.quantize = quantize;
+        self
+    }
+
+    /// 拡大縮小する前に、RGBを輝度(Rec.709)にして色を抜く。書き出す色形式は変えない
+    pub fn with_desaturate(mut self, desaturate: bool) -> Self {
+        self.desaturate = desaturate;
+        self
+    }
+
+    /// 元がpngで大きさが変わらない場合は、読み込み直さずにそのまま写す。回転や反転、色を変える指定がある場合は写さない
+    pub fn with_copy_through(mut self, copy_through: bool) -> Self {
+        self.copy_through = copy_through;
+        self
+    }
+
+    /// 2フレーム以上のgifをpngで書き出す場合、全てのフレームを拡大縮小してapngにする
+    pub fn with_animate_gif(mut self, animate_gif: bool) -> Self {
+        self.animate_gif = animate_gif;
+        self
+    }
+
+    /// `with_quantize`で減色するときに、Floyd-Steinbergのディザリングを掛ける
+    pub fn with_dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// `FitMode::Contain`の余白の埋め方
+    pub fn with_pad_fill(mut self, pad_fill: PadFill) -> Self {
+        self.pad_fill = pad_fill;
+        self
+    }
+
+    /// 元のpngのtEXtとzTXtを、tEXtとして書き出す。既定では捨てる
+    pub fn with_preserve_text_chunks(mut self, preserve_text_chunks: bool) -> Self {
+        self.preserve_text_chunks = preserve_text_chunks;
+        self
+    }
+}
+
+impl Default for ResizeOptions {
+    fn default() -> Self {
+        ResizeOptions {
+            filter: ResizeFilter::default(),
+            preserve_grayscale: false,
+            preserve_indexed: false,
+            bit_depth: BitDepth::Eight,
+            compression: Compression::default(),
+            interlaced: false,
+            no_upscale: false,
+            max_pixels: DEFAULT_MAX_PIXELS,
+            output_format: OutputFormat::default(),
+            fit: FitMode::default(),
+            background: [0, 0, 0, 0],
+            pad_fill: PadFill::default(),
+            rotation: Rotation::default(),
+            flip_horizontal: false,
+            flip_vertical: false,
+            preserve_pixel_dims: false,
+            linear_light: false,
+            jpeg_quality: 90,
+            opaque_background: [255, 255, 255],
+            gif_frame: 0,
+            create_parent_dirs: false,
+            preserve_16bit: false,
+            palette_index: PaletteIndexMode::default(),
+            output_color: OutputColor::default(),
+            max_megapixels: None,
+            png_background: None,
+            preserve_icc_profile: false,
+            preserve_text_chunks: false,
+            sharpen: None,
+            decode_limits: DecodeLimits::default(),
+            auto_trim: false,
+            verify_output: false,
+            quantize: false,
+            dither: false,
+            desaturate: false,
+            copy_through: false,
+            animate_gif: false,
+        }
+    }
+}
+
+/// ファイルの先頭のバイト列から画像形式の名前を返す。判別できないときは`UNKNOWN`
 pub(crate) fn get_image_type(src_path: &PathBuf) -> &'static str {
-    if image::png::read_image_data(src_path).is_ok() {
-        return "PNG";
+    get_image_format(src_path).map_or("UNKNOWN", |v| v.name())
+}
+
+/// ファイルの先頭のバイト列から画像形式を返す。読めないか判別できないときはNone
+pub(crate) fn get_image_format(src_path: &PathBuf) -> Option<ImageFormat> {
+    let head = image::read_head(src_path).ok()?;
+
+    image::sniff_format(&head)
+}
+
+/// 先頭のバイト列から画像形式の名前を返す。判別できないときは`UNKNOWN`
+///
+/// 先頭の数バイトを見るだけで、画素は読まない。
+pub fn detect_format_from_bytes(bytes: &[u8]) -> &'static str {
+    image::sniff_format(bytes).map_or("UNKNOWN", |v| v.name())
+}
+
+pub(crate) fn get_image_info(src_path: &PathBuf) -> Result<ImageInfo, ResizedPngError> {
+    // 幅と高さを返すだけなので、画素は読まない。
+    let ((width, height), format) = image::read_dimensions(src_path)?;
+
+    let (input_width, input_height) = input_size(width, height)?;
+    let frame_count = image::frame_count(src_path, format)?;
+
+    Ok(ImageInfo {
+        width: input_width.get() as i64,
+        height: input_height.get() as i64,
+        frame_count,
+    })
+}
+
+/// 画素を読まずに、to_resized_pngで出力される幅と高さを求める。サイズが計算できないときはNone
+pub(crate) fn planned_output_size(
+    src_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Result<Option<(u32, u32)>, ResizedPngError> {
+    let ((width, height), _format) = image::read_dimensions(src_path)?;
+
+    let (width, height) = match options.rotation {
+        Rotation::Cw90 | Rotation::Cw270 => (height, width),
+        Rotation::None | Rotation::Cw180 => (width, height),
+    };
+    let (input_width, input_height) = input_size(width, height)?;
+
+    Ok(plan_size(
+        width_command,
+        height_command,
+        input_width,
+        input_height,
+        options,
+    )?
+    .map(|((width, height), _)| (width.get(), height.get())))
+}
+
+/// 画素を読まずに、拡大縮小で元の画像より大きくなるかを返す
+///
+/// 既定の設定で、幅と高さのどちらかが元より大きくなるときだけtrue。サイズが計算できないときはfalse。
+pub fn would_upscale(
+    src_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+) -> Result<bool, ResizedPngError> {
+    let ((width, height), _format) = image::read_dimensions(src_path)?;
+    let (input_width, input_height) = input_size(width, height)?;
+
+    let output = output_size(
+        width_command,
+        height_command,
+        input_width,
+        input_height,
+        false,
+    )?;
+
+    Ok(output.is_some_and(|(output_width, output_height)| {
+        output_width > input_width || output_height > input_height
+    }))
+}
+
+pub(crate) fn to_resized_png(
+    src_path: &PathBuf,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Result<(), ResizedPngError> {
+    to_resized_png_with_progress(
+        src_path,
+        dist_path,
+        width_command,
+        height_command,
+        options,
+        |_| {},
+    )
+}
+
+/// to_resized_pngと同じ。読み込んだ画像の形式や大きさ、書き出したバイト数を返す
+///
+/// サイズが計算できず、何も書き出さなかったときはNone。
+pub(crate) fn to_resized_png_with_result(
+    src_path: &PathBuf,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Result<Option<ResizeResult>, ResizedPngError> {
+    let mut cache = ResizerCache::default();
+
+    to_resized_png_with_cache(
+        src_path,
+        dist_path,
+        width_command,
+        height_command,
+        options,
+        &mut cache,
+        &|_| {},
+    )
+}
+
+/// 元の画像を短い辺に合わせて中央で正方形に切り取り、size×sizeに拡大縮小して書き出す
+///
+/// アイコンなどのため。切り取りは`FitMode::Cover`と同じ。sizeが0のときはParameterError。
+pub fn to_square_png(
+    src_path: &PathBuf,
+    dist_path: &PathBuf,
+    size: u32,
+) -> Result<(), ResizedPngError> {
+    if size == 0 {
+        return Err(ResizedPngError::ParameterError(None));
+    }
+
+    let options = ResizeOptions {
+        fit: FitMode::Cover,
+        ..Default::default()
+    };
+
+    to_resized_png(
+        src_path,
+        dist_path,
+        SizeCommand::Pixel(size as i64),
+        SizeCommand::Pixel(size as i64),
+        &options,
+    )
+}
+
+/// to_resized_pngと同じ。読み込み、拡大縮小、書き出しが終わるたびにprogressを呼ぶ
+///
+/// progressには進み具合を0.0から1.0で渡す。サイズが計算できないときは読み込みの後で終わる。
+pub(crate) fn to_resized_png_with_progress(
+    src_path: &PathBuf,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+    progress: impl Fn(f32),
+) -> Result<(), ResizedPngError> {
+    let mut cache = ResizerCache::default();
+
+    to_resized_png_with_cache(
+        src_path,
+        dist_path,
+        width_command,
+        height_command,
+        options,
+        &mut cache,
+        &progress,
+    )
+    .map(|_| ())
+}
+
+/// 複数の画像を同じ設定で拡大縮小する。結果は入力と同じ順で返す
+///
+/// 元と先の大きさが同じ画像の間ではresizerを使い回す。
+/// 途中で失敗しても、残りの画像の処理は続ける。
+pub(crate) fn resize_batch(
+    inputs: &[(PathBuf, PathBuf)],
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Vec<Result<(), ResizedPngError>> {
+    let mut cache = ResizerCache::default();
+
+    inputs
+        .iter()
+        .map(|(src_path, dist_path)| {
+            to_resized_png_with_cache(
+                src_path,
+                dist_path,
+                width_command,
+                height_command,
+                options,
+                &mut cache,
+                &|_| {},
+            )
+            .map(|_| ())
+        })
+        .collect()
+}
+
+/// resize_batchを、workersの数のスレッドで並列に行う。結果は入力と同じ順で返す
+pub(crate) fn resize_batch_parallel(
+    inputs: &[(PathBuf, PathBuf)],
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+    workers: NonZeroUsize,
+) -> Vec<Result<(), ResizedPngError>> {
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<Result<(), ResizedPngError>>> =
+        inputs.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers.get().min(inputs.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    // resizerはスレッドをまたいで使えないので、スレッドごとに持つ。
+                    let mut cache = ResizerCache::default();
+                    let mut done = Vec::new();
+
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((src_path, dist_path)) = inputs.get(index) else {
+                            break;
+                        };
+
+                        let result = to_resized_png_with_cache(
+                            src_path,
+                            dist_path,
+                            width_command,
+                            height_command,
+                            options,
+                            &mut cache,
+                            &|_| {},
+                        )
+                        .map(|_| ());
+                        done.push((index, result));
+                    }
+
+                    done
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            let done = handle
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e));
+
+            for (index, result) in done {
+                results[index] = Some(result);
+            }
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|v| v.expect("every input is processed"))
+        .collect()
+}
+
+/// to_resized_pngと同じ。画像をファイルではなく、readerから最後まで読み込む
+///
+/// 標準入力などから流し込めるように。形式は読み込んだバイト列の先頭から判別する。
+pub(crate) fn to_resized_png_from_reader<R: Read>(
+    mut reader: R,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Result<(), ResizedPngError> {
+    let mut src = Vec::new();
+    reader.read_to_end(&mut src)?;
+
+    let mut cache = ResizerCache::default();
+
+    resize_bytes_to_file(
+        src,
+        dist_path,
+        width_command,
+        height_command,
+        options,
+        &mut cache,
+        &|_| {},
+    )
+    .map(|_| ())
+}
+
+fn to_resized_png_with_cache(
+    src_path: &PathBuf,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+    cache: &mut ResizerCache,
+    progress: &dyn Fn(f32),
+) -> Result<Option<ResizeResult>, ResizedPngError> {
+    let src = std::fs::read(src_path)?;
+
+    resize_bytes_to_file(
+        src,
+        dist_path,
+        width_command,
+        height_command,
+        options,
+        cache,
+        progress,
+    )
+}
+
+/// 読み込んだ画像のバイト列を拡大縮小し、dist_pathに書き出す。サイズが計算できないときはNone
+fn resize_bytes_to_file(
+    src: Vec<u8>,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+    cache: &mut ResizerCache,
+    progress: &dyn Fn(f32),
+) -> Result<Option<ResizeResult>, ResizedPngError> {
+    // 前の段階が終わってからの時間を、段階ごとに出力する。
+    #[cfg(feature = "log")]
+    let stage_started = std::cell::Cell::new(std::time::Instant::now());
+    #[cfg(feature = "log")]
+    let progress = &|v: f32| {
+        let stage = if v <= PROGRESS_DECODED {
+            "decode"
+        } else if v <= PROGRESS_RESIZED {
+            "resize"
+        } else {
+            "encode"
+        };
+        let elapsed = stage_started.replace(std::time::Instant::now()).elapsed();
+        log::debug!("{} finished in {:?}", stage, elapsed);
+        progress(v)
+    };
+
+    if let Some(source_size) = copy_through_size(&src, width_command, height_command, options)? {
+        create_parent_dir(dist_path, options)?;
+        std::fs::write(dist_path, &src)?;
+        progress(PROGRESS_DECODED);
+        progress(PROGRESS_RESIZED);
+        progress(PROGRESS_ENCODED);
+
+        return written_result(dist_path, ImageFormat::Png, source_size, source_size).map(Some);
+    }
+
+    if let Some(frames) = read_gif_frames(&src, options)? {
+        drop(src);
+        progress(PROGRESS_DECODED);
+
+        return resize_frames_to_file(
+            frames,
+            dist_path,
+            width_command,
+            height_command,
+            options,
+            cache,
+            progress,
+        );
+    }
+
+    if let Some(indexed) = read_indexed_source(&src, options)? {
+        drop(src);
+        progress(PROGRESS_DECODED);
+
+        return resize_indices_to_file(
+            indexed,
+            dist_path,
+            width_command,
+            height_command,
+            options,
+            progress,
+        );
+    }
+
+    if let Some(src16) = read_16bit_source(&src, options)? {
+        drop(src);
+        progress(PROGRESS_DECODED);
+        let original_size = (src16.width, src16.height);
+        let resized = match resize_pixels(
+            src16,
+            original_size,
+            width_command,
+            height_command,
+            options,
+            cache,
+        )? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        progress(PROGRESS_RESIZED);
+
+        let output_size = (resized.1, resized.2);
+        write_resized16(dist_path, resized, options)?;
+        progress(PROGRESS_ENCODED);
+
+        return written_result(dist_path, ImageFormat::Png, original_size, output_size).map(Some);
+    }
+
+    // サイズが計算できないときは、何もせず終了。
+    let (resized, format, original_size) = match resize_image(
+        &src,
+        width_command,
+        height_command,
+        options,
+        cache,
+        progress,
+    )? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    // 書き出す間まで元のファイルの中身を持たない。
+    drop(src);
+    progress(PROGRESS_RESIZED);
+
+    let output_size = (resized.1, resized.2);
+    write_resized(dist_path, resized, options)?;
+    progress(PROGRESS_ENCODED);
+
+    written_result(dist_path, format, original_size, output_size).map(Some)
+}
+
+/// `animate_gif`の指定があってpngで書き出す場合に、2フレーム以上のgifの全てのフレームを読み込む
+fn read_gif_frames(
+    src: &[u8],
+    options: &ResizeOptions,
+) -> Result<Option<Vec<(ImageData, u16)>>, ResizedPngError> {
+    if !options.animate_gif
+        || options.output_format != OutputFormat::Png
+        || image::sniff_format(src) != Some(ImageFormat::Gif)
+    {
+        return Ok(None);
+    }
+
+    options.decode_limits.check_header(src)?;
+    let frames = image::gif::read_frames_from_bytes(src, &options.decode_limits)?;
+
+    // 1フレームならアニメーションにせず、いつものpngにする。
+    Ok((frames.len() > 1).then_some(frames))
+}
+
+/// gifの全てのフレームを拡大縮小し、apngとしてdist_pathに書き出す。サイズが計算できないときはNone
+fn resize_frames_to_file(
+    frames: Vec<(ImageData, u16)>,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+    cache: &mut ResizerCache,
+    progress: &dyn Fn(f32),
+) -> Result<Option<ResizeResult>, ResizedPngError> {
+    // フレームごとに切り取る範囲が変わると大きさが揃わないので、切り取らない。
+    let options = &ResizeOptions {
+        auto_trim: false,
+        ..options.clone()
+    };
+    let original_size = (frames[0].0.width, frames[0].0.height);
+
+    let mut resized_frames = Vec::with_capacity(frames.len());
+    let mut output = None;
+    for (frame, delay) in frames {
+        let Some((rgba, width, height, write_options)) = resize_pixels(
+            frame,
+            original_size,
+            width_command,
+            height_command,
+            options,
+            cache,
+        )?
+        else {
+            return Ok(None);
+        };
+        resized_frames.push((rgba, delay));
+        output = Some((width, height, write_options));
+    }
+    let Some((width, height, write_options)) = output else {
+        return Ok(None);
+    };
+    progress(PROGRESS_RESIZED);
+
+    create_parent_dir(dist_path, options)?;
+    image::png::write_apng(dist_path, &resized_frames, width, height, &write_options)?;
+    progress(PROGRESS_ENCODED);
+
+    written_result(dist_path, ImageFormat::Gif, original_size, (width, height)).map(Some)
+}
+
+/// `preserve_indexed`の指定があってフィルタがPointの場合に、パレットのpngを番号のまま読み込む
+///
+/// 番号を選ぶだけで済む場合に限る。回転や反転、余白や色を変える指定がある場合はNone
+fn read_indexed_source(
+    src: &[u8],
+    options: &ResizeOptions,
+) -> Result<Option<image::png::PaletteImage>, ResizedPngError> {
+    let changes_pixels = options.rotation != Rotation::None
+        || options.flip_horizontal
+        || options.flip_vertical
+        || options.desaturate
+        || options.auto_trim
+        || options.quantize
+        || options.sharpen.is_some()
+        || options.output_color != OutputColor::Auto
+        || !matches!(options.fit, FitMode::Stretch | FitMode::Inside);
+    if !options.preserve_indexed
+        || options.filter != ResizeFilter::Point
+        || changes_pixels
+        || options.output_format != OutputFormat::Png
+        || image::sniff_format(src) != Some(ImageFormat::Png)
+    {
+        return Ok(None);
+    }
+
+    options.decode_limits.check_header(src)?;
+    image::png::read_palette_image_from_bytes(src)
+}
+
+/// パレットの番号を最近傍で拡大縮小し、元のパレットのままdist_pathに書き出す。サイズが計算できないときはNone
+fn resize_indices_to_file(
+    src: image::png::PaletteImage,
+    dist_path: &PathBuf,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+    progress: &dyn Fn(f32),
+) -> Result<Option<ResizeResult>, ResizedPngError> {
+    let (input_width, input_height) = input_size(src.width, src.height)?;
+    // StretchとInsideだけなので、出力する大きさは拡大縮小する大きさと同じ。
+    let Some(((output_width, output_height), _)) = plan_size(
+        width_command,
+        height_command,
+        input_width,
+        input_height,
+        options,
+    )?
+    else {
+        return Ok(None);
+    };
+    output_buffer_size(output_width, output_height, options.max_pixels)?;
+
+    debug_log!(
+        "resizing palette indices {}x{} to {}x{}",
+        src.width,
+        src.height,
+        output_width,
+        output_height
+    );
+
+    let output_pixels = (output_width.get() as usize, output_height.get() as usize);
+    let mut indices = vec![0; output_pixels.0 * output_pixels.1];
+    resize_nearest(
+        &src.indices,
+        (src.width as usize, src.height as usize),
+        &mut indices,
+        output_pixels,
+    )?;
+    progress(PROGRESS_RESIZED);
+
+    let pixel_dims = match src.pixel_dims {
+        Some(v) if options.preserve_pixel_dims => Some(scale_pixel_dims(
+            v,
+            Rotation::None,
+            (input_width, input_height),
+            (output_width, output_height),
+        )),
+        _ => None,
+    };
+    let write_options = WriteOptions {
+        color_type: ColorType::Indexed,
+        bit_depth: BitDepth::Eight,
+        compression: options.compression,
+        pixel_dims,
+        interlaced: options.interlaced,
+        background: options.png_background,
+        icc_profile: src.icc_profile.filter(|_| options.preserve_icc_profile),
+        text_chunks: if options.preserve_text_chunks {
+            src.text_chunks
+        } else {
+            Vec::new()
+        },
+        verify: options.verify_output,
+        quantize: false,
+        dither: false,
+    };
+
+    create_parent_dir(dist_path, options)?;
+    image::png::write_palette_png(
+        dist_path,
+        &indices,
+        output_width.get(),
+        output_height.get(),
+        &src.palette,
+        &src.trns,
+        &write_options,
+    )?;
+    progress(PROGRESS_ENCODED);
+
+    written_result(
+        dist_path,
+        ImageFormat::Png,
+        (src.width, src.height),
+        (output_width.get(), output_height.get()),
+    )
+    .map(Some)
+}
+
+/// `copy_through`で元のpngをそのまま写せる場合に、その大きさを返す。写せないときはNone
+fn copy_through_size(
+    src: &[u8],
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Result<Option<(u32, u32)>, ResizedPngError> {
+    let changes_pixels = options.rotation != Rotation::None
+        || options.flip_horizontal
+        || options.flip_vertical
+        || options.desaturate
+        || options.auto_trim
+        || options.quantize
+        || options.output_color != OutputColor::Auto;
+    if !options.copy_through
+        || changes_pixels
+        || options.output_format != OutputFormat::Png
+        || image::sniff_format(src) != Some(ImageFormat::Png)
+    {
+        return Ok(None);
+    }
+
+    options.decode_limits.check_header(src)?;
+    let (width, height) = image::png::read_dimensions_from_bytes(src)?;
+    let (input_width, input_height) = input_size(width, height)?;
+    let unchanged = plan_size(
+        width_command,
+        height_command,
+        input_width,
+        input_height,
+        options,
+    )?
+    .is_some_and(|(output, resize)| output == resize && output == (input_width, input_height));
+
+    Ok(unchanged.then_some((width, height)))
+}
+
+/// 書き出したファイルの大きさを調べて、ResizeResultにまとめる
+fn written_result(
+    dist_path: &PathBuf,
+    source_format: ImageFormat,
+    source_size: (u32, u32),
+    output_size: (u32, u32),
+) -> Result<ResizeResult, ResizedPngError> {
+    let output_bytes_written = std::fs::metadata(dist_path)?.len();
+
+    Ok(ResizeResult {
+        source_format,
+        source_size,
+        output_size,
+        output_bytes_written,
+    })
+}
+
+/// 一つの画像を、targetsの出力先と大きさのそれぞれに拡大縮小する。結果はtargetsと同じ順で返す
+///
+/// 画像は一度だけ読み込む。読み込みに失敗した場合だけ全体をエラーにし、
+/// 出力先ごとの失敗では残りの出力先の処理を続ける。
+pub(crate) fn to_resized_pngs(
+    src_path: &PathBuf,
+    targets: &[(PathBuf, SizeCommand, SizeCommand)],
+    options: &ResizeOptions,
+) -> Result<Vec<Result<(), ResizedPngError>>, ResizedPngError> {
+    let src = std::fs::read(src_path)?;
+    let mut cache = ResizerCache::default();
+
+    if let Some(src16) = read_16bit_source(&src, options)? {
+        drop(src);
+        let original_size = (src16.width, src16.height);
+
+        return Ok(targets
+            .iter()
+            .map(
+                |(dist_path, width_command, height_command)| -> Result<(), ResizedPngError> {
+                    if let Some(resized) = resize_pixels(
+                        src16.clone(),
+                        original_size,
+                        *width_command,
+                        *height_command,
+                        options,
+                        &mut cache,
+                    )? {
+                        write_resized16(dist_path, resized, options)?;
+                    }
+                    Ok(())
+                },
+            )
+            .collect());
+    }
+
+    // jpegは、一番大きい出力先の大きさを下回らない範囲で縮小しながら読み込む。
+    let (src, _format, original_size) = image::read_any_scaled_from_bytes(
+        &src,
+        options.gif_frame,
+        options.palette_index,
+        &options.decode_limits,
+        |width, height| {
+            targets
+                .iter()
+                .map(|(_, width_command, height_command)| {
+                    decode_size(width, height, *width_command, *height_command, options)
+                })
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .reduce(|a, b| (a.0.max(b.0), a.1.max(b.1)))
+        },
+    )?;
+
+    Ok(targets
+        .iter()
+        .map(
+            |(dist_path, width_command, height_command)| -> Result<(), ResizedPngError> {
+                if let Some(resized) = resize_pixels(
+                    src.clone(),
+                    original_size,
+                    *width_command,
+                    *height_command,
+                    options,
+                    &mut cache,
+                )? {
+                    write_resized(dist_path, resized, options)?;
+                }
+                Ok(())
+            },
+        )
+        .collect())
+}
+
+/// 16bitのまま拡大縮小した内容をpngで書き出す
+fn write_resized16(
+    dist_path: &PathBuf,
+    resized: ResizedImage<u16>,
+    options: &ResizeOptions,
+) -> Result<(), ResizedPngError> {
+    let (dist_rgba, output_width, output_height, write_options) = resized;
+
+    create_parent_dir(dist_path, options)?;
+    image::png::write_png16(
+        dist_path,
+        &dist_rgba,
+        output_width,
+        output_height,
+        &write_options,
+    )
+}
+
+/// 拡大縮小した内容を、指定の形式で書き出す
+fn write_resized(
+    dist_path: &PathBuf,
+    resized: ResizedImage,
+    options: &ResizeOptions,
+) -> Result<(), ResizedPngError> {
+    let (dist_rgba, output_width, output_height, write_options) = resized;
+
+    create_parent_dir(dist_path, options)?;
+
+    match options.output_format {
+        OutputFormat::Png => image::png::write_png(
+            dist_path,
+            &dist_rgba,
+            output_width,
+            output_height,
+            &write_options,
+        )?,
+        OutputFormat::Webp => {
+            image::webp::write_webp(dist_path, &dist_rgba, output_width, output_height)?
+        }
+        OutputFormat::Jpeg => image::jpeg::write_jpeg(
+            dist_path,
+            &flatten_rgba(
+                dist_rgba,
+                output_width,
+                output_height,
+                options.opaque_background,
+            ),
+            output_width,
+            output_height,
+            options.jpeg_quality,
+        )?,
+    }
+
+    Ok(())
+}
+
+/// 指定があれば、出力先のフォルダを作る
+fn create_parent_dir(dist_path: &Path, options: &ResizeOptions) -> Result<(), ResizedPngError> {
+    if options.create_parent_dirs {
+        if let Some(parent) = dist_path.parent().filter(|v| !v.exists()) {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// メモリ上の画像を拡大縮小し、ファイルに書き出さずにpngのバイト列で返す
+pub(crate) fn to_resized_png_bytes(
+    src: &[u8],
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Result<Vec<u8>, ResizedPngError> {
+    // サイズが計算できないときは、空のバイト列を返す。
+    let mut cache = ResizerCache::default();
+
+    if let Some(src16) = read_16bit_source(src, options)? {
+        let original_size = (src16.width, src16.height);
+        return match resize_pixels(
+            src16,
+            original_size,
+            width_command,
+            height_command,
+            options,
+            &mut cache,
+        )? {
+            Some((dist_rgba, output_width, output_height, write_options)) => {
+                image::png::encode_png16(&dist_rgba, output_width, output_height, &write_options)
+            }
+            None => Ok(Vec::new()),
+        };
+    }
+
+    let ((dist_rgba, output_width, output_height, write_options), _, _) = match resize_image(
+        src,
+        width_command,
+        height_command,
+        options,
+        &mut cache,
+        &|_| {},
+    )? {
+        Some(v) => v,
+        None => return Ok(Vec::new()),
+    };
+
+    image::png::encode_png(&dist_rgba, output_width, output_height, &write_options)
+}
+
+/// to_resized_png_bytes_withinで、縮める割合を探す回数の上限
+///
+/// 1%から100%までを二分探索するので、幅が100から1になるまでの7回で足りる。
+const BUDGET_SEARCH_STEPS: usize = 7;
+
+/// メモリ上の画像を、pngのバイト列がbudget以下になるように縦横比を保って縮めて返す
+///
+/// 元の大きさで収まればそのまま返す。収まらなければ、収まる最も大きい割合(1%単位)を二分探索する。
+/// 1%まで縮めても収まらない場合は、試した中で最も小さいものを返す。
+pub(crate) fn to_resized_png_bytes_within(
+    src: &[u8],
+    budget: usize,
+    options: &ResizeOptions,
+) -> Result<Vec<u8>, ResizedPngError> {
+    let encode = |percent| {
+        to_resized_png_bytes(
+            src,
+            SizeCommand::Percent(percent),
+            SizeCommand::Percent(percent),
+            options,
+        )
+    };
+
+    let mut smallest = encode(100)?;
+    if smallest.len() <= budget {
+        return Ok(smallest);
+    }
+
+    // fittingは収まった割合、over_budgetは収まらなかった割合。
+    let (mut fitting, mut over_budget) = (0, 100);
+    let mut best = None;
+    for _ in 0..BUDGET_SEARCH_STEPS {
+        if over_budget - fitting <= 1 {
+            break;
+        }
+
+        let percent = (fitting + over_budget) / 2;
+        let bytes = encode(percent)?;
+        if bytes.len() <= budget {
+            fitting = percent;
+            best = Some(bytes);
+        } else {
+            over_budget = percent;
+            smallest = bytes;
+        }
+    }
+
+    Ok(best.unwrap_or(smallest))
+}
+
+/// 拡大縮小した画像のRGBAと幅と高さ、書き出しの設定
+type ResizedImage<T = u8> = (Vec<T>, u32, u32, WriteOptions);
+
+/// 拡大縮小した画像と、元の形式と元の大きさ
+type ResizedSource = (ResizedImage, ImageFormat, (u32, u32));
+
+/// 画像を読み込んで拡大縮小し、書き出す内容と元の形式、元の大きさを返す。サイズが計算できないときはNone
+fn resize_image(
+    src: &[u8],
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+    cache: &mut ResizerCache,
+    progress: &dyn Fn(f32),
+) -> Result<Option<ResizedSource>, ResizedPngError> {
+    // jpegは、拡大縮小する大きさを下回らない範囲で縮小しながら読み込む。
+    let (src, format, original_size) = image::read_any_scaled_from_bytes(
+        src,
+        options.gif_frame,
+        options.palette_index,
+        &options.decode_limits,
+        |width, height| decode_size(width, height, width_command, height_command, options),
+    )?;
+    progress(PROGRESS_DECODED);
+
+    Ok(resize_pixels(
+        src,
+        original_size,
+        width_command,
+        height_command,
+        options,
+        cache,
+    )?
+    .map(|resized| (resized, format, original_size)))
+}
+
+/// 読み込む前の向きの幅と高さから、拡大縮小する大きさを読み込む前の向きで返す
+fn decode_size(
+    width: u32,
+    height: u32,
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+) -> Option<(u32, u32)> {
+    let (width, height) = rotated_size(width, height, options.rotation);
+    let (input_width, input_height) = NonZeroU32::new(width).zip(NonZeroU32::new(height))?;
+    let (_, (resize_width, resize_height)) = plan_size(
+        width_command,
+        height_command,
+        input_width,
+        input_height,
+        options,
+    )
+    .ok()
+    .flatten()?;
+
+    Some(rotated_size(
+        resize_width.get(),
+        resize_height.get(),
+        options.rotation,
+    ))
+}
+
+/// 16bitを保つ指定があってpngで書き出す場合に、16bitのまま扱える画像を読み込む
+fn read_16bit_source(
+    src: &[u8],
+    options: &ResizeOptions,
+) -> Result<Option<ImageData<u16>>, ResizedPngError> {
+    // 減色する場合は8bitで扱う。
+    if !options.preserve_16bit || options.output_format != OutputFormat::Png || options.quantize {
+        return Ok(None);
+    }
+
+    image::read_16bit_from_bytes(src, &options.decode_limits)
+}
+
+/// 読み込んだ画像を拡大縮小し、書き出す内容を返す。サイズが計算できないときはNone
+///
+/// 大きさは`original_size`(縮小して読み込む前の元の大きさ)で計算する。
+fn resize_pixels<T: Channel>(
+    src: ImageData<T>,
+    original_size: (u32, u32),
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    options: &ResizeOptions,
+    cache: &mut ResizerCache,
+) -> Result<Option<ResizedImage<T>>, ResizedPngError> {
+    let (src_rgba, src_width, src_height) =
+        rotate_rgba(src.pixels, src.width, src.height, options.rotation);
+    let src_rgba = flip_rgba(
+        src_rgba,
+        src_width,
+        src_height,
+        options.flip_horizontal,
+        options.flip_vertical,
+    );
+    let src_rgba = if options.desaturate {
+        desaturate_rgba(src_rgba)
+    } else {
+        src_rgba
+    };
+
+    // 大きさの指定は回転した後の向きに対して行う。
+    let (original_width, original_height) =
+        rotated_size(original_size.0, original_size.1, options.rotation);
+
+    // 透明な余白を切り取り、大きさも切り取った後の大きさで計算する。全て透明ならそのまま。
+    let trim = options
+        .auto_trim
+        .then(|| opaque_bounds(&src_rgba, src_width, src_height))
+        .flatten()
+        .filter(|&(_, _, width, height)| (width, height) != (src_width, src_height));
+    let (src_rgba, src_width, src_height, original_width, original_height) = match trim {
+        Some((x, y, width, height)) => {
+            // 縮小して読み込んだ場合は、元の大きさも同じ割合で切り取る。
+            let scale = |original: u32, trimmed: u32, decoded: u32| {
+                (original as u64 * trimmed as u64 / decoded as u64).max(1) as u32
+            };
+            (
+                crop_rgba(src_rgba, src_width, x, y, width, height),
+                width,
+                height,
+                scale(original_width, width, src_width),
+                scale(original_height, height, src_height),
+            )
+        }
+        None => (
+            src_rgba,
+            src_width,
+            src_height,
+            original_width,
+            original_height,
+        ),
+    };
+    let (input_width, input_height) = input_size(original_width, original_height)?;
+
+    let ((output_width, output_height), (resize_width, resize_height)) = match plan_size(
+        width_command,
+        height_command,
+        input_width,
+        input_height,
+        options,
+    )? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    debug_log!(
+        "resizing {}x{} to {}x{} (output {}x{})",
+        src_width,
+        src_height,
+        resize_width,
+        resize_height,
+        output_width,
+        output_height
+    );
+
+    // 余白をつける場合は、出力の方が大きい。
+    output_buffer_size(output_width, output_height, options.max_pixels)?;
+    let buffer_size = output_buffer_size(resize_width, resize_height, options.max_pixels)?;
+
+    // RGBA8P、RGBA16Pは内部で乗算済みアルファに変換してから拡大縮小し、元に戻して返す。
+    // そのため、透明な部分の色が縁ににじむことはない。
+    let key = (
+        src_width as usize,
+        src_height as usize,
+        resize_width.get() as usize,
+        resize_height.get() as usize,
+        options.filter,
+    );
+    let mut dist_rgba = T::resize(cache, key, src_rgba, buffer_size, options.linear_light)?;
+
+    // 縮小でぼやけた分を戻す。余白をつける前に掛けて、余白との境目は強めない。
+    let downscaled = (resize_width.get() as u64 * resize_height.get() as u64)
+        < (input_width.get() as u64 * input_height.get() as u64);
+    if let Some(sharpen) = options.sharpen.filter(|_| downscaled) {
+        dist_rgba = unsharp_mask(
+            dist_rgba,
+            resize_width.get(),
+            resize_height.get(),
+            sharpen.amount,
+            sharpen.radius,
+        );
+    }
+
+    match options.fit {
+        FitMode::Stretch | FitMode::Inside => {}
+        // 縦横比が同じで、はみ出していなければ切り取らない。
+        FitMode::Cover if (resize_width, resize_height) == (output_width, output_height) => {}
+        // はみ出した部分を中央で切り取る。
+        FitMode::Cover => {
+            dist_rgba = crop_rgba(
+                dist_rgba,
+                resize_width.get(),
+                (resize_width.get() - output_width.get()) / 2,
+                (resize_height.get() - output_height.get()) / 2,
+                output_width.get(),
+                output_height.get(),
+            );
+        }
+        // 背景色で埋めた出力の中央に置く。
+        FitMode::Contain => {
+            let background = match options.pad_fill {
+                PadFill::Fixed => options.background.map(T::from_u8),
+                PadFill::EdgeSample => {
+                    edge_color(&dist_rgba, resize_width.get(), resize_height.get())
+                }
+                PadFill::Dominant => dominant_color(&dist_rgba),
+            };
+            dist_rgba = pad_rgba(
+                &dist_rgba,
+                resize_width.get(),
+                resize_height.get(),
+                output_width.get(),
+                output_height.get(),
+                background,
+            );
+        }
+    }
+
+    let opaque_background = options.opaque_background.map(T::from_u8);
+    let color_type = match options.output_color {
+        // Pointなら元のパレットにない色は増えない。増えた場合はRGBAで書き出される。
+        OutputColor::Auto
+            if options.preserve_indexed
+                && src.source_color == image::SourceColorType::Indexed
+                && options.filter == ResizeFilter::Point =>
+        {
+            ColorType::Indexed
+        }
+        OutputColor::Auto if options.quantize => ColorType::Indexed,
+        OutputColor::Auto if options.preserve_grayscale && is_grayscale(&dist_rgba) => {
+            ColorType::Grayscale
+        }
+        OutputColor::Auto | OutputColor::Rgba => ColorType::Rgba,
+        OutputColor::Rgb => {
+            dist_rgba = flatten_rgba(
+                dist_rgba,
+                output_width.get(),
+                output_height.get(),
+                opaque_background,
+            );
+            ColorType::Rgb
+        }
+        OutputColor::Grayscale => {
+            dist_rgba = to_luma_rgba(flatten_rgba(
+                dist_rgba,
+                output_width.get(),
+                output_height.get(),
+                opaque_background,
+            ));
+            ColorType::Grayscale
+        }
+        OutputColor::GrayscaleAlpha => {
+            dist_rgba = to_luma_rgba(dist_rgba);
+            ColorType::GrayscaleAlpha
+        }
+    };
+
+    let pixel_dims = match src.pixel_dims {
+        Some(v) if options.preserve_pixel_dims => Some(scale_pixel_dims(
+            v,
+            options.rotation,
+            (input_width, input_height),
+            (resize_width, resize_height),
+        )),
+        _ => None,
+    };
+
+    let write_options = WriteOptions {
+        color_type,
+        bit_depth: options.bit_depth,
+        compression: options.compression,
+        pixel_dims,
+        interlaced: options.interlaced,
+        background: options.png_background,
+        icc_profile: src.icc_profile.filter(|_| options.preserve_icc_profile),
+        text_chunks: if options.preserve_text_chunks {
+            src.text_chunks
+        } else {
+            Vec::new()
+        },
+        verify: options.verify_output,
+        quantize: options.quantize,
+        dither: options.dither,
+    };
+
+    Ok(Some((
+        dist_rgba,
+        output_width.get(),
+        output_height.get(),
+        write_options,
+    )))
+}
+
+/// 回転した後の幅と高さ
+fn rotated_size(width: u32, height: u32, rotation: Rotation) -> (u32, u32) {
+    match rotation {
+        Rotation::Cw90 | Rotation::Cw270 => (height, width),
+        Rotation::None | Rotation::Cw180 => (width, height),
+    }
+}
+
+/// 元の画像の幅と高さ。どちらかが0ならエラー
+fn input_size(width: u32, height: u32) -> Result<(NonZeroU32, NonZeroU32), ResizedPngError> {
+    NonZeroU32::new(width)
+        .zip(NonZeroU32::new(height))
+        .ok_or(ResizedPngError::InputSizeError { width, height })
+}
+
+/// 出力する大きさと、拡大縮小する大きさ
+type PlannedSize = ((NonZeroU32, NonZeroU32), (NonZeroU32, NonZeroU32));
+
+/// 出力する大きさと、拡大縮小する大きさ。サイズが計算できないときはNone
+///
+/// 縦横比を保って合わせる場合は、拡大縮小する大きさが出力する大きさと異なる。
+fn plan_size(
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    input_width: NonZeroU32,
+    input_height: NonZeroU32,
+    options: &ResizeOptions,
+) -> Result<Option<PlannedSize>, ResizedPngError> {
+    let (output_width, output_height) = match output_size(
+        width_command,
+        height_command,
+        input_width,
+        input_height,
+        options.no_upscale,
+    )? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let (output_width, output_height) = match options.max_megapixels {
+        Some(v) => fit_within_megapixels(output_width, output_height, v)?,
+        None => (output_width, output_height),
+    };
+
+    let output = (output_width, output_height);
+    let planned = match options.fit {
+        FitMode::Stretch => (output, output),
+        FitMode::Cover => (
+            output,
+            cover_size(output_width, output_height, input_width, input_height),
+        ),
+        FitMode::Contain => (
+            output,
+            contain_size(output_width, output_height, input_width, input_height),
+        ),
+        // 余白をつけないので、収まる大きさがそのまま出力の大きさになる。
+        FitMode::Inside => {
+            let size = contain_size(output_width, output_height, input_width, input_height);
+            (size, size)
+        }
+    };
+
+    Ok(Some(planned))
+}
+
+/// 画素数がmax_megapixelsを越える場合は、縦横比を保ったまま収まる大きさに縮める
+///
+/// 越えない場合はそのまま返す。上限が正の数でない場合はエラー
+fn fit_within_megapixels(
+    width: NonZeroU32,
+    height: NonZeroU32,
+    max_megapixels: f64,
+) -> Result<(NonZeroU32, NonZeroU32), ResizedPngError> {
+    if max_megapixels.is_nan() || max_megapixels <= 0.0 {
+        return Err(ResizedPngError::ParameterError(None));
+    }
+
+    let max_pixels = max_megapixels * 1_000_000.0;
+    let pixels = width.get() as f64 * height.get() as f64;
+    if pixels <= max_pixels {
+        return Ok((width, height));
+    }
+
+    // 切り捨てるので、縮めた後の画素数は上限を越えない。
+    let scale = (max_pixels / pixels).sqrt();
+    let scaled = |v: NonZeroU32| {
+        NonZeroU32::new((v.get() as f64 * scale) as u32).unwrap_or(NonZeroU32::new(1).unwrap())
+    };
+
+    Ok((scaled(width), scaled(height)))
+}
+
+/// 出力用のRGBAのバッファの大きさ。上限を越えるか、計算があふれる場合はエラー
+fn output_buffer_size(
+    width: NonZeroU32,
+    height: NonZeroU32,
+    max_pixels: u64,
+) -> Result<usize, ResizedPngError> {
+    // 確保する前に、どこで桁あふれしてもLimitsErrorにする。
+    (width.get() as u64)
+        .checked_mul(height.get() as u64)
+        .filter(|pixels| *pixels <= max_pixels)
+        .and_then(|pixels| usize::try_from(pixels).ok())
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or(ResizedPngError::LimitsError(None))
+}
+
+/// 0.0から1.0のsRGBの値を、リニアな値にする
+fn srgb_to_linear_value(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// 0.0から1.0のリニアな値を、sRGBの値にする
+fn linear_to_srgb_value(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// sRGBの8bitの値から、リニアな16bitの値への変換表
+fn srgb_to_linear_table() -> [u16; 256] {
+    let mut table = [0; 256];
+
+    for (i, v) in table.iter_mut().enumerate() {
+        let linear = srgb_to_linear_value(i as f64 / 255.0);
+
+        *v = (linear * u16::MAX as f64).round() as u16;
+    }
+
+    table
+}
+
+/// 16bitのRGBAのRGBを、0.0から1.0の値として変換する。アルファはそのまま
+fn map_rgb16(mut rgba: Vec<u16>, f: fn(f64) -> f64) -> Vec<u16> {
+    let max = u16::MAX as f64;
+
+    for pixel in rgba.chunks_exact_mut(4) {
+        for v in &mut pixel[..3] {
+            *v = (f(*v as f64 / max) * max).round().clamp(0.0, max) as u16;
+        }
+    }
+
+    rgba
+}
+
+/// RGBAのRGBをリニアな16bitの値にする。アルファは16bitに広げるだけ
+fn srgb_to_linear(rgba: &[u8], table: &[u16; 256]) -> Vec<u16> {
+    rgba.chunks_exact(4)
+        .flat_map(|p| {
+            [
+                table[p[0] as usize],
+                table[p[1] as usize],
+                table[p[2] as usize],
+                p[3] as u16 * 257,
+            ]
+        })
+        .collect()
+}
+
+/// リニアな16bitのRGBAを、sRGBの8bitのRGBAに戻す
+fn linear_to_srgb(rgba: &[u16], table: &[u16; 256]) -> Vec<u8> {
+    // 変換表の中で最も近い値を探す。
+    let to_srgb = |v: u16| match table.binary_search(&v) {
+        Ok(i) => i as u8,
+        Err(0) => 0,
+        Err(256) => u8::MAX,
+        Err(i) if v - table[i - 1] <= table[i] - v => (i - 1) as u8,
+        Err(i) => i as u8,
+    };
+
+    rgba.chunks_exact(4)
+        .flat_map(|p| {
+            [
+                to_srgb(p[0]),
+                to_srgb(p[1]),
+                to_srgb(p[2]),
+                image::u16_to_u8(p[3]),
+            ]
+        })
+        .collect()
+}
+
+/// 拡大縮小の比率に合わせて、単位あたりのピクセル数を変える
+///
+/// 大きさは回転した後のもので渡す。
+fn scale_pixel_dims(
+    pixel_dims: PixelDimensions,
+    rotation: Rotation,
+    (input_width, input_height): (NonZeroU32, NonZeroU32),
+    (resize_width, resize_height): (NonZeroU32, NonZeroU32),
+) -> PixelDimensions {
+    let (xppu, yppu) = match rotation {
+        Rotation::Cw90 | Rotation::Cw270 => (pixel_dims.yppu, pixel_dims.xppu),
+        Rotation::None | Rotation::Cw180 => (pixel_dims.xppu, pixel_dims.yppu),
+    };
+    let scale = |ppu: u32, output: NonZeroU32, input: NonZeroU32| {
+        (ppu as f64 * output.get() as f64 / input.get() as f64)
+            .round()
+            .min(u32::MAX as f64) as u32
+    };
+
+    PixelDimensions {
+        xppu: scale(xppu, resize_width, input_width),
+        yppu: scale(yppu, resize_height, input_height),
+        unit: pixel_dims.unit,
+    }
+}
+
+/// RGBAのバッファを時計回りに回転し、回転後のバッファと幅と高さを返す
+fn rotate_rgba<T: Copy>(
+    rgba: Vec<T>,
+    width: u32,
+    height: u32,
+    rotation: Rotation,
+) -> (Vec<T>, u32, u32) {
+    let (new_width, new_height) = match rotation {
+        Rotation::None => return (rgba, width, height),
+        Rotation::Cw180 => (width, height),
+        Rotation::Cw90 | Rotation::Cw270 => (height, width),
+    };
+    let (w, h) = (width as usize, height as usize);
+
+    let mut buf = Vec::with_capacity(rgba.len());
+    for y in 0..new_height as usize {
+        for x in 0..new_width as usize {
+            // 回転後の(x, y)に対応する、元の画像の(x, y)
+            let (src_x, src_y) = match rotation {
+                Rotation::Cw90 => (y, h - 1 - x),
+                Rotation::Cw180 => (w - 1 - x, h - 1 - y),
+                _ => (w - 1 - y, x),
+            };
+            let index = (src_y * w + src_x) * 4;
+            buf.extend_from_slice(&rgba[index..index + 4]);
+        }
+    }
+
+    (buf, new_width, new_height)
+}
+
+/// RGBAのバッファを左右、上下に反転する。大きさは変わらない
+fn flip_rgba<T>(
+    mut rgba: Vec<T>,
+    width: u32,
+    height: u32,
+    horizontal: bool,
+    vertical: bool,
+) -> Vec<T> {
+    let stride = width as usize * 4;
+
+    if horizontal {
+        for row in rgba.chunks_exact_mut(stride) {
+            row.reverse();
+            // ピクセル単位で反転したので、チャンネルの順を戻す。
+            for pixel in row.chunks_exact_mut(4) {
+                pixel.reverse();
+            }
+        }
+    }
+
+    if vertical {
+        let height = height as usize;
+        for y in 0..height / 2 {
+            let (upper, lower) = rgba.split_at_mut((height - 1 - y) * stride);
+            upper[y * stride..(y + 1) * stride].swap_with_slice(&mut lower[..stride]);
+        }
+    }
+
+    rgba
+}
+
+/// 縦横比を保ったまま、出力の大きさを覆う最小の大きさ
+fn cover_size(
+    output_width: NonZeroU32,
+    output_height: NonZeroU32,
+    input_width: NonZeroU32,
+    input_height: NonZeroU32,
+) -> (NonZeroU32, NonZeroU32) {
+    let ratio = f64::max(
+        output_width.get() as f64 / input_width.get() as f64,
+        output_height.get() as f64 / input_height.get() as f64,
+    );
+
+    // 丸めで出力より小さくならないようにする。
+    let width = ((input_width.get() as f64 * ratio).round() as u32).max(output_width.get());
+    let height = ((input_height.get() as f64 * ratio).round() as u32).max(output_height.get());
+
+    (
+        NonZeroU32::new(width).unwrap_or(output_width),
+        NonZeroU32::new(height).unwrap_or(output_height),
+    )
+}
+
+/// 縦横比を保ったまま、出力の大きさに収まる最大の大きさ
+fn contain_size(
+    output_width: NonZeroU32,
+    output_height: NonZeroU32,
+    input_width: NonZeroU32,
+    input_height: NonZeroU32,
+) -> (NonZeroU32, NonZeroU32) {
+    let ratio = f64::min(
+        output_width.get() as f64 / input_width.get() as f64,
+        output_height.get() as f64 / input_height.get() as f64,
+    );
+
+    // 丸めで出力より大きくならないようにし、0の場合は1にする。
+    let width = ((input_width.get() as f64 * ratio).round() as u32).min(output_width.get());
+    let height = ((input_height.get() as f64 * ratio).round() as u32).min(output_height.get());
+
+    (
+        NonZeroU32::new(width).unwrap_or(NonZeroU32::new(1).unwrap()),
+        NonZeroU32::new(height).unwrap_or(NonZeroU32::new(1).unwrap()),
+    )
+}
+
+/// RGBAのバッファを、背景色で埋めたcanvasの中央に置く
+fn pad_rgba<T: Copy>(
+    rgba: &[T],
+    width: u32,
+    height: u32,
+    canvas_width: u32,
+    canvas_height: u32,
+    background: [T; 4],
+) -> Vec<T> {
+    let mut canvas = background.repeat(canvas_width as usize * canvas_height as usize);
+
+    let left = (canvas_width - width) as usize / 2;
+    let top = (canvas_height - height) as usize / 2;
+    let stride = width as usize * 4;
+    let canvas_stride = canvas_width as usize * 4;
+
+    for (y, row) in rgba.chunks_exact(stride).enumerate() {
+        let start = (top + y) * canvas_stride + left * 4;
+        canvas[start..start + stride].copy_from_slice(row);
+    }
+
+    canvas
+}
+
+/// RGBAのバッファの縁(上下左右の端の1列)のピクセルの平均の色
+fn edge_color<T: Channel>(rgba: &[T], width: u32, height: u32) -> [T; 4] {
+    let (width, height) = (width as usize, height as usize);
+    let edge = rgba.chunks_exact(4).enumerate().filter(|(i, _)| {
+        let (x, y) = (i % width, i / width);
+        x == 0 || y == 0 || x + 1 == width || y + 1 == height
+    });
+
+    average_color(edge.map(|(_, v)| v))
+}
+
+/// RGBAのバッファで最も多い色
+///
+/// 近い色をまとめて数えるため、各チャンネルを16段階に分けたヒストグラムで最も多い段階を選び、その中の平均の色を返す。
+fn dominant_color<T: Channel>(rgba: &[T]) -> [T; 4] {
+    let level = |v: T| (v.to_u64() * 16 / (T::MAX.to_u64() + 1)) as u8;
+
+    // 段階ごとに、ピクセルの数と色の合計を持つ。
+    let mut histogram: HashMap<[u8; 4], (u64, [u64; 4])> = HashMap::new();
+    for v in rgba.chunks_exact(4) {
+        let key = [level(v[0]), level(v[1]), level(v[2]), level(v[3])];
+        let (count, sum) = histogram.entry(key).or_default();
+        *count += 1;
+        for (s, c) in sum.iter_mut().zip(v) {
+            *s += c.to_u64();
+        }
+    }
+
+    // 同じ数の段階があっても結果が変わらないよう、段階の値でも比べる。
+    match histogram
+        .into_iter()
+        .max_by_key(|(key, (count, _))| (*count, *key))
+    {
+        Some((_, (count, sum))) => sum.map(|v| T::from_u64((v + count / 2) / count)),
+        None => [T::from_u8(0); 4],
+    }
+}
+
+/// ピクセルの平均の色。ピクセルがなければ透明
+fn average_color<'a, T: Channel + 'a>(pixels: impl Iterator<Item = &'a [T]>) -> [T; 4] {
+    let mut sum = [0u64; 4];
+    let mut count = 0u64;
+    for v in pixels {
+        for (s, c) in sum.iter_mut().zip(v) {
+            *s += c.to_u64();
+        }
+        count += 1;
+    }
+
+    if count == 0 {
+        return [T::from_u8(0); 4];
+    }
+
+    sum.map(|v| T::from_u64((v + count / 2) / count))
+}
+
+/// RGBAのバッファを背景色の上に合成し、アルファが全て255のRGBAにする
+///
+/// 新しく確保せず、渡されたバッファを書き換えて返す。
+fn flatten_rgba<T: Channel>(
+    mut rgba: Vec<T>,
+    width: u32,
+    height: u32,
+    background: [T; 3],
+) -> Vec<T> {
+    let max = T::MAX.to_u64();
+    let blend = |color: T, background: T, alpha: T| {
+        let alpha = alpha.to_u64();
+        T::from_u64((color.to_u64() * alpha + background.to_u64() * (max - alpha) + max / 2) / max)
+    };
+
+    rgba.truncate(width as usize * height as usize * 4);
+    for v in rgba.chunks_exact_mut(4) {
+        v[0] = blend(v[0], background[0], v[3]);
+        v[1] = blend(v[1], background[1], v[3]);
+        v[2] = blend(v[2], background[2], v[3]);
+        v[3] = T::MAX;
+    }
+
+    rgba
+}
+
+/// RGBAのRGBを輝度(BT.601)にする。アルファはそのまま
+fn to_luma_rgba<T: Channel>(mut rgba: Vec<T>) -> Vec<T> {
+    for v in rgba.chunks_exact_mut(4) {
+        let luma = (v[0].to_u64() * 299 + v[1].to_u64() * 587 + v[2].to_u64() * 114 + 500) / 1000;
+        v[..3].fill(T::from_u64(luma));
+    }
+
+    rgba
+}
+
+/// RGBAのRGBを輝度(Rec.709)にして色を抜く。アルファはそのまま
+fn desaturate_rgba<T: Channel>(mut rgba: Vec<T>) -> Vec<T> {
+    for v in rgba.chunks_exact_mut(4) {
+        let luma =
+            (v[0].to_u64() * 2126 + v[1].to_u64() * 7152 + v[2].to_u64() * 722 + 5000) / 10000;
+        v[..3].fill(T::from_u64(luma));
+    }
+
+    rgba
+}
+
+/// RGBAのRGBにアンシャープマスクを掛ける。アルファはそのまま
+///
+/// radiusを標準偏差としてぼかし、`元 + amount * (元 - ぼかした値)`を範囲に収める。
+/// radiusが正の数でない場合は何もしない。
+fn unsharp_mask<T: Channel>(
+    mut rgba: Vec<T>,
+    width: u32,
+    height: u32,
+    amount: f32,
+    radius: f32,
+) -> Vec<T> {
+    let w = width as usize;
+    let h = height as usize;
+    if radius.is_nan() || radius <= 0.0 || w == 0 || h == 0 {
+        return rgba;
+    }
+
+    let kernel = gaussian_kernel(radius);
+    let reach = kernel.len() / 2;
+    let src: Vec<[f32; 3]> = rgba
+        .chunks_exact(4)
+        .take(w * h)
+        .map(|v| [v[0], v[1], v[2]].map(|c| c.to_u64() as f32))
+        .collect();
+
+    // 横と縦に分けてぼかす。画像の外は端のピクセルが続いているものとする。
+    let blur = |src: &[[f32; 3]], horizontal: bool| {
+        let mut dist = vec![[0.0; 3]; src.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let sum = &mut dist[y * w + x];
+                for (i, weight) in kernel.iter().enumerate() {
+                    let (sx, sy) = if horizontal {
+                        ((x + i).saturating_sub(reach).min(w - 1), y)
+                    } else {
+                        (x, (y + i).saturating_sub(reach).min(h - 1))
+                    };
+                    for (sum, c) in sum.iter_mut().zip(src[sy * w + sx]) {
+                        *sum += c * weight;
+                    }
+                }
+            }
+        }
+        dist
+    };
+    let blurred = blur(&blur(&src, true), false);
+
+    let max = T::MAX.to_u64() as f32;
+    for ((pixel, original), blurred) in rgba.chunks_exact_mut(4).zip(&src).zip(&blurred) {
+        for ((v, original), blurred) in pixel[..3].iter_mut().zip(original).zip(blurred) {
+            let sharpened = original + amount * (original - blurred);
+            *v = T::from_u64(sharpened.round().clamp(0.0, max) as u64);
+        }
+    }
+
+    rgba
+}
+
+/// 標準偏差がsigmaのガウス関数の重み。3sigmaまでで打ち切り、合計が1になるようにする
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let reach = (sigma * 3.0).ceil().max(1.0) as usize;
+    let weights: Vec<f32> = (0..=reach * 2)
+        .map(|i| {
+            let d = i as f32 - reach as f32;
+            (-(d * d) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let total: f32 = weights.iter().sum();
+
+    weights.iter().map(|v| v / total).collect()
+}
+
+/// RGBAのバッファから(x, y)を左上とする範囲を切り出す
+///
+/// 新しく確保せず、渡されたバッファの中で行を前に詰めて返す。
+fn crop_rgba<T: Copy>(
+    mut rgba: Vec<T>,
+    width: u32,
+    x: u32,
+    y: u32,
+    crop_width: u32,
+    crop_height: u32,
+) -> Vec<T> {
+    let stride = width as usize * 4;
+    let crop_stride = crop_width as usize * 4;
+
+    // 書き込み先は常に読み込み元より前なので、上の行から順に詰めてよい。
+    for row in 0..crop_height as usize {
+        let start = (y as usize + row) * stride + x as usize * 4;
+        rgba.copy_within(start..start + crop_stride, row * crop_stride);
+    }
+    rgba.truncate(crop_stride * crop_height as usize);
+
+    rgba
+}
+
+/// 完全に透明でないピクセルを囲む範囲(x, y, 幅, 高さ)。全て透明ならNone
+fn opaque_bounds<T: Channel>(rgba: &[T], width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    let transparent = T::from_u8(0);
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+
+    for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+        if pixel[3] == transparent {
+            continue;
+        }
+        let (x, y) = ((i % width as usize) as u32, (i / width as usize) as u32);
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x + 1);
+        bottom = bottom.max(y + 1);
+    }
+
+    (left < right).then(|| (left, top, right - left, bottom - top))
+}
+
+/// 全てのピクセルが不透明な灰色かどうか
+fn is_grayscale<T: Channel>(rgba: &[T]) -> bool {
+    rgba.chunks_exact(4)
+        .all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == T::MAX)
+}
+
+fn output_size(
+    width_command: SizeCommand,
+    height_command: SizeCommand,
+    input_width: NonZeroU32,
+    input_height: NonZeroU32,
+    no_upscale: bool,
+) -> Result<Option<(NonZeroU32, NonZeroU32)>, ResizedPngError> {
+    // 百分率は画素数に直す。
+    let width_command = width_command.to_pixel(input_width)?;
+    let height_command = height_command.to_pixel(input_height)?;
+
+    // command が0の場合は元のサイズが指定されているとして扱う。
+    // 負の数は-1だけを受け付け、それ以外は指定の誤りとする。
+    let width_origin = match width_command {
+        0 => input_width.get() as i64,
+        w if w < -1 => return Err(ResizedPngError::ParameterError(None)),
+        w => w,
+    };
+    let height_origin = match height_command {
+        0 => input_height.get() as i64,
+        h if h < -1 => return Err(ResizedPngError::ParameterError(None)),
+        h => h,
+    };
+
+    // 両方とも-1ならサイズなし。
+    if width_origin < 0 && height_origin < 0 {
+        return Ok(None);
+    }
+
+    // originが0未満の場合はもう片方の拡大率に従う。
+    let width_temp = match width_origin {
+        w if w < 0 => {
+            let ratio = height_origin as f64 / input_height.get() as f64;
+
+            (input_width.get() as f64 * ratio) as u32
+        }
+        // u32に収まらない値は上限にする。
+        w => w.min(u32::MAX as i64) as u32,
+    };
+    let height_temp = match height_origin {
+        h if h < 0 => {
+            let ratio = width_origin as f64 / input_width.get() as f64;
+
+            (input_height.get() as f64 * ratio) as u32
+        }
+        h => h.min(u32::MAX as i64) as u32,
+    };
+
+    // 拡大しない場合は、元のサイズを超えないようにする。
+    let (width_temp, height_temp) = if !no_upscale {
+        (width_temp, height_temp)
+    } else if width_origin < 0 || height_origin < 0 {
+        // 比率を保っているので、はみ出すなら元のサイズそのものになる。
+        if width_temp > input_width.get() || height_temp > input_height.get() {
+            (input_width.get(), input_height.get())
+        } else {
+            (width_temp, height_temp)
+        }
+    } else {
+        (
+            width_temp.min(input_width.get()),
+            height_temp.min(input_height.get()),
+        )
+    };
+
+    // tempが0の場合は1にfallbackして返す。
+    let width = NonZeroU32::new(width_temp).unwrap_or(NonZeroU32::new(1).unwrap());
+    let height = NonZeroU32::new(height_temp).unwrap_or(NonZeroU32::new(1).unwrap());
+
+    Ok(Some((width, height)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod get_image_type {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_image_file_exists() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert_eq!(get_image_type(&path), "PNG");
+        }
+
+        #[test]
+        fn checking_value_when_tiff_file_exists() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.tif");
+
+            assert_eq!(get_image_type(&path), "TIFF");
+        }
+
+        #[test]
+        fn checking_value_when_non_image_file_exists() {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+
+            assert_eq!(get_image_type(&path), "UNKNOWN");
+        }
+
+        #[test]
+        fn checking_value_when_file_does_not_exist() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/something_wrong.png");
+            assert_eq!(get_image_type(&path), "UNKNOWN");
+        }
+    }
+
+    mod get_image_format {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_image_file_exists() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+
+            assert_eq!(get_image_format(&path), Some(ImageFormat::Jpeg));
+        }
+
+        #[test]
+        fn none_when_non_image_file_exists() {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+
+            assert_eq!(get_image_format(&path), None);
+        }
+    }
+
+    mod detect_format_from_bytes {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_known_signature() {
+            let cases: [(&[u8], &str); 5] = [
+                (b"\x89PNG\r\n\x1a\n\0\0\0\x0d", "PNG"),
+                (b"BM\0\0\0\0", "BMP"),
+                (b"GIF89a\x01\0", "GIF"),
+                (b"\xff\xd8\xff\xe0", "JPEG"),
+                (b"RIFF\0\0\0\0WEBPVP8L", "WEBP"),
+            ];
+
+            for (bytes, name) in cases {
+                assert_eq!(detect_format_from_bytes(bytes), name);
+            }
+        }
+
+        #[test]
+        fn checking_value_when_sample_files() {
+            for (file, name) in [
+                ("sample.png", "PNG"),
+                ("sample.bmp", "BMP"),
+                ("sample.gif", "GIF"),
+                ("sample.jpg", "JPEG"),
+                ("sample.webp", "WEBP"),
+                ("sample.tif", "TIFF"),
+            ] {
+                let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("test_target/image")
+                    .join(file);
+                let bytes = std::fs::read(&path).unwrap();
+
+                assert_eq!(detect_format_from_bytes(&bytes), name);
+            }
+        }
+
+        #[test]
+        fn checking_value_when_unknown_bytes() {
+            assert_eq!(
+                detect_format_from_bytes(&[0x3A, 0x91, 0x07, 0xC4, 0x5E, 0x00, 0xEE, 0x12]),
+                "UNKNOWN"
+            );
+            assert_eq!(detect_format_from_bytes(&[]), "UNKNOWN");
+        }
+    }
+
+    mod get_image_info {
+        use super::*;
+
+        #[test]
+        fn get_image_info_when_image_file_exists() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let info = get_image_info(&path).unwrap();
+            assert_eq!(info.width, 100);
+            assert_eq!(info.height, 200);
+            assert_eq!(info.frame_count, 1);
+            assert!(!info.is_animated());
+        }
+
+        #[test]
+        fn not_animated_when_static_gif() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.gif");
+            let info = get_image_info(&path).unwrap();
+            assert_eq!(info.frame_count, 1);
+            assert!(!info.is_animated());
+        }
+
+        #[test]
+        fn animated_when_animated_gif() {
+            let out_dir = tempfile::tempdir().unwrap();
+            let path = out_dir.path().join("animated.gif");
+
+            {
+                let fs = std::fs::File::create(&path).unwrap();
+                let palette = [0, 0, 0, 255, 255, 255];
+                let mut encoder = gif::Encoder::new(fs, 100, 200, &palette).unwrap();
+                for index in 0..2 {
+                    let frame = gif::Frame {
+                        width: 100,
+                        height: 200,
+                        buffer: std::borrow::Cow::Owned(vec![index; 100 * 200]),
+                        ..Default::default()
+                    };
+                    encoder.write_frame(&frame).unwrap();
+                }
+            }
+
+            let info = get_image_info(&path).unwrap();
+            assert_eq!((info.width, info.height), (100, 200));
+            assert_eq!(info.frame_count, 2);
+            assert!(info.is_animated());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn get_image_info_when_non_image_file_exists() {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+            assert!(get_image_info(&path).is_err());
+        }
+
+        #[test]
+        fn get_image_info_when_file_does_not_exist() {
+            let path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/something_wrong.png");
+            assert!(get_image_info(&path).is_err());
+        }
+    }
+
+    mod would_upscale {
+        use super::*;
+
+        fn sample_path() -> PathBuf {
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png")
+        }
+
+        #[test]
+        fn true_when_upscaling() {
+            // sample.pngは100x200。
+            let upscale = would_upscale(
+                &sample_path(),
+                SizeCommand::Pixel(200),
+                SizeCommand::Pixel(-1),
+            )
+            .unwrap();
+            assert!(upscale);
+        }
+
+        #[test]
+        fn true_when_only_one_side_is_upscaled() {
+            let upscale = would_upscale(
+                &sample_path(),
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(300),
+            )
+            .unwrap();
+            assert!(upscale);
+        }
+
+        #[test]
+        fn false_when_downscaling() {
+            let upscale = would_upscale(
+                &sample_path(),
+                SizeCommand::Percent(50),
+                SizeCommand::Percent(50),
+            )
+            .unwrap();
+            assert!(!upscale);
+        }
+
+        #[test]
+        fn false_when_original_size() {
+            let upscale =
+                would_upscale(&sample_path(), SizeCommand::Pixel(0), SizeCommand::Pixel(0))
+                    .unwrap();
+            assert!(!upscale);
+        }
+
+        #[test]
+        fn false_when_size_cannot_be_calculated() {
+            let upscale = would_upscale(
+                &sample_path(),
+                SizeCommand::Pixel(-1),
+                SizeCommand::Pixel(-1),
+            )
+            .unwrap();
+            assert!(!upscale);
+        }
+    }
+
+    mod planned_output_size {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn same_as_output_when_each_format() {
+            let out_dir = tempdir().unwrap();
+            let dist_path = out_dir.path().join("dist.png");
+
+            for name in [
+                "sample.png",
+                "sample.bmp",
+                "sample.gif",
+                "sample.jpg",
+                "sample.webp",
+                "sample.tif",
+            ] {
+                let src_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("test_target/image")
+                    .join(name);
+
+                for (width_command, height_command) in [
+                    (SizeCommand::Pixel(30), SizeCommand::Pixel(-1)),
+                    (SizeCommand::Pixel(-1), SizeCommand::Percent(25)),
+                    (SizeCommand::Pixel(0), SizeCommand::Pixel(7)),
+                ] {
+                    let options = ResizeOptions::default();
+                    let planned =
+                        planned_output_size(&src_path, width_command, height_command, &options)
+                            .unwrap();
+
+                    to_resized_png(
+                        &src_path,
+                        &dist_path,
+                        width_command,
+                        height_command,
+                        &options,
+                    )
+                    .unwrap();
+                    let image = image::png::read_image_data(&dist_path).unwrap();
+
+                    assert_eq!(planned, Some((image.width, image.height)), "{name}");
+                }
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn reduced_when_over_max_megapixels() {
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let options = ResizeOptions {
+                max_megapixels: Some(0.5),
+                ..Default::default()
+            };
+
+            // 1000x2000の指定が、縦横比を保って0.5メガピクセルに収まる。
+            assert_eq!(
+                planned_output_size(
+                    &src_path,
+                    SizeCommand::Pixel(1000),
+                    SizeCommand::Pixel(-1),
+                    &options
+                )
+                .unwrap(),
+                Some((500, 1000))
+            );
+            assert_eq!(
+                planned_output_size(
+                    &src_path,
+                    SizeCommand::Pixel(50),
+                    SizeCommand::Pixel(-1),
+                    &options
+                )
+                .unwrap(),
+                Some((50, 100))
+            );
+        }
+
+        #[test]
+        fn swapped_when_rotated_by_90() {
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let options = ResizeOptions {
+                rotation: Rotation::Cw90,
+                ..Default::default()
+            };
+
+            assert_eq!(
+                planned_output_size(
+                    &src_path,
+                    SizeCommand::Pixel(0),
+                    SizeCommand::Pixel(0),
+                    &options
+                )
+                .unwrap(),
+                Some((200, 100))
+            );
+        }
+
+        #[test]
+        fn none_when_both_commands_are_minus() {
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            assert_eq!(
+                planned_output_size(
+                    &src_path,
+                    SizeCommand::Pixel(-1),
+                    SizeCommand::Pixel(-1),
+                    &ResizeOptions::default()
+                )
+                .unwrap(),
+                None
+            );
+        }
+
+        #[test]
+        fn failed_when_non_image_file() {
+            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+
+            assert!(planned_output_size(
+                &path,
+                SizeCommand::Pixel(10),
+                SizeCommand::Pixel(10),
+                &ResizeOptions::default()
+            )
+            .is_err());
+        }
+    }
+
+    mod to_resized_png {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn decoding_error_when_input_is_empty() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("empty.png");
+            let dist_path = out_dir.path().join("dist.png");
+            std::fs::File::create(&src_path).unwrap();
+
+            let result = to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            );
+
+            assert!(matches!(
+                result,
+                Err(ResizedPngError::DecodingError(Some(_)))
+            ));
+            assert!(!dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn success_when_input_image_is_png() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("from_png.png");
+            let width_command = SizeCommand::Pixel(50);
+            let height_command = SizeCommand::Pixel(100);
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                width_command,
+                height_command,
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            assert!(dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn success_when_input_image_is_large() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("large.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let (width, height) = (4000, 4000);
+            let buf: Vec<u8> = (0..width * height)
+                .flat_map(|i| [(i % width) as u8, (i / width) as u8, 128, 255])
+                .collect();
+            image::png::write_png(&src_path, &buf, width, height, &WriteOptions::default())
+                .unwrap();
+            drop(buf);
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(100),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            assert_eq!(image::png::read_dimensions(&dist_path).unwrap(), (100, 100));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_16bit_grayscale_is_preserved() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("gray16.png");
+            let dist_path = out_dir.path().join("dist.png");
+            // 下位のバイトまで異なる値にして、8bitに丸められていないことを確かめる。
+            let samples: Vec<u16> = (0..16).map(|i| i * 4099 + 1).collect();
+            let buf: Vec<u16> = samples
+                .iter()
+                .flat_map(|v| [*v, *v, *v, u16::MAX])
+                .collect();
+            let write_options = WriteOptions {
+                color_type: ColorType::Grayscale,
+                ..Default::default()
+            };
+            image::png::write_png16(&src_path, &buf, 4, 4, &write_options).unwrap();
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                preserve_grayscale: true,
+                preserve_16bit: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(4),
+                SizeCommand::Pixel(4),
+                &options,
+            )
+            .unwrap();
+
+            let bytes = std::fs::read(&dist_path).unwrap();
+            let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+            assert_eq!(reader.info().bit_depth, BitDepth::Sixteen);
+            assert_eq!(reader.info().color_type, ColorType::Grayscale);
+            let mut decoded = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut decoded).unwrap();
+            let decoded: Vec<u16> = decoded
+                .chunks_exact(2)
+                .map(|v| u16::from_be_bytes([v[0], v[1]]))
+                .collect();
+
+            assert_eq!(decoded, samples);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_16bit_is_not_preserved() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("gray16.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let buf = [0x1234, 0x1234, 0x1234, u16::MAX];
+            image::png::write_png16(&src_path, &buf, 1, 1, &WriteOptions::default()).unwrap();
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(1),
+                SizeCommand::Pixel(1),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            let bytes = std::fs::read(&dist_path).unwrap();
+            let reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+            assert_eq!(reader.info().bit_depth, BitDepth::Eight);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_output_color_is_forced() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+
+            for (output_color, color_type) in [
+                (OutputColor::Grayscale, ColorType::Grayscale),
+                (OutputColor::GrayscaleAlpha, ColorType::GrayscaleAlpha),
+                (OutputColor::Rgb, ColorType::Rgb),
+                (OutputColor::Rgba, ColorType::Rgba),
+            ] {
+                let dist_path = out_dir.path().join(format!("{:?}.png", output_color));
+                let options = ResizeOptions {
+                    output_color,
+                    ..Default::default()
+                };
+                to_resized_png(
+                    &src_path,
+                    &dist_path,
+                    SizeCommand::Pixel(50),
+                    SizeCommand::Pixel(100),
+                    &options,
+                )
+                .unwrap();
+
+                let bytes = std::fs::read(&dist_path).unwrap();
+                let reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+                assert_eq!(reader.info().color_type, color_type, "{:?}", output_color);
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_icc_profile_is_preserved() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("icc.png");
+            let icc_profile = b"dummy icc profile".to_vec();
+            let write_options = WriteOptions {
+                icc_profile: Some(icc_profile.clone()),
+                ..Default::default()
+            };
+            image::png::write_png(
+                &src_path,
+                &[10, 20, 30, 255].repeat(4),
+                2,
+                2,
+                &write_options,
+            )
+            .unwrap();
+
+            for (preserve_icc_profile, expected) in [(true, Some(icc_profile)), (false, None)] {
+                let dist_path = out_dir.path().join(format!("{}.png", preserve_icc_profile));
+                let options = ResizeOptions {
+                    preserve_icc_profile,
+                    ..Default::default()
+                };
+                to_resized_png(
+                    &src_path,
+                    &dist_path,
+                    SizeCommand::Pixel(1),
+                    SizeCommand::Pixel(1),
+                    &options,
+                )
+                .unwrap();
+
+                let bytes = std::fs::read(&dist_path).unwrap();
+                let reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+                assert_eq!(
+                    reader.info().icc_profile.as_deref(),
+                    expected.as_deref(),
+                    "{}",
+                    preserve_icc_profile
+                );
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_text_chunks_are_preserved() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("text.png");
+            let write_options = WriteOptions {
+                text_chunks: vec![("Author".to_string(), "tukinami seika".to_string())],
+                ..Default::default()
+            };
+            image::png::write_png(
+                &src_path,
+                &[10, 20, 30, 255].repeat(4),
+                2,
+                2,
+                &write_options,
+            )
+            .unwrap();
+
+            for preserve_text_chunks in [true, false] {
+                let dist_path = out_dir.path().join(format!("{}.png", preserve_text_chunks));
+                let options = ResizeOptions {
+                    preserve_text_chunks,
+                    ..Default::default()
+                };
+                to_resized_png(
+                    &src_path,
+                    &dist_path,
+                    SizeCommand::Pixel(1),
+                    SizeCommand::Pixel(1),
+                    &options,
+                )
+                .unwrap();
+
+                let bytes = std::fs::read(&dist_path).unwrap();
+                let reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+                let texts: Vec<(&str, &str)> = reader
+                    .info()
+                    .uncompressed_latin1_text
+                    .iter()
+                    .map(|v| (v.keyword.as_str(), v.text.as_str()))
+                    .collect();
+                if preserve_text_chunks {
+                    assert_eq!(texts, [("Author", "tukinami seika")]);
+                } else {
+                    assert!(texts.is_empty());
+                }
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_rgb_is_forced_on_transparent_source() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("transparent.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let buf = [10, 20, 30, 0].repeat(4);
+            image::png::write_png(&src_path, &buf, 2, 2, &WriteOptions::default()).unwrap();
+
+            let options = ResizeOptions {
+                output_color: OutputColor::Rgb,
+                opaque_background: [200, 100, 50],
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(2),
+                SizeCommand::Pixel(2),
+                &options,
+            )
+            .unwrap();
+
+            let bytes = std::fs::read(&dist_path).unwrap();
+            let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+            let mut decoded = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut decoded).unwrap();
+
+            // アルファは落ち、透明な部分は背景色になる。
+            assert_eq!(reader.info().color_type, ColorType::Rgb);
+            assert_eq!(decoded, [200, 100, 50].repeat(4));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn success_when_input_image_is_webp() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.webp");
+            let dist_path = out_dir.path().join("from_webp.png");
+            let width_command = SizeCommand::Pixel(-1);
+            let height_command = SizeCommand::Pixel(50);
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                width_command,
+                height_command,
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            assert!(dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn success_when_input_image_is_bmp() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
+            let dist_path = out_dir.path().join("from_bmp.png");
+            let width_command = SizeCommand::Pixel(50);
+            let height_command = SizeCommand::Pixel(-1);
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                width_command,
+                height_command,
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            assert!(dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn success_when_input_image_is_jpg() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let dist_path = out_dir.path().join("from_jpg.png");
+            let width_command = SizeCommand::Pixel(0);
+            let height_command = SizeCommand::Pixel(0);
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                width_command,
+                height_command,
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            assert!(dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn no_dark_halo_when_edge_between_opaque_and_transparent() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            // 左半分が不透明な赤、右半分が透明な黒。
+            let row = [[[255, 0, 0, 255]; 4].concat(), [[0, 0, 0, 0]; 4].concat()].concat();
+            let buf = [row.clone(), row].concat();
+            image::png::write_png(&src_path, &buf, 8, 2, &WriteOptions::default()).unwrap();
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(4),
+                SizeCommand::Pixel(1),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            let data = image::png::read_image_data(&dist_path).unwrap().pixels;
+            for pixel in data.chunks_exact(4).filter(|p| p[3] > 0) {
+                assert_eq!(pixel[..3], [255, 0, 0]);
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_size_when_input_jpg_is_scaled_on_decode() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let dist_path = out_dir.path().join("dist.png");
+
+            // 元の大きさに対する百分率も、縮小して読み込む前の大きさで計算される。
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Percent(10),
+                SizeCommand::Pixel(-1),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.width, 10);
+            assert_eq!(image.height, 20);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn crisp_edges_when_filter_is_point() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let buf = [0, 0, 0, 255, 255, 255, 255, 255];
+            image::png::write_png(&src_path, &buf, 2, 1, &WriteOptions::default()).unwrap();
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(8),
+                SizeCommand::Pixel(1),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.width, 8);
+            assert_eq!(image.height, 1);
+            assert_eq!(
+                image.pixels,
+                [[[0, 0, 0, 255]; 4].concat(), [[255; 4]; 4].concat()].concat()
+            );
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn grayscale_output_when_preserve_grayscale_and_gray_source() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let buf = [
+                0, 0, 0, 255, 128, 128, 128, 255, 255, 255, 255, 255, 64, 64, 64, 255,
+            ];
+            image::png::write_png(&src_path, &buf, 2, 2, &WriteOptions::default()).unwrap();
+
+            let options = ResizeOptions {
+                preserve_grayscale: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(4),
+                SizeCommand::Pixel(4),
+                &options,
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(std::fs::File::open(&dist_path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Grayscale);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn grayscale_output_when_preserve_grayscale_and_grayscale_jpeg() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.jpg");
+            let dist_path = out_dir.path().join("dist.png");
+            let luma: Vec<u8> = (0..32 * 32).map(|v| (v % 256) as u8).collect();
+            let mut bytes = Vec::new();
+            jpeg_encoder::Encoder::new(&mut bytes, 90)
+                .encode(&luma, 32, 32, jpeg_encoder::ColorType::Luma)
+                .unwrap();
+            std::fs::write(&src_path, bytes).unwrap();
+
+            // 縮小して読み込む場合と、リニアに変換する場合も灰色のまま。
+            for linear_light in [false, true] {
+                let options = ResizeOptions {
+                    preserve_grayscale: true,
+                    linear_light,
+                    ..Default::default()
+                };
+                to_resized_png(
+                    &src_path,
+                    &dist_path,
+                    SizeCommand::Pixel(8),
+                    SizeCommand::Pixel(8),
+                    &options,
+                )
+                .unwrap();
+
+                let decoder = png::Decoder::new(std::fs::File::open(&dist_path).unwrap());
+                let reader = decoder.read_info().unwrap();
+                assert_eq!(reader.info().color_type, ColorType::Grayscale);
+                assert_eq!((reader.info().width, reader.info().height), (8, 8));
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn gray_rgba_output_when_desaturate() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let buf = [255, 0, 0, 255].repeat(4);
+            image::png::write_png(&src_path, &buf, 2, 2, &WriteOptions::default()).unwrap();
+
+            let options = ResizeOptions {
+                desaturate: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(2),
+                SizeCommand::Pixel(2),
+                &options,
+            )
+            .unwrap();
+
+            // 色形式はそのまま。
+            let decoder = png::Decoder::new(std::fs::File::open(&dist_path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Rgba);
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.pixels, [54, 54, 54, 255].repeat(4));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn indexed_output_when_quantize() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            let options = ResizeOptions {
+                quantize: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &options,
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(std::fs::File::open(&dist_path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Indexed);
+            assert_eq!((reader.info().width, reader.info().height), (50, 100));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn rgba_output_when_preserve_grayscale_and_color_source() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            let options = ResizeOptions {
+                preserve_grayscale: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &options,
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(std::fs::File::open(&dist_path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Rgba);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_output_is_over_limit() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            let result = to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(100000),
+                SizeCommand::Pixel(100000),
+                &ResizeOptions::default(),
+            );
+
+            assert!(matches!(result, Err(ResizedPngError::LimitsError(_))));
+            assert!(!dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_without_panic_when_output_size_is_u32_max() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            // 画素数の上限をなくしても、バッファの大きさの計算で止まる。
+            for fit in [FitMode::Stretch, FitMode::Cover, FitMode::Contain] {
+                for command in [u32::MAX as i64, i64::MAX] {
+                    let options = ResizeOptions {
+                        max_pixels: u64::MAX,
+                        fit,
+                        ..Default::default()
+                    };
+                    let result = to_resized_png(
+                        &src_path,
+                        &dist_path,
+                        SizeCommand::Pixel(command),
+                        SizeCommand::Pixel(command),
+                        &options,
+                    );
+
+                    assert!(
+                        matches!(result, Err(ResizedPngError::LimitsError(_))),
+                        "{fit:?} {command}"
+                    );
+                    assert!(!dist_path.exists());
+                }
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn round_trip_when_output_format_is_webp() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.webp");
+
+            let options = ResizeOptions {
+                output_format: OutputFormat::Webp,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &options,
+            )
+            .unwrap();
+
+            let (image, format) = image::read_any(&dist_path).unwrap();
+            assert_eq!(format, image::ImageFormat::Webp);
+            assert_eq!(image.width, 50);
+            assert_eq!(image.height, 100);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn round_trip_when_output_format_is_jpeg() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let jpeg_path = out_dir.path().join("dist.jpg");
+            let png_path = out_dir.path().join("dist.png");
+
+            for (dist_path, output_format) in [
+                (&jpeg_path, OutputFormat::Jpeg),
+                (&png_path, OutputFormat::Png),
+            ] {
+                let options = ResizeOptions {
+                    output_format,
+                    ..Default::default()
+                };
+                to_resized_png(
+                    &src_path,
+                    dist_path,
+                    SizeCommand::Pixel(50),
+                    SizeCommand::Pixel(100),
+                    &options,
+                )
+                .unwrap();
+            }
+
+            let (jpeg, format) = image::read_any(&jpeg_path).unwrap();
+            let (png, _) = image::read_any(&png_path).unwrap();
+            assert_eq!(format, image::ImageFormat::Jpeg);
+            assert_eq!((jpeg.width, jpeg.height), (50, 100));
+
+            // 非可逆なので、平均の差で比べる。
+            let diff: u64 = jpeg
+                .pixels
+                .iter()
+                .zip(&png.pixels)
+                .map(|(a, b)| a.abs_diff(*b) as u64)
+                .sum();
+            assert!(diff / (jpeg.pixels.len() as u64) < 8);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn flattened_when_output_format_is_jpeg_and_transparent() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("transparent.png");
+            let dist_path = out_dir.path().join("dist.jpg");
+            image::png::write_png(
+                &src_path,
+                &[0, 0, 0, 0].repeat(16 * 16),
+                16,
+                16,
+                &WriteOptions::default(),
+            )
+            .unwrap();
+
+            let options = ResizeOptions {
+                output_format: OutputFormat::Jpeg,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(0),
+                SizeCommand::Pixel(0),
+                &options,
+            )
+            .unwrap();
+
+            // 既定の背景色は白。
+            let (image, _) = image::read_any(&dist_path).unwrap();
+            assert!(image.pixels.iter().all(|v| *v >= 250));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn checking_value_when_gif_frame_is_selected() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("frames.gif");
+            let dist_path = out_dir.path().join("dist.png");
+            {
+                let fs = std::fs::File::create(&src_path).unwrap();
+                let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+                let mut encoder = gif::Encoder::new(fs, 4, 4, &palette).unwrap();
+                for index in 0..3 {
+                    let frame = gif::Frame {
+                        width: 4,
+                        height: 4,
+                        buffer: std::borrow::Cow::Owned(vec![index; 16]),
+                        ..Default::default()
+                    };
+                    encoder.write_frame(&frame).unwrap();
+                }
+            }
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                gif_frame: 2,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(2),
+                SizeCommand::Pixel(2),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.pixels, [0, 0, 255, 255].repeat(4));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn success_when_parent_dirs_are_created() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("a/b/c/out.png");
+
+            let options = ResizeOptions {
+                create_parent_dirs: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &options,
+            )
+            .unwrap();
+
+            assert_eq!(image::png::read_dimensions(&dist_path).unwrap(), (50, 100));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_parent_dirs_do_not_exist() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("a/b/c/out.png");
+
+            let result = to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            );
+
+            assert!(result.is_err());
+            assert!(!out_dir.path().join("a").exists());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn exact_size_when_fit_is_cover() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            let options = ResizeOptions {
+                fit: FitMode::Cover,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(50),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.width, 50);
+            assert_eq!(image.height, 50);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn cropped_at_center_when_fit_is_cover() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            // 左から赤、緑、青、白の列。
+            let row = [
+                [255, 0, 0, 255],
+                [0, 255, 0, 255],
+                [0, 0, 255, 255],
+                [255, 255, 255, 255],
+            ]
+            .concat();
+            let buf = [row.clone(), row].concat();
+            image::png::write_png(&src_path, &buf, 4, 2, &WriteOptions::default()).unwrap();
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                fit: FitMode::Cover,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(2),
+                SizeCommand::Pixel(2),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.width, 2);
+            assert_eq!(image.height, 2);
+            let expected = [[0, 255, 0, 255], [0, 0, 255, 255]].concat();
+            assert_eq!(image.pixels, [expected.clone(), expected].concat());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn padded_top_and_bottom_when_fit_is_contain_and_input_is_wide() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let buf = [[255, 0, 0, 255]; 4 * 2].concat();
+            image::png::write_png(&src_path, &buf, 4, 2, &WriteOptions::default()).unwrap();
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                fit: FitMode::Contain,
+                background: [0, 0, 255, 255],
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(4),
+                SizeCommand::Pixel(4),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.width, 4);
+            assert_eq!(image.height, 4);
+            let padding = [[0, 0, 255, 255]; 4].concat();
+            let row = [[255, 0, 0, 255]; 4].concat();
+            assert_eq!(
+                image.pixels,
+                [padding.clone(), row.clone(), row, padding].concat()
+            );
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn bluish_padding_when_pad_fill_is_dominant_and_input_is_mostly_blue() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            // 2x4の、下の1行だけ赤で残りは青い不透明な画像。
+            let buf = [
+                [[0, 0, 255, 255]; 2 * 3].concat(),
+                [[255, 0, 0, 255]; 2].concat(),
+            ]
+            .concat();
+            image::png::write_png(&src_path, &buf, 2, 4, &WriteOptions::default()).unwrap();
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                fit: FitMode::Contain,
+                pad_fill: PadFill::Dominant,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(4),
+                SizeCommand::Pixel(4),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!((image.width, image.height), (4, 4));
+            // 左右の1ピクセルが余白。
+            for row in image.pixels.chunks_exact(4 * 4) {
+                assert_eq!(row[..4], [0, 0, 255, 255]);
+                assert_eq!(row[3 * 4..], [0, 0, 255, 255]);
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn padded_left_and_right_when_fit_is_contain_and_input_is_tall() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            let options = ResizeOptions {
+                fit: FitMode::Contain,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(100),
+                SizeCommand::Pixel(100),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.width, 100);
+            assert_eq!(image.height, 100);
+            // 左右の25ピクセルは透明。
+            for row in image.pixels.chunks_exact(100 * 4) {
+                assert!(row[..25 * 4].iter().all(|v| *v == 0));
+                assert!(row[75 * 4..].iter().all(|v| *v == 0));
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn scaled_phys_when_source_has_phys() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            // 144DPIは約5669ピクセル/m。
+            let write_options = WriteOptions {
+                pixel_dims: Some(PixelDimensions {
+                    xppu: 5669,
+                    yppu: 5669,
+                    unit: png::Unit::Meter,
+                }),
+                ..Default::default()
+            };
+            let buf = [255; 4 * 4 * 2];
+            image::png::write_png(&src_path, &buf, 4, 2, &write_options).unwrap();
+
+            let options = ResizeOptions {
+                preserve_pixel_dims: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Percent(50),
+                SizeCommand::Percent(50),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.width, 2);
+            assert_eq!(image.height, 1);
+            let pixel_dims = image.pixel_dims.unwrap();
+            assert_eq!(pixel_dims.xppu, 2835);
+            assert_eq!(pixel_dims.yppu, 2835);
+            assert_eq!(pixel_dims.unit, png::Unit::Meter);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn no_phys_when_default_options() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+            assert!(image::png::read_image_data(&src_path)
+                .unwrap()
+                .pixel_dims
+                .is_some());
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert!(image.pixel_dims.is_none());
+
+            out_dir.close().unwrap();
+        }
+
+        /// 2x2の白黒の市松模様を1x1に縮小した色
+        fn downscaled_checkerboard(linear_light: bool) -> Vec<u8> {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let black = [0, 0, 0, 255];
+            let white = [255, 255, 255, 255];
+            let buf = [white, black, black, white].concat();
+            image::png::write_png(&src_path, &buf, 2, 2, &WriteOptions::default()).unwrap();
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Triangle,
+                linear_light,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(1),
+                SizeCommand::Pixel(1),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            out_dir.close().unwrap();
+
+            image.pixels
+        }
+
+        #[test]
+        fn mid_gray_in_linear_light_when_linear_light() {
+            let pixels = downscaled_checkerboard(true);
+
+            for v in &pixels[..3] {
+                assert!((187..=189).contains(v), "{v}");
+            }
+            assert_eq!(pixels[3], 255);
+        }
+
+        #[test]
+        fn mid_value_when_not_linear_light() {
+            let pixels = downscaled_checkerboard(false);
+
+            for v in &pixels[..3] {
+                assert!((127..=128).contains(v), "{v}");
+            }
+            assert_eq!(pixels[3], 255);
+        }
+
+        #[test]
+        fn indexed_output_when_preserve_indexed_and_indexed_source() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let colors = [
+                [255, 0, 0, 255],
+                [0, 255, 0, 255],
+                [0, 0, 255, 255],
+                [0, 0, 0, 0],
+            ];
+            let buf = colors.concat();
+            let write_options = WriteOptions {
+                color_type: ColorType::Indexed,
+                ..Default::default()
+            };
+            image::png::write_png(&src_path, &buf, 2, 2, &write_options).unwrap();
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                preserve_indexed: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(4),
+                SizeCommand::Pixel(4),
+                &options,
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(std::fs::File::open(&dist_path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Indexed);
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.width, 4);
+            assert_eq!(image.height, 4);
+            for (i, pixel) in image.pixels.chunks_exact(4).enumerate() {
+                let (x, y) = (i % 4, i / 4);
+                assert_eq!(pixel, colors[(y / 2) * 2 + x / 2]);
+            }
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod to_resized_png_indexed_palette {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        /// パレットとtRNS
+        fn palette_of(path: &PathBuf) -> (Vec<u8>, Vec<u8>) {
+            let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            let info = reader.info();
+            assert_eq!(info.color_type, ColorType::Indexed);
+
+            (
+                info.palette.as_deref().unwrap().to_vec(),
+                info.trns.as_deref().unwrap_or_default().to_vec(),
+            )
+        }
+
+        #[test]
+        fn original_palette_when_color_is_dropped_by_downscale() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let colors = [
+                [255, 0, 0, 255],
+                [0, 255, 0, 255],
+                [0, 0, 255, 255],
+                [0, 0, 0, 0],
+            ];
+            // 黄色は(0, 0)だけなので、2x2に縮めると選ばれない。
+            let buf: Vec<u8> = (0..16)
+                .flat_map(|i| match (i % 4, i / 4) {
+                    (0, 0) => [255, 255, 0, 255],
+                    (x, y) => colors[(y / 2) * 2 + x / 2],
+                })
+                .collect();
+            let write_options = WriteOptions {
+                color_type: ColorType::Indexed,
+                ..Default::default()
+            };
+            image::png::write_png(&src_path, &buf, 4, 4, &write_options).unwrap();
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                preserve_indexed: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(2),
+                SizeCommand::Pixel(2),
+                &options,
+            )
+            .unwrap();
+
+            let (src_palette, src_trns) = palette_of(&src_path);
+            let (dist_palette, dist_trns) = palette_of(&dist_path);
+            assert_eq!(dist_palette, src_palette);
+            assert_eq!(dist_trns, src_trns);
+            assert_eq!(dist_palette.len(), 5 * 3);
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.width, 2);
+            assert_eq!(image.height, 2);
+            assert_eq!(image.pixels, colors.concat());
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn same_as_source_when_same_size() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let buf: Vec<u8> = (0..9u8)
+                .flat_map(|i| [i * 20, 0, 255 - i * 20, 255])
+                .collect();
+            let write_options = WriteOptions {
+                color_type: ColorType::Indexed,
+                ..Default::default()
+            };
+            image::png::write_png(&src_path, &buf, 3, 3, &write_options).unwrap();
+
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                preserve_indexed: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(0),
+                SizeCommand::Pixel(0),
+                &options,
+            )
+            .unwrap();
+
+            assert_eq!(palette_of(&dist_path), palette_of(&src_path));
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!(image.pixels, buf);
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod to_resized_png_auto_trim {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        /// 透明な画像の(x, y)から幅と高さの範囲を赤にしたpngを書き出し、auto_trimで100%に拡大縮小する
+        fn trimmed(width: u32, height: u32, opaque: Option<(u32, u32, u32, u32)>) -> ImageData {
+            let out_dir = tempdir().unwrap();
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            let mut buf = vec![0; width as usize * height as usize * 4];
+            if let Some((x, y, w, h)) = opaque {
+                for row in y..y + h {
+                    for col in x..x + w {
+                        let index = (row * width + col) as usize * 4;
+                        buf[index..index + 4].copy_from_slice(&[255, 0, 0, 255]);
+                    }
+                }
+            }
+            image::png::write_png(&src_path, &buf, width, height, &WriteOptions::default())
+                .unwrap();
+
+            let options = ResizeOptions {
+                auto_trim: true,
+                ..Default::default()
+            };
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Percent(100),
+                SizeCommand::Percent(100),
+                &options,
+            )
+            .unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            out_dir.close().unwrap();
+
+            image
+        }
+
+        #[test]
+        fn trimmed_when_transparent_border() {
+            let image = trimmed(6, 5, Some((1, 2, 3, 2)));
+
+            assert_eq!((image.width, image.height), (3, 2));
+            assert_eq!(image.pixels, [255, 0, 0, 255].repeat(6));
+        }
+
+        #[test]
+        fn unchanged_when_all_transparent() {
+            let image = trimmed(4, 3, None);
+
+            assert_eq!((image.width, image.height), (4, 3));
+            assert!(image.pixels.chunks_exact(4).all(|v| v[3] == 0));
+        }
+
+        #[test]
+        fn unchanged_when_opaque() {
+            let image = trimmed(4, 3, Some((0, 0, 4, 3)));
+
+            assert_eq!((image.width, image.height), (4, 3));
+            assert_eq!(image.pixels, [255, 0, 0, 255].repeat(12));
+        }
+    }
+
+    mod to_resized_png_with_progress {
+        use super::*;
+
+        use std::cell::RefCell;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn checking_value_of_reported_progress() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let reported = RefCell::new(Vec::new());
+
+            to_resized_png_with_progress(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+                |v| reported.borrow_mut().push(v),
+            )
+            .unwrap();
+
+            let reported = reported.into_inner();
+            assert_eq!(reported.len(), 3);
+            assert!(reported.windows(2).all(|v| v[0] < v[1]), "{:?}", reported);
+            assert_eq!(reported.last(), Some(&1.0));
+            assert!(dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    #[cfg(feature = "log")]
+    mod logging {
+        use super::*;
+
+        use std::sync::{Mutex, Once};
+
+        use tempfile::tempdir;
+
+        /// 出力された内容を溜めておくだけのロガー。他のテストの分も混ざる
+        struct TestLogger;
+
+        static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        impl log::Log for TestLogger {
+            fn enabled(&self, _metadata: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                RECORDS.lock().unwrap().push(record.args().to_string());
+            }
+
+            fn flush(&self) {}
+        }
+
+        fn init_logger() {
+            static INIT: Once = Once::new();
+            INIT.call_once(|| {
+                log::set_logger(&TestLogger).unwrap();
+                log::set_max_level(log::LevelFilter::Debug);
+            });
+        }
+
+        #[test]
+        fn record_decoded_format_when_png() {
+            init_logger();
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            let records = RECORDS.lock().unwrap();
+            assert!(
+                records.iter().any(|v| v.starts_with("decoded as PNG")),
+                "{:?}",
+                records
+            );
+            assert!(records.iter().any(|v| v.starts_with("encode finished in")));
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod to_resized_png_with_result {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn success_when_png() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            let result = to_resized_png_with_result(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(-1),
+                &ResizeOptions::default(),
+            )
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(result.source_format, ImageFormat::Png);
+            assert_eq!(result.source_size, (100, 200));
+            assert_eq!(result.output_size, (50, 100));
+            assert_eq!(
+                result.output_bytes_written,
+                std::fs::metadata(&dist_path).unwrap().len()
+            );
+            assert!(result.output_bytes_written > 0);
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn none_when_size_cannot_be_calculated() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            let result = to_resized_png_with_result(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(-1),
+                SizeCommand::Pixel(-1),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            assert_eq!(result, None);
+            assert!(!dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod to_resized_png_copy_through {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        fn copy_through_options() -> ResizeOptions {
+            ResizeOptions {
+                copy_through: true,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn identical_bytes_when_png_at_original_size() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            let result = to_resized_png_with_result(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(0),
+                SizeCommand::Pixel(0),
+                &copy_through_options(),
+            )
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(
+                std::fs::read(&dist_path).unwrap(),
+                std::fs::read(&src_path).unwrap()
+            );
+            assert_eq!(result.source_size, (100, 200));
+            assert_eq!(result.output_size, (100, 200));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn reencoded_when_size_changes() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(0),
+                &copy_through_options(),
+            )
+            .unwrap();
+
+            let (image, _) = image::read_any(&dist_path).unwrap();
+            assert_eq!((image.width, image.height), (50, 200));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn reencoded_when_jpeg_at_original_size() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
+            let dist_path = out_dir.path().join("dist.png");
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(0),
+                SizeCommand::Pixel(0),
+                &copy_through_options(),
+            )
+            .unwrap();
+
+            assert_eq!(get_image_type(&dist_path), "PNG");
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn reencoded_when_flipped() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(0),
+                SizeCommand::Pixel(0),
+                &ResizeOptions {
+                    flip_horizontal: true,
+                    ..copy_through_options()
+                },
+            )
+            .unwrap();
+
+            assert_ne!(
+                std::fs::read(&dist_path).unwrap(),
+                std::fs::read(&src_path).unwrap()
+            );
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod to_resized_png_animate_gif {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        /// 4x4の、赤、緑、青の順に表示時間が10、20、30(1/100秒)のフレームのgif
+        fn write_animated_gif(path: &std::path::Path) {
+            let fs = std::fs::File::create(path).unwrap();
+            let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+            let mut encoder = gif::Encoder::new(fs, 4, 4, &palette).unwrap();
+            for index in 0..3 {
+                let frame = gif::Frame {
+                    width: 4,
+                    height: 4,
+                    delay: (index as u16 + 1) * 10,
+                    buffer: std::borrow::Cow::Owned(vec![index; 16]),
+                    ..Default::default()
+                };
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+
+        #[test]
+        fn apng_with_every_frame_when_animated_gif() {
+            let out_dir = tempdir().unwrap();
+            let src_path = out_dir.path().join("animated.gif");
+            let dist_path = out_dir.path().join("dist.png");
+            write_animated_gif(&src_path);
+
+            let result = to_resized_png_with_result(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(8),
+                SizeCommand::Pixel(8),
+                &ResizeOptions {
+                    animate_gif: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(result.source_format, ImageFormat::Gif);
+            assert_eq!(result.output_size, (8, 8));
+
+            let decoder = png::Decoder::new(std::fs::File::open(&dist_path).unwrap());
+            let mut reader = decoder.read_info().unwrap();
+            let control = *reader.info().animation_control().unwrap();
+            assert_eq!(control.num_frames, 3);
+            assert_eq!((reader.info().width, reader.info().height), (8, 8));
+
+            let mut buf = vec![0; reader.output_buffer_size()];
+            for (index, color) in [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]]
+                .iter()
+                .enumerate()
+            {
+                reader.next_frame(&mut buf).unwrap();
+                let frame_control = reader.info().frame_control().unwrap();
+                assert_eq!((frame_control.width, frame_control.height), (8, 8));
+                assert_eq!(frame_control.delay_num, (index as u16 + 1) * 10);
+                assert_eq!(frame_control.delay_den, 100);
+                assert_eq!(&buf[..4], color);
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn single_frame_when_not_enabled() {
+            let out_dir = tempdir().unwrap();
+            let src_path = out_dir.path().join("animated.gif");
+            let dist_path = out_dir.path().join("dist.png");
+            write_animated_gif(&src_path);
+
+            to_resized_png(
+                &src_path,
+                &dist_path,
+                SizeCommand::Pixel(8),
+                SizeCommand::Pixel(8),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            let decoder = png::Decoder::new(std::fs::File::open(&dist_path).unwrap());
+            let reader = decoder.read_info().unwrap();
+            assert!(reader.info().animation_control().is_none());
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod to_square_png {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        /// 赤、緑、青の3つの正方形を、wideなら横に、そうでなければ縦に並べた画像
+        fn write_three_squares(path: &PathBuf, side: u32, wide: bool) {
+            let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+            let (width, height) = if wide {
+                (side * 3, side)
+            } else {
+                (side, side * 3)
+            };
+            let buf: Vec<u8> = (0..height)
+                .flat_map(|y| {
+                    (0..width).flat_map(move |x| {
+                        let index = if wide { x / side } else { y / side };
+                        colors[index as usize]
+                    })
+                })
+                .collect();
+            image::png::write_png(path, &buf, width, height, &WriteOptions::default()).unwrap();
+        }
+
+        #[test]
+        fn center_square_when_landscape() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            write_three_squares(&src_path, 4, true);
+
+            to_square_png(&src_path, &dist_path, 4).unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!((image.width, image.height), (4, 4));
+            assert_eq!(image.pixels, [0, 255, 0, 255].repeat(16));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn center_square_when_portrait() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            write_three_squares(&src_path, 4, false);
+
+            to_square_png(&src_path, &dist_path, 4).unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!((image.width, image.height), (4, 4));
+            assert_eq!(image.pixels, [0, 255, 0, 255].repeat(16));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn resized_when_square() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = out_dir.path().join("src.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let buf = [255, 0, 0, 255].repeat(8 * 8);
+            image::png::write_png(&src_path, &buf, 8, 8, &WriteOptions::default()).unwrap();
+
+            to_square_png(&src_path, &dist_path, 3).unwrap();
+
+            let image = image::png::read_image_data(&dist_path).unwrap();
+            assert_eq!((image.width, image.height), (3, 3));
+            assert_eq!(image.pixels, [255, 0, 0, 255].repeat(9));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn parameter_error_when_size_is_zero() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+
+            assert!(matches!(
+                to_square_png(&src_path, &dist_path, 0),
+                Err(ResizedPngError::ParameterError(None))
+            ));
+            assert!(!dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod to_resized_pngs {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn checking_value_when_1x_and_2x() {
+            let out_dir = tempdir().unwrap();
+
+            for name in ["sample.png", "sample.jpg"] {
+                let src_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("test_target/image")
+                    .join(name);
+                let dist_1x = out_dir.path().join("1x.png");
+                let dist_2x = out_dir.path().join("2x.png");
+                let targets = [
+                    (
+                        dist_1x.clone(),
+                        SizeCommand::Pixel(25),
+                        SizeCommand::Pixel(50),
+                    ),
+                    (
+                        dist_2x.clone(),
+                        SizeCommand::Pixel(50),
+                        SizeCommand::Pixel(100),
+                    ),
+                ];
+
+                let results =
+                    to_resized_pngs(&src_path, &targets, &ResizeOptions::default()).unwrap();
+
+                assert!(results.iter().all(|v| v.is_ok()), "{name}");
+                assert_eq!(image::png::read_dimensions(&dist_1x).unwrap(), (25, 50));
+                assert_eq!(image::png::read_dimensions(&dist_2x).unwrap(), (50, 100));
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn others_are_written_when_one_target_fails() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let dist_path = out_dir.path().join("dist.png");
+            let targets = [
+                (
+                    out_dir.path().join("missing/dist.png"),
+                    SizeCommand::Pixel(25),
+                    SizeCommand::Pixel(50),
+                ),
+                (
+                    dist_path.clone(),
+                    SizeCommand::Pixel(50),
+                    SizeCommand::Pixel(100),
+                ),
+            ];
+
+            let results = to_resized_pngs(&src_path, &targets, &ResizeOptions::default()).unwrap();
+
+            assert!(results[0].is_err());
+            assert!(results[1].is_ok());
+            assert_eq!(image::png::read_dimensions(&dist_path).unwrap(), (50, 100));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_source_is_not_image() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+            let targets = [(
+                out_dir.path().join("dist.png"),
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+            )];
+
+            assert!(to_resized_pngs(&src_path, &targets, &ResizeOptions::default()).is_err());
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod to_resized_png_from_reader {
+        use super::*;
+
+        use std::io::Cursor;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn success_when_sample_png_from_cursor() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let reader = Cursor::new(std::fs::read(src_path).unwrap());
+            let dist_path = out_dir.path().join("dist.png");
+
+            to_resized_png_from_reader(
+                reader,
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            assert_eq!(image::png::read_dimensions(&dist_path).unwrap(), (50, 100));
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn failed_when_not_image() {
+            let out_dir = tempdir().unwrap();
+
+            let dist_path = out_dir.path().join("dist.png");
+
+            let result = to_resized_png_from_reader(
+                Cursor::new(b"[package]".to_vec()),
+                &dist_path,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            );
+
+            assert!(result.is_err());
+            assert!(!dist_path.exists());
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod resize_nearest {
+        use super::*;
+
+        #[test]
+        fn centered_when_upscale() {
+            let mut dist = [0; 8];
+
+            resize_nearest(&[1, 2], (2, 1), &mut dist, (8, 1)).unwrap();
+
+            assert_eq!(dist, [1, 1, 1, 1, 2, 2, 2, 2]);
+        }
+
+        #[test]
+        fn centered_when_downscale() {
+            let src: Vec<u8> = (0..16).collect();
+            let mut dist = [0; 4];
+
+            resize_nearest(&src, (4, 4), &mut dist, (2, 2)).unwrap();
+
+            assert_eq!(dist, [5, 7, 13, 15]);
+        }
+
+        #[test]
+        fn failed_when_buffer_is_short() {
+            let mut dist = [0; 4];
+
+            assert!(resize_nearest(&[1, 2, 3], (2, 2), &mut dist, (2, 2)).is_err());
+        }
+    }
+
+    mod resizer_cache {
+        use super::*;
+
+        #[test]
+        fn reused_when_same_key() {
+            let mut cache = ResizerCache::default();
+            let src = [0; 4 * 4 * 4];
+            let mut dist = [0; 2 * 2 * 4];
+
+            for _ in 0..2 {
+                let key = (4, 4, 2, 2, ResizeFilter::default());
+                cache
+                    .resize_rgba8(key, src.as_rgba(), dist.as_rgba_mut())
+                    .unwrap();
+            }
+            assert_eq!(cache.rgba8.len(), 1);
+
+            let key = (4, 4, 2, 2, ResizeFilter::Point);
+            cache
+                .resize_rgba8(key, src.as_rgba(), dist.as_rgba_mut())
+                .unwrap();
+            assert_eq!(cache.rgba8.len(), 2);
+        }
+    }
+
+    mod resize_batch {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn all_outputs_exist_when_inputs_have_same_size() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let inputs: Vec<(PathBuf, PathBuf)> = (0..3)
+                .map(|i| (src_path.clone(), out_dir.path().join(format!("{i}.png"))))
+                .collect();
+
+            let results = resize_batch(
+                &inputs,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            );
+
+            assert_eq!(results.len(), 3);
+            for ((_, dist_path), result) in inputs.iter().zip(results) {
+                result.unwrap();
+                let image = image::png::read_image_data(dist_path).unwrap();
+                assert_eq!(image.width, 50);
+                assert_eq!(image.height, 100);
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn continue_when_some_input_fails() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let wrong_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/something_wrong.png");
+            let inputs = [
+                (src_path.clone(), out_dir.path().join("0.png")),
+                (wrong_path, out_dir.path().join("1.png")),
+                (src_path, out_dir.path().join("2.png")),
+            ];
+
+            let results = resize_batch(
+                &inputs,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            );
+
+            assert!(results[0].is_ok());
+            assert!(results[1].is_err());
+            assert!(results[2].is_ok());
+            assert!(inputs[0].1.exists());
+            assert!(!inputs[1].1.exists());
+            assert!(inputs[2].1.exists());
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod resize_batch_parallel {
+        use super::*;
+
+        use tempfile::tempdir;
+
+        #[test]
+        fn same_outputs_as_sequential() {
+            let out_dir = tempdir().unwrap();
+            let sequential_dir = out_dir.path().join("sequential");
+            let parallel_dir = out_dir.path().join("parallel");
+            std::fs::create_dir(&sequential_dir).unwrap();
+            std::fs::create_dir(&parallel_dir).unwrap();
+
+            let names = [
+                "sample.png",
+                "sample.bmp",
+                "sample.jpg",
+                "sample.gif",
+                "sample.webp",
+                "sample.png",
+            ];
+            let inputs = |dir: &Path| -> Vec<(PathBuf, PathBuf)> {
+                names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| {
+                        (
+                            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                                .join("test_target/image")
+                                .join(name),
+                            dir.join(format!("{i}.png")),
+                        )
+                    })
+                    .collect()
+            };
+            let sequential_inputs = inputs(&sequential_dir);
+            let parallel_inputs = inputs(&parallel_dir);
+
+            let sequential = resize_batch(
+                &sequential_inputs,
+                SizeCommand::Pixel(30),
+                SizeCommand::Pixel(-1),
+                &ResizeOptions::default(),
+            );
+            let parallel = resize_batch_parallel(
+                &parallel_inputs,
+                SizeCommand::Pixel(30),
+                SizeCommand::Pixel(-1),
+                &ResizeOptions::default(),
+                NonZeroUsize::new(3).unwrap(),
+            );
+
+            assert_eq!(parallel.len(), names.len());
+            for (((_, a), (_, b)), (c, d)) in sequential_inputs
+                .iter()
+                .zip(&parallel_inputs)
+                .zip(sequential.into_iter().zip(parallel))
+            {
+                c.unwrap();
+                d.unwrap();
+                assert_eq!(std::fs::read(a).unwrap(), std::fs::read(b).unwrap());
+            }
+
+            out_dir.close().unwrap();
+        }
+
+        #[test]
+        fn results_in_input_order_when_some_input_fails() {
+            let out_dir = tempdir().unwrap();
+
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let wrong_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/something_wrong.png");
+            let inputs = [
+                (wrong_path.clone(), out_dir.path().join("0.png")),
+                (src_path.clone(), out_dir.path().join("1.png")),
+                (wrong_path, out_dir.path().join("2.png")),
+                (src_path, out_dir.path().join("3.png")),
+            ];
+
+            let results = resize_batch_parallel(
+                &inputs,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+                NonZeroUsize::new(8).unwrap(),
+            );
+
+            let oks: Vec<bool> = results.iter().map(|v| v.is_ok()).collect();
+            assert_eq!(oks, [false, true, false, true]);
+
+            out_dir.close().unwrap();
+        }
+    }
+
+    mod to_resized_png_bytes {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_valid_parameter() {
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let src = std::fs::read(src_path).unwrap();
+
+            let bytes = to_resized_png_bytes(
+                &src,
+                SizeCommand::Pixel(50),
+                SizeCommand::Pixel(100),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            assert!(bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]));
+
+            let decoder = png::Decoder::new(bytes.as_slice());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().width, 50);
+            assert_eq!(reader.info().height, 100);
+        }
+
+        #[test]
+        fn indexed_output_when_preserve_indexed_and_indexed_source() {
+            let colors = [
+                [255, 0, 0, 255],
+                [0, 255, 0, 255],
+                [0, 0, 255, 255],
+                [0, 0, 0, 0],
+            ];
+            let write_options = WriteOptions {
+                color_type: ColorType::Indexed,
+                ..Default::default()
+            };
+            let src = image::png::encode_png(&colors.concat(), 2, 2, &write_options).unwrap();
+
+            // バイト列で返す場合は、RGBAにしてから拡大縮小する。
+            let options = ResizeOptions {
+                filter: ResizeFilter::Point,
+                preserve_indexed: true,
+                ..Default::default()
+            };
+            let bytes =
+                to_resized_png_bytes(&src, SizeCommand::Pixel(4), SizeCommand::Pixel(4), &options)
+                    .unwrap();
+
+            let decoder = png::Decoder::new(bytes.as_slice());
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, ColorType::Indexed);
+
+            let image = image::png::read_image_data_from_bytes(&bytes).unwrap();
+            assert_eq!(image.width, 4);
+            assert_eq!(image.height, 4);
+            for (i, pixel) in image.pixels.chunks_exact(4).enumerate() {
+                let (x, y) = (i % 4, i / 4);
+                assert_eq!(pixel, colors[(y / 2) * 2 + x / 2]);
+            }
+        }
+
+        #[test]
+        fn empty_when_size_can_not_be_calculated() {
+            let src_path =
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+            let src = std::fs::read(src_path).unwrap();
+
+            let bytes = to_resized_png_bytes(
+                &src,
+                SizeCommand::Pixel(-1),
+                SizeCommand::Pixel(-1),
+                &ResizeOptions::default(),
+            )
+            .unwrap();
+
+            assert!(bytes.is_empty());
+        }
+    }
+
+    mod to_resized_png_bytes_within {
+        use super::*;
+
+        /// 圧縮しにくい、大きなpngのバイト列
+        ///
+        /// 掛け算だけの値は規則が残って縮んでしまうので、xorshiftの乱数で埋める。
+        fn noisy_png(width: u32, height: u32) -> Vec<u8> {
+            let mut state = 0x2545_F491u32;
+            let buf: Vec<u8> = (0..width * height * 4)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    (state >> 24) as u8
+                })
+                .collect();
+
+            image::png::encode_png(&buf, width, height, &WriteOptions::default()).unwrap()
+        }
+
+        #[test]
+        fn under_budget_when_large_image() {
+            let src = noisy_png(400, 200);
+            let budget = 20_000;
+            assert!(src.len() > budget);
+
+            let bytes =
+                to_resized_png_bytes_within(&src, budget, &ResizeOptions::default()).unwrap();
+
+            assert!(bytes.len() <= budget, "{}", bytes.len());
+            let reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+            let (width, height) = (reader.info().width, reader.info().height);
+            assert!(width < 400);
+            // 縦横比は保たれる。
+            assert!(width.abs_diff(height * 2) <= 2, "{}x{}", width, height);
+        }
+
+        #[test]
+        fn original_size_when_already_under_budget() {
+            let src = noisy_png(10, 10);
+
+            let bytes =
+                to_resized_png_bytes_within(&src, usize::MAX, &ResizeOptions::default()).unwrap();
+
+            let reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+            assert_eq!((reader.info().width, reader.info().height), (10, 10));
+        }
+
+        #[test]
+        fn smallest_when_budget_is_infeasible() {
+            let src = noisy_png(400, 200);
+
+            let bytes = to_resized_png_bytes_within(&src, 1, &ResizeOptions::default()).unwrap();
+
+            let reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+            assert_eq!((reader.info().width, reader.info().height), (4, 2));
+        }
+    }
+
+    mod input_size {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_not_zero() {
+            let image = image::ImageData {
+                pixels: vec![0; 100 * 200 * 4],
+                width: 100,
+                height: 200,
+                source_color: image::SourceColorType::Rgba,
+                pixel_dims: None,
+                icc_profile: None,
+                text_chunks: Vec::new(),
+            };
+
+            let (width, height) = input_size(image.width, image.height).unwrap();
+            assert_eq!((width.get(), height.get()), (100, 200));
+        }
+
+        #[test]
+        fn failed_with_size_when_width_is_zero() {
+            let image: image::ImageData = image::ImageData {
+                pixels: Vec::new(),
+                width: 0,
+                height: 200,
+                source_color: image::SourceColorType::Rgba,
+                pixel_dims: None,
+                icc_profile: None,
+                text_chunks: Vec::new(),
+            };
+
+            let e = input_size(image.width, image.height).unwrap_err();
+            assert!(matches!(
+                e,
+                ResizedPngError::InputSizeError {
+                    width: 0,
+                    height: 200
+                }
+            ));
+            assert_eq!(e.to_code(), 8);
+        }
+    }
+
+    mod plan_size {
+        use super::*;
+
+        fn size(width: u32, height: u32) -> (NonZeroU32, NonZeroU32) {
+            (
+                NonZeroU32::new(width).unwrap(),
+                NonZeroU32::new(height).unwrap(),
+            )
+        }
+
+        fn inside_box(input: (NonZeroU32, NonZeroU32)) -> Option<PlannedSize> {
+            let options = ResizeOptions {
+                fit: FitMode::Inside,
+                ..Default::default()
+            };
+
+            plan_size(
+                SizeCommand::Pixel(100),
+                SizeCommand::Pixel(100),
+                input.0,
+                input.1,
+                &options,
+            )
+            .unwrap()
+        }
+
+        #[test]
+        fn limited_by_width_when_inside_and_input_is_wider() {
+            assert_eq!(
+                inside_box(size(400, 200)),
+                Some((size(100, 50), size(100, 50)))
+            );
+        }
+
+        #[test]
+        fn limited_by_height_when_inside_and_input_is_taller() {
+            assert_eq!(
+                inside_box(size(150, 600)),
+                Some((size(25, 100), size(25, 100)))
+            );
+        }
+    }
+
+    mod fit_within_megapixels {
+        use super::*;
+
+        fn size(width: u32, height: u32) -> (NonZeroU32, NonZeroU32) {
+            (
+                NonZeroU32::new(width).unwrap(),
+                NonZeroU32::new(height).unwrap(),
+            )
+        }
+
+        #[test]
+        fn checking_value_when_over_cap() {
+            let (width, height) = size(4000, 3000);
+
+            assert_eq!(
+                fit_within_megapixels(width, height, 3.0).unwrap(),
+                size(2000, 1500)
+            );
+        }
+
+        #[test]
+        fn checking_value_when_ratio_is_not_round() {
+            let (width, height) = size(1920, 1080);
+
+            let (width, height) = fit_within_megapixels(width, height, 1.0).unwrap();
+
+            assert!(width.get() as u64 * height.get() as u64 <= 1_000_000);
+            assert_eq!((width.get(), height.get()), (1333, 750));
+        }
+
+        #[test]
+        fn untouched_when_under_cap() {
+            let (width, height) = size(1000, 1000);
+
+            assert_eq!(
+                fit_within_megapixels(width, height, 1.0).unwrap(),
+                size(1000, 1000)
+            );
+        }
+
+        #[test]
+        fn failed_when_cap_is_not_positive() {
+            let (width, height) = size(10, 10);
+
+            for max_megapixels in [0.0, -1.0, f64::NAN] {
+                assert!(matches!(
+                    fit_within_megapixels(width, height, max_megapixels),
+                    Err(ResizedPngError::ParameterError(None))
+                ));
+            }
+        }
+    }
+
+    mod output_buffer_size {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_under_limit() {
+            let width = NonZeroU32::new(100).unwrap();
+            let height = NonZeroU32::new(200).unwrap();
+
+            assert_eq!(
+                output_buffer_size(width, height, DEFAULT_MAX_PIXELS).unwrap(),
+                100 * 200 * 4
+            );
+        }
+
+        #[test]
+        fn failed_when_over_limit() {
+            let width = NonZeroU32::new(10001).unwrap();
+            let height = NonZeroU32::new(10000).unwrap();
+
+            assert!(matches!(
+                output_buffer_size(width, height, DEFAULT_MAX_PIXELS),
+                Err(ResizedPngError::LimitsError(_))
+            ));
+        }
+
+        #[test]
+        fn failed_without_panic_when_multiplication_overflows() {
+            let width = NonZeroU32::new(u32::MAX).unwrap();
+            let height = NonZeroU32::new(u32::MAX).unwrap();
+
+            assert!(matches!(
+                output_buffer_size(width, height, u64::MAX),
+                Err(ResizedPngError::LimitsError(_))
+            ));
+        }
+    }
+
+    mod linear_to_srgb {
+        use super::*;
+
+        #[test]
+        fn round_trip_when_srgb_to_linear() {
+            let table = srgb_to_linear_table();
+            let rgba: Vec<u8> = (0..=255).flat_map(|v| [v, v, v, v]).collect();
+
+            assert_eq!(linear_to_srgb(&srgb_to_linear(&rgba, &table), &table), rgba);
+        }
+
+        #[test]
+        fn checking_value_when_half_of_linear() {
+            let table = srgb_to_linear_table();
+
+            assert_eq!(
+                linear_to_srgb(&[32768, 0, u16::MAX, u16::MAX], &table),
+                [188, 0, 255, 255]
+            );
+        }
+    }
+
+    mod scale_pixel_dims {
+        use super::*;
+
+        #[test]
+        fn swapped_when_rotated_by_90() {
+            let size = |v| NonZeroU32::new(v).unwrap();
+            let pixel_dims = PixelDimensions {
+                xppu: 100,
+                yppu: 200,
+                unit: png::Unit::Unspecified,
+            };
+
+            let result = scale_pixel_dims(
+                pixel_dims,
+                Rotation::Cw90,
+                (size(20), size(10)),
+                (size(40), size(5)),
+            );
+
+            assert_eq!(result.xppu, 400);
+            assert_eq!(result.yppu, 50);
+        }
+    }
+
+    mod rotate_rgba {
+        use super::*;
+
+        const A: [u8; 4] = [1, 2, 3, 4];
+        const B: [u8; 4] = [5, 6, 7, 8];
+
+        #[test]
+        fn checking_value_when_none() {
+            assert_eq!(
+                rotate_rgba([A, B].concat(), 2, 1, Rotation::None),
+                ([A, B].concat(), 2, 1)
+            );
+        }
+
+        #[test]
+        fn checking_value_when_cw90() {
+            // 左にあったAが上に来る。
+            assert_eq!(
+                rotate_rgba([A, B].concat(), 2, 1, Rotation::Cw90),
+                ([A, B].concat(), 1, 2)
+            );
+        }
+
+        #[test]
+        fn checking_value_when_cw180() {
+            assert_eq!(
+                rotate_rgba([A, B].concat(), 2, 1, Rotation::Cw180),
+                ([B, A].concat(), 2, 1)
+            );
+        }
+
+        #[test]
+        fn checking_value_when_cw270() {
+            // 左にあったAが下に来る。
+            assert_eq!(
+                rotate_rgba([A, B].concat(), 2, 1, Rotation::Cw270),
+                ([B, A].concat(), 1, 2)
+            );
+        }
+
+        #[test]
+        fn checking_value_when_cw90_and_not_square() {
+            // 2x3の番号を回転すると3x2になる。
+            let rgba: Vec<u8> = (0..6).flat_map(|v| [v; 4]).collect();
+            let expected: Vec<u8> = [4, 2, 0, 5, 3, 1].iter().flat_map(|v| [*v; 4]).collect();
+
+            assert_eq!(rotate_rgba(rgba, 2, 3, Rotation::Cw90), (expected, 3, 2));
+        }
+    }
+
+    mod flip_rgba {
+        use super::*;
+
+        // 2x2の各ピクセルに0から3の番号をつける。
+        fn numbered() -> Vec<u8> {
+            (0..4).flat_map(|v| [v, v + 10, v + 20, 255]).collect()
+        }
+
+        fn pixels(order: [u8; 4]) -> Vec<u8> {
+            order
+                .iter()
+                .flat_map(|v| [*v, v + 10, v + 20, 255])
+                .collect()
+        }
+
+        #[test]
+        fn unchanged_when_no_flip() {
+            assert_eq!(flip_rgba(numbered(), 2, 2, false, false), numbered());
+        }
+
+        #[test]
+        fn checking_value_when_horizontal() {
+            assert_eq!(
+                flip_rgba(numbered(), 2, 2, true, false),
+                pixels([1, 0, 3, 2])
+            );
+        }
+
+        #[test]
+        fn checking_value_when_vertical() {
+            assert_eq!(
+                flip_rgba(numbered(), 2, 2, false, true),
+                pixels([2, 3, 0, 1])
+            );
+        }
+
+        #[test]
+        fn same_as_cw180_when_both() {
+            let rgba: Vec<u8> = (0..6).flat_map(|v| [v, v + 10, v + 20, 255]).collect();
+
+            assert_eq!(
+                flip_rgba(rgba.clone(), 3, 2, true, true),
+                rotate_rgba(rgba, 3, 2, Rotation::Cw180).0
+            );
+        }
+    }
+
+    mod cover_size {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_input_is_taller() {
+            let size = |v| NonZeroU32::new(v).unwrap();
+
+            assert_eq!(
+                cover_size(size(50), size(50), size(100), size(200)),
+                (size(50), size(100))
+            );
+        }
+
+        #[test]
+        fn checking_value_when_input_is_wider() {
+            let size = |v| NonZeroU32::new(v).unwrap();
+
+            assert_eq!(
+                cover_size(size(30), size(20), size(300), size(100)),
+                (size(60), size(20))
+            );
+        }
+    }
+
+    mod contain_size {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_input_is_taller() {
+            let size = |v| NonZeroU32::new(v).unwrap();
+
+            assert_eq!(
+                contain_size(size(50), size(50), size(100), size(200)),
+                (size(25), size(50))
+            );
+        }
+
+        #[test]
+        fn checking_value_when_input_is_wider() {
+            let size = |v| NonZeroU32::new(v).unwrap();
+
+            assert_eq!(
+                contain_size(size(30), size(30), size(300), size(100)),
+                (size(30), size(10))
+            );
+        }
     }
-    if image::bmp::read_image_data(src_path).is_ok() {
-        return "BMP";
+
+    mod pad_rgba {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_canvas_is_larger() {
+            let rgba = [[1; 4], [2; 4]].concat();
+
+            assert_eq!(
+                pad_rgba(&rgba, 2, 1, 4, 3, [9; 4]),
+                [
+                    [[9; 4]; 4].concat(),
+                    [[9; 4], [1; 4], [2; 4], [9; 4]].concat(),
+                    [[9; 4]; 4].concat(),
+                ]
+                .concat()
+            );
+        }
     }
-    if image::gif::read_image_data(src_path).is_ok() {
-        return "GIF";
+
+    mod edge_color {
+        use super::*;
+
+        #[test]
+        fn average_of_edge_when_center_differs() {
+            // 3x3の縁は白、中央は黒。
+            let mut rgba = [[255u8; 4]; 9].concat();
+            rgba[4 * 4..5 * 4].copy_from_slice(&[0, 0, 0, 255]);
+
+            assert_eq!(edge_color(&rgba, 3, 3), [255; 4]);
+        }
     }
-    if image::jpeg::read_image_data(src_path).is_ok() {
-        return "JPEG";
+
+    mod dominant_color {
+        use super::*;
+
+        #[test]
+        fn most_common_color_when_mixed() {
+            let rgba = [
+                [[0u8, 0, 255, 255]; 6].concat(),
+                [[255, 0, 0, 255]; 3].concat(),
+            ]
+            .concat();
+
+            assert_eq!(dominant_color(&rgba), [0, 0, 255, 255]);
+        }
+
+        #[test]
+        fn average_of_close_colors() {
+            let rgba = [[0u8, 0, 250, 255], [0, 0, 254, 255], [255, 0, 0, 255]].concat();
+
+            assert_eq!(dominant_color(&rgba), [0, 0, 252, 255]);
+        }
+
+        #[test]
+        fn transparent_when_empty() {
+            assert_eq!(dominant_color::<u16>(&[]), [0; 4]);
+        }
     }
-    if image::webp::read_image_data(src_path).is_ok() {
-        return "WEBP";
+
+    mod flatten_rgba {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_half_transparent() {
+            let rgba = [255u8, 0, 0, 128];
+
+            assert_eq!(
+                flatten_rgba(rgba.to_vec(), 1, 1, [255, 255, 255]),
+                [255, 127, 127, 255]
+            );
+        }
+
+        #[test]
+        fn background_when_fully_transparent() {
+            let rgba = [[255u8, 0, 0, 0], [0, 255, 0, 255]].concat();
+
+            assert_eq!(
+                flatten_rgba(rgba, 2, 1, [10, 20, 30]),
+                [[10, 20, 30, 255], [0, 255, 0, 255]].concat()
+            );
+        }
+
+        #[test]
+        fn checking_value_when_16bit() {
+            let rgba = vec![u16::MAX, 0, 0, 32768];
+
+            assert_eq!(
+                flatten_rgba(rgba, 1, 1, [u16::MAX; 3]),
+                [u16::MAX, 32767, 32767, u16::MAX]
+            );
+        }
     }
 
-    "UNKNOWN"
-}
+    mod desaturate_rgba {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_primary_colors() {
+            let rgba = vec![255u8, 0, 0, 128, 0, 255, 0, 255, 0, 0, 255, 0];
 
-pub(crate) fn get_image_info(src_path: &PathBuf) -> Result<(i64, i64), ResizedPngError> {
-    let (_src_rgba, input_width_raw, input_height_raw) = image::png::read_image_data(src_path)
-        .or(image::bmp::read_image_data(src_path))
-        .or(image::gif::read_image_data(src_path))
-        .or(image::jpeg::read_image_data(src_path))
-        .or(image::webp::read_image_data(src_path))?;
+            assert_eq!(
+                desaturate_rgba(rgba),
+                [54, 54, 54, 128, 182, 182, 182, 255, 18, 18, 18, 0]
+            );
+        }
 
-    let (input_width, input_height) = NonZeroU32::new(input_width_raw)
-        .zip(NonZeroU32::new(input_height_raw))
-        .ok_or(ResizedPngError::InputSizeError)?;
+        #[test]
+        fn checking_value_when_16bit_white() {
+            assert_eq!(desaturate_rgba(vec![u16::MAX; 4]), [u16::MAX; 4]);
+        }
+    }
 
-    Ok((input_width.get() as i64, input_height.get() as i64))
-}
+    mod unsharp_mask_new {
+        use super::*;
 
-pub(crate) fn to_resized_png(
-    src_path: &PathBuf,
-    dist_path: &PathBuf,
-    width_command: i64,
-    height_command: i64,
-) -> Result<(), ResizedPngError> {
-    let (src_rgba, input_width_raw, input_height_raw) = image::png::read_image_data(src_path)
-        .or(image::bmp::read_image_data(src_path))
-        .or(image::gif::read_image_data(src_path))
-        .or(image::jpeg::read_image_data(src_path))
-        .or(image::webp::read_image_data(src_path))?;
+        #[test]
+        fn success_when_valid_values() {
+            let mask = UnsharpMask::new(0.5, 1.0).unwrap();
+            assert_eq!(mask.amount, 0.5);
+            assert_eq!(mask.radius, 1.0);
+        }
 
-    let (input_width, input_height) = NonZeroU32::new(input_width_raw)
-        .zip(NonZeroU32::new(input_height_raw))
-        .ok_or(ResizedPngError::InputSizeError)?;
+        #[test]
+        fn failed_when_invalid_values() {
+            for (amount, radius) in [
+                (-0.1, 1.0),
+                (f32::NAN, 1.0),
+                (f32::INFINITY, 1.0),
+                (0.5, 0.0),
+                (0.5, f32::NAN),
+                (0.5, MAX_UNSHARP_RADIUS + 1.0),
+            ] {
+                assert!(matches!(
+                    UnsharpMask::new(amount, radius),
+                    Err(ResizedPngError::ParameterError(None))
+                ));
+            }
+        }
+    }
 
-    // サイズが計算できないときは、何もせず終了。
-    let (output_width, output_height) =
-        match output_size(width_command, height_command, input_width, input_height) {
-            Some(v) => v,
-            None => return Ok(()),
-        };
+    mod unsharp_mask {
+        use super::*;
 
-    let mut dist_rgba = vec![0; (output_width.get() * output_height.get() * 4) as usize];
+        #[test]
+        fn overshoot_when_hard_edge() {
+            let rgba: Vec<u8> = [50, 50, 50, 50, 200, 200, 200, 200]
+                .iter()
+                .flat_map(|v| [*v, *v, *v, 255])
+                .collect();
 
-    let mut resizer = resize::new(
-        input_width.get() as usize,
-        input_height.get() as usize,
-        output_width.get() as usize,
-        output_height.get() as usize,
-        resize::Pixel::RGBA8P,
-        resize::Type::Lanczos3,
-    )?;
+            let sharpened = unsharp_mask(rgba, 8, 1, 1.0, 1.0);
+            let red: Vec<u8> = sharpened.chunks_exact(4).map(|v| v[0]).collect();
 
-    resizer.resize(src_rgba.as_rgba(), dist_rgba.as_rgba_mut())?;
+            // 境目の両側が、元の値を越えて暗く、明るくなる。
+            assert!(red[3] < 50, "{:?}", red);
+            assert!(red[4] > 200, "{:?}", red);
+            // 境目から離れた端は変わらない。
+            assert_eq!((red[0], red[7]), (50, 200));
+        }
 
-    image::png::write_png(
-        dist_path,
-        &dist_rgba,
-        output_width.get(),
-        output_height.get(),
-    )?;
+        #[test]
+        fn alpha_is_untouched() {
+            let rgba: Vec<u8> = [[0, 0, 0, 10], [255, 255, 255, 200]].concat();
 
-    Ok(())
-}
+            let sharpened = unsharp_mask(rgba, 2, 1, 2.0, 1.0);
 
-fn output_size(
-    width_command: i64,
-    height_command: i64,
-    input_width: NonZeroU32,
-    input_height: NonZeroU32,
-) -> Option<(NonZeroU32, NonZeroU32)> {
-    // 両方とも0未満ならサイズなし。
-    if width_command < 0 && height_command < 0 {
-        return None;
-    }
+            assert_eq!((sharpened[3], sharpened[7]), (10, 200));
+        }
 
-    // command が0の場合は元のサイズが指定されているとして扱う。
-    let width_origin = match width_command {
-        0 => input_width.get() as i64,
-        w => w,
-    };
-    let height_origin = match height_command {
-        0 => input_height.get() as i64,
-        h => h,
-    };
+        #[test]
+        fn clamped_when_16bit() {
+            let rgba: Vec<u16> = [
+                [0, 0, 0, u16::MAX],
+                [u16::MAX, u16::MAX, u16::MAX, u16::MAX],
+            ]
+            .concat();
 
-    // originが0未満の場合はもう片方の拡大率に従う。
-    let width_temp = match width_origin {
-        w if w < 0 => {
-            let ratio = height_origin as f64 / input_height.get() as f64;
+            let sharpened = unsharp_mask(rgba.clone(), 2, 1, 5.0, 1.0);
 
-            (input_width.get() as f64 * ratio) as u32
+            assert_eq!(sharpened, rgba);
         }
-        w => w as u32,
-    };
-    let height_temp = match height_origin {
-        h if h < 0 => {
-            let ratio = width_origin as f64 / input_width.get() as f64;
 
-            (input_height.get() as f64 * ratio) as u32
+        #[test]
+        fn untouched_when_radius_is_not_positive() {
+            let rgba: Vec<u8> = [[0, 0, 0, 255], [255, 255, 255, 255]].concat();
+
+            assert_eq!(unsharp_mask(rgba.clone(), 2, 1, 1.0, 0.0), rgba);
         }
-        h => h as u32,
-    };
+    }
 
-    // tempが0の場合は1にfallbackして返す。
-    let width = NonZeroU32::new(width_temp).unwrap_or(NonZeroU32::new(1).unwrap());
-    let height = NonZeroU32::new(height_temp).unwrap_or(NonZeroU32::new(1).unwrap());
+    mod to_luma_rgba {
+        use super::*;
 
-    Some((width, height))
-}
+        #[test]
+        fn checking_value_when_primary_colors() {
+            let rgba = [[255u8, 0, 0, 255], [0, 255, 0, 128], [0, 0, 255, 0]].concat();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+            assert_eq!(
+                to_luma_rgba(rgba),
+                [[76, 76, 76, 255], [150, 150, 150, 128], [29, 29, 29, 0]].concat()
+            );
+        }
+    }
 
-    mod get_image_type {
+    mod crop_rgba {
         use super::*;
 
         #[test]
-        fn checking_value_when_image_file_exists() {
-            let path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
+        fn checking_value_when_inner_area() {
+            // 3x3の各ピクセルに0から8の番号をつける。
+            let rgba: Vec<u8> = (0..9).flat_map(|v| [v; 4]).collect();
 
-            assert_eq!(get_image_type(&path), "PNG");
+            assert_eq!(
+                crop_rgba(rgba, 3, 1, 1, 2, 2),
+                [[4; 4], [5; 4], [7; 4], [8; 4]].concat()
+            );
         }
+    }
+
+    mod opaque_bounds {
+        use super::*;
 
         #[test]
-        fn checking_value_when_non_image_file_exists() {
-            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        fn checking_value_when_transparent_border() {
+            // 4x3の(1, 1)と(2, 1)だけが不透明でない。
+            let mut rgba = vec![0u8; 4 * 3 * 4];
+            rgba[(4 + 1) * 4 + 3] = 255;
+            rgba[(4 + 2) * 4 + 3] = 1;
 
-            assert_eq!(get_image_type(&path), "UNKNOWN");
+            assert_eq!(opaque_bounds(&rgba, 4, 3), Some((1, 1, 2, 1)));
         }
 
         #[test]
-        fn checking_value_when_file_does_not_exist() {
-            let path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/something_wrong.png");
-            assert_eq!(get_image_type(&path), "UNKNOWN");
+        fn checking_value_when_opaque() {
+            let rgba = [255u8; 3 * 2 * 4];
+
+            assert_eq!(opaque_bounds(&rgba, 3, 2), Some((0, 0, 3, 2)));
+        }
+
+        #[test]
+        fn none_when_all_transparent() {
+            let rgba = [255u8, 255, 255, 0].repeat(6);
+
+            assert_eq!(opaque_bounds(&rgba, 3, 2), None);
         }
     }
 
-    mod get_image_info {
+    mod is_grayscale {
         use super::*;
 
         #[test]
-        fn get_image_info_when_image_file_exists() {
-            let path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
-            let (width, height) = get_image_info(&path).unwrap();
-            assert_eq!(width, 100);
-            assert_eq!(height, 200);
+        fn true_when_all_pixels_are_opaque_gray() {
+            assert!(is_grayscale(&[0u8, 0, 0, 255, 10, 10, 10, 255]));
         }
 
         #[test]
-        fn get_image_info_when_non_image_file_exists() {
-            let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
-            assert!(get_image_info(&path).is_err());
+        fn false_when_some_pixel_has_color() {
+            assert!(!is_grayscale(&[0u8, 0, 0, 255, 10, 11, 10, 255]));
         }
 
         #[test]
-        fn get_image_info_when_file_does_not_exist() {
-            let path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/something_wrong.png");
-            assert!(get_image_info(&path).is_err());
+        fn false_when_some_pixel_is_transparent() {
+            assert!(!is_grayscale(&[0u8, 0, 0, 255, 10, 10, 10, 254]));
         }
     }
 
-    mod to_resized_png {
+    mod resize_filter {
         use super::*;
 
-        use tempfile::tempdir;
+        #[test]
+        fn some_value_when_valid_name() {
+            assert_eq!(ResizeFilter::parse("Point"), Some(ResizeFilter::Point));
+            assert_eq!(
+                ResizeFilter::parse("Triangle"),
+                Some(ResizeFilter::Triangle)
+            );
+            assert_eq!(ResizeFilter::parse("Catrom"), Some(ResizeFilter::Catrom));
+            assert_eq!(
+                ResizeFilter::parse("Mitchell"),
+                Some(ResizeFilter::Mitchell)
+            );
+            assert_eq!(
+                ResizeFilter::parse("Lanczos3"),
+                Some(ResizeFilter::Lanczos3)
+            );
+        }
 
         #[test]
-        fn success_when_input_image_is_png() {
-            let out_dir = tempdir().unwrap();
+        fn some_value_when_full_name() {
+            for name in ["CatmullRom", "Catmull-Rom"] {
+                assert_eq!(ResizeFilter::parse(name), Some(ResizeFilter::Catrom));
+            }
+            for name in ["MitchellNetravali", "Mitchell-Netravali"] {
+                assert_eq!(ResizeFilter::parse(name), Some(ResizeFilter::Mitchell));
+            }
+        }
+
+        #[test]
+        fn checking_size_when_resized_with_each_filter() {
+            let out_dir = tempfile::tempdir().unwrap();
 
             let src_path =
                 PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
-            let dist_path = out_dir.path().join("from_png.png");
-            let width_command = 50;
-            let height_command = 100;
+            for filter in [ResizeFilter::Catrom, ResizeFilter::Mitchell] {
+                for (width, height) in [(37u32, 74u32), (150, 300)] {
+                    let dist_path = out_dir
+                        .path()
+                        .join(format!("{filter:?}_{width}x{height}.png"));
+                    let options = ResizeOptions {
+                        filter,
+                        ..Default::default()
+                    };
 
-            to_resized_png(&src_path, &dist_path, width_command, height_command).unwrap();
+                    to_resized_png(
+                        &src_path,
+                        &dist_path,
+                        SizeCommand::Pixel(width as i64),
+                        SizeCommand::Pixel(height as i64),
+                        &options,
+                    )
+                    .unwrap();
 
-            assert!(dist_path.exists());
+                    assert_eq!(
+                        image::png::read_dimensions(&dist_path).unwrap(),
+                        (width, height)
+                    );
+                }
+            }
 
             out_dir.close().unwrap();
         }
 
         #[test]
-        fn success_when_input_image_is_webp() {
-            let out_dir = tempdir().unwrap();
+        fn none_when_invalid_name() {
+            assert_eq!(ResizeFilter::parse("Something"), None);
+            assert_eq!(ResizeFilter::parse(""), None);
+        }
 
-            let src_path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.webp");
-            let dist_path = out_dir.path().join("from_webp.png");
-            let width_command = -1;
-            let height_command = 50;
+        #[test]
+        fn lanczos3_when_default() {
+            assert_eq!(ResizeFilter::default(), ResizeFilter::Lanczos3);
+        }
+    }
 
-            to_resized_png(&src_path, &dist_path, width_command, height_command).unwrap();
+    mod resize_quality {
+        use super::*;
 
-            assert!(dist_path.exists());
+        use tempfile::tempdir;
 
-            out_dir.close().unwrap();
+        #[test]
+        fn some_value_when_valid_name() {
+            assert_eq!(ResizeQuality::parse("Fast"), Some(ResizeQuality::Fast));
+            assert_eq!(
+                ResizeQuality::parse("Balanced"),
+                Some(ResizeQuality::Balanced)
+            );
+            assert_eq!(ResizeQuality::parse("High"), Some(ResizeQuality::High));
+            assert_eq!(ResizeQuality::parse("Lanczos3"), None);
         }
 
         #[test]
-        fn success_when_input_image_is_bmp() {
-            let out_dir = tempdir().unwrap();
+        fn checking_value_of_resize_type() {
+            assert!(matches!(
+                ResizeQuality::Fast.filter().to_type(),
+                resize::Type::Triangle
+            ));
+            assert!(matches!(
+                ResizeQuality::Balanced.filter().to_type(),
+                resize::Type::Catrom
+            ));
+            assert!(matches!(
+                ResizeQuality::High.filter().to_type(),
+                resize::Type::Lanczos3
+            ));
+        }
+
+        #[test]
+        fn checking_value_of_options() {
+            let options = ResizeOptions::from_quality(ResizeQuality::Fast);
+            assert_eq!(options.filter, ResizeFilter::Triangle);
+            assert!(!options.linear_light);
+
+            let options = ResizeOptions::from_quality(ResizeQuality::High);
+            assert_eq!(options.filter, ResizeFilter::Lanczos3);
+            assert!(options.linear_light);
+        }
 
+        #[test]
+        fn success_when_each_quality() {
+            let out_dir = tempdir().unwrap();
             let src_path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.bmp");
-            let dist_path = out_dir.path().join("from_bmp.png");
-            let width_command = 50;
-            let height_command = -1;
+                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.png");
 
-            to_resized_png(&src_path, &dist_path, width_command, height_command).unwrap();
+            for (name, quality) in [
+                ("fast.png", ResizeQuality::Fast),
+                ("balanced.png", ResizeQuality::Balanced),
+                ("high.png", ResizeQuality::High),
+            ] {
+                let dist_path = out_dir.path().join(name);
 
-            assert!(dist_path.exists());
+                to_resized_png(
+                    &src_path,
+                    &dist_path,
+                    SizeCommand::Pixel(50),
+                    SizeCommand::Pixel(-1),
+                    &ResizeOptions::from_quality(quality),
+                )
+                .unwrap();
+
+                let (width, height) = image::png::read_dimensions(&dist_path).unwrap();
+                assert_eq!((width, height), (50, 100), "{}", name);
+            }
 
             out_dir.close().unwrap();
         }
+    }
+
+    mod output_format {
+        use super::*;
 
         #[test]
-        fn success_when_input_image_is_jpg() {
-            let out_dir = tempdir().unwrap();
+        fn some_value_when_valid_name() {
+            assert_eq!(OutputFormat::parse("png"), Some(OutputFormat::Png));
+            assert_eq!(OutputFormat::parse("WebP"), Some(OutputFormat::Webp));
+            assert_eq!(OutputFormat::parse("jpeg"), Some(OutputFormat::Jpeg));
+            assert_eq!(OutputFormat::parse("JPG"), Some(OutputFormat::Jpeg));
+        }
 
-            let src_path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_target/image/sample.jpg");
-            let dist_path = out_dir.path().join("from_jpg.png");
-            let width_command = 0;
-            let height_command = 0;
+        #[test]
+        fn none_when_invalid_name() {
+            assert_eq!(OutputFormat::parse("gif"), None);
+            assert_eq!(OutputFormat::parse(""), None);
+        }
+    }
 
-            to_resized_png(&src_path, &dist_path, width_command, height_command).unwrap();
+    mod size_command {
+        use super::*;
 
-            assert!(dist_path.exists());
+        #[test]
+        fn checking_value_when_parse() {
+            assert_eq!(SizeCommand::parse("50"), Some(SizeCommand::Pixel(50)));
+            assert_eq!(SizeCommand::parse("-1"), Some(SizeCommand::Pixel(-1)));
+            assert_eq!(SizeCommand::parse("50%"), Some(SizeCommand::Percent(50)));
+            assert_eq!(SizeCommand::parse("-50%"), None);
+            assert_eq!(SizeCommand::parse("abc"), None);
+        }
+    }
 
-            out_dir.close().unwrap();
+    mod parse_dimension_command {
+        use super::*;
+
+        #[test]
+        fn checking_value_when_zero() {
+            assert_eq!(parse_dimension_command("0").unwrap(), SizeCommand::Pixel(0));
+        }
+
+        #[test]
+        fn checking_value_when_keep_ratio() {
+            for s in ["-1", "auto", "AUTO", "x"] {
+                assert_eq!(parse_dimension_command(s).unwrap(), SizeCommand::Pixel(-1));
+            }
+        }
+
+        #[test]
+        fn checking_value_when_pixel() {
+            assert_eq!(
+                parse_dimension_command("200").unwrap(),
+                SizeCommand::Pixel(200)
+            );
+        }
+
+        #[test]
+        fn checking_value_when_percent() {
+            assert_eq!(
+                parse_dimension_command("50%").unwrap(),
+                SizeCommand::Percent(50)
+            );
+        }
+
+        #[test]
+        fn failed_when_invalid_string() {
+            assert!(matches!(
+                parse_dimension_command("abc"),
+                Err(ResizedPngError::ParameterError(None))
+            ));
+        }
+
+        #[test]
+        fn failed_when_zero_percent_or_negative() {
+            assert!(parse_dimension_command("0%").is_err());
+            assert!(parse_dimension_command("-50%").is_err());
+            assert!(parse_dimension_command("-2").is_err());
         }
     }
 
@@ -266,25 +6332,38 @@ mod tests {
 
         #[test]
         fn none_when_both_width_and_height_are_minus() {
-            let width_command = -1;
-            let height_command = -1;
+            let width_command = SizeCommand::Pixel(-1);
+            let height_command = SizeCommand::Pixel(-1);
             let input_width = NonZeroU32::new(100).unwrap();
             let input_height = NonZeroU32::new(200).unwrap();
 
-            assert!(
-                output_size(width_command, height_command, input_width, input_height).is_none()
-            );
+            assert!(output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                false
+            )
+            .unwrap()
+            .is_none());
         }
 
         #[test]
         fn original_value_when_width_and_height_are_0() {
-            let width_command = 0;
-            let height_command = 0;
+            let width_command = SizeCommand::Pixel(0);
+            let height_command = SizeCommand::Pixel(0);
             let input_width = NonZeroU32::new(100).unwrap();
             let input_height = NonZeroU32::new(200).unwrap();
 
-            let (width, height) =
-                output_size(width_command, height_command, input_width, input_height).unwrap();
+            let (width, height) = output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                false,
+            )
+            .unwrap()
+            .unwrap();
 
             assert_eq!(width, input_width);
             assert_eq!(height, input_height);
@@ -292,13 +6371,20 @@ mod tests {
 
         #[test]
         fn keep_aspect_ratio_when_one_of_width_and_height_is_minus() {
-            let width_command = -1;
-            let height_command = 100;
+            let width_command = SizeCommand::Pixel(-1);
+            let height_command = SizeCommand::Pixel(100);
             let input_width = NonZeroU32::new(100).unwrap();
             let input_height = NonZeroU32::new(200).unwrap();
 
-            let (width, height) =
-                output_size(width_command, height_command, input_width, input_height).unwrap();
+            let (width, height) = output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                false,
+            )
+            .unwrap()
+            .unwrap();
 
             assert_eq!(width, NonZeroU32::new(50).unwrap());
             assert_eq!(height, NonZeroU32::new(100).unwrap());
@@ -306,16 +6392,200 @@ mod tests {
 
         #[test]
         fn target_values_when_width_and_henght_are_not_0_and_minis() {
-            let width_command = 200;
-            let height_command = 300;
+            let width_command = SizeCommand::Pixel(200);
+            let height_command = SizeCommand::Pixel(300);
             let input_width = NonZeroU32::new(100).unwrap();
             let input_height = NonZeroU32::new(200).unwrap();
 
-            let (width, height) =
-                output_size(width_command, height_command, input_width, input_height).unwrap();
+            let (width, height) = output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                false,
+            )
+            .unwrap()
+            .unwrap();
 
             assert_eq!(width, NonZeroU32::new(200).unwrap());
             assert_eq!(height, NonZeroU32::new(300).unwrap());
         }
+
+        #[test]
+        fn clamped_to_input_when_no_upscale_and_larger_than_input() {
+            let width_command = SizeCommand::Pixel(200);
+            let height_command = SizeCommand::Pixel(150);
+            let input_width = NonZeroU32::new(100).unwrap();
+            let input_height = NonZeroU32::new(200).unwrap();
+
+            let (width, height) = output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                true,
+            )
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(width, NonZeroU32::new(100).unwrap());
+            assert_eq!(height, NonZeroU32::new(150).unwrap());
+        }
+
+        #[test]
+        fn keep_aspect_ratio_when_no_upscale_and_one_of_width_and_height_is_minus() {
+            let width_command = SizeCommand::Pixel(-1);
+            let height_command = SizeCommand::Pixel(4000);
+            let input_width = NonZeroU32::new(100).unwrap();
+            let input_height = NonZeroU32::new(200).unwrap();
+
+            let (width, height) = output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                true,
+            )
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(width, input_width);
+            assert_eq!(height, input_height);
+        }
+
+        #[test]
+        fn target_values_when_no_upscale_and_smaller_than_input() {
+            let width_command = SizeCommand::Pixel(-1);
+            let height_command = SizeCommand::Pixel(100);
+            let input_width = NonZeroU32::new(100).unwrap();
+            let input_height = NonZeroU32::new(200).unwrap();
+
+            let (width, height) = output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                true,
+            )
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(width, NonZeroU32::new(50).unwrap());
+            assert_eq!(height, NonZeroU32::new(100).unwrap());
+        }
+
+        #[test]
+        fn half_size_when_percent_is_50() {
+            let width_command = SizeCommand::Percent(50);
+            let height_command = SizeCommand::Percent(50);
+            let input_width = NonZeroU32::new(100).unwrap();
+            let input_height = NonZeroU32::new(200).unwrap();
+
+            let (width, height) = output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                false,
+            )
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(width, NonZeroU32::new(50).unwrap());
+            assert_eq!(height, NonZeroU32::new(100).unwrap());
+        }
+
+        #[test]
+        fn double_size_when_percent_is_200_and_other_is_minus() {
+            let width_command = SizeCommand::Percent(200);
+            let height_command = SizeCommand::Pixel(-1);
+            let input_width = NonZeroU32::new(100).unwrap();
+            let input_height = NonZeroU32::new(200).unwrap();
+
+            let (width, height) = output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                false,
+            )
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(width, NonZeroU32::new(200).unwrap());
+            assert_eq!(height, NonZeroU32::new(400).unwrap());
+        }
+
+        #[test]
+        fn fallback_to_1_when_percent_rounds_to_0() {
+            let width_command = SizeCommand::Percent(1);
+            let height_command = SizeCommand::Percent(1);
+            let input_width = NonZeroU32::new(50).unwrap();
+            let input_height = NonZeroU32::new(200).unwrap();
+
+            let (width, height) = output_size(
+                width_command,
+                height_command,
+                input_width,
+                input_height,
+                false,
+            )
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(width, NonZeroU32::new(1).unwrap());
+            assert_eq!(height, NonZeroU32::new(2).unwrap());
+        }
+
+        #[test]
+        fn failed_when_percent_is_0() {
+            let input_width = NonZeroU32::new(100).unwrap();
+            let input_height = NonZeroU32::new(200).unwrap();
+
+            assert!(matches!(
+                output_size(
+                    SizeCommand::Percent(0),
+                    SizeCommand::Pixel(-1),
+                    input_width,
+                    input_height,
+                    false,
+                ),
+                Err(ResizedPngError::ParameterError(None))
+            ));
+        }
+
+        #[test]
+        fn failed_when_width_is_minus_other_than_1() {
+            let input_width = NonZeroU32::new(100).unwrap();
+            let input_height = NonZeroU32::new(200).unwrap();
+
+            assert!(matches!(
+                output_size(
+                    SizeCommand::Pixel(-500),
+                    SizeCommand::Pixel(100),
+                    input_width,
+                    input_height,
+                    false,
+                ),
+                Err(ResizedPngError::ParameterError(_))
+            ));
+        }
+
+        #[test]
+        fn failed_when_height_is_minus_other_than_1() {
+            let input_width = NonZeroU32::new(100).unwrap();
+            let input_height = NonZeroU32::new(200).unwrap();
+
+            assert!(matches!(
+                output_size(
+                    SizeCommand::Pixel(-1),
+                    SizeCommand::Pixel(-2),
+                    input_width,
+                    input_height,
+                    false,
+                ),
+                Err(ResizedPngError::ParameterError(_))
+            ));
+        }
     }
 }